@@ -0,0 +1,26 @@
+#!/bin/bash
+
+# Well known Azurite development storage account, see
+# https://docs.microsoft.com/en-us/azure/storage/common/storage-use-azurite
+export AZURE_STORAGE_CONNECTION_STRING="DefaultEndpointsProtocol=http;AccountName=devstoreaccount1;AccountKey=Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==;BlobEndpoint=http://azurite:10000/devstoreaccount1;"
+
+function wait_for() {
+  retries=10
+  until eval $2 > /dev/null 2>&1
+  do
+    if [ "$retries" -lt "0" ]; then
+      echo "$1 is still offline after 10 retries";
+      exit 1;
+    fi
+    echo "Waiting on $1 to start..."
+    sleep 5
+    retries=$((retries - 1))
+  done
+}
+
+wait_for "Azurite" "az storage container list"
+
+echo "Creating Azurite test container..."
+az storage container create --name deltars > /dev/null
+
+echo Azurite is configured!
@@ -2,6 +2,7 @@
 
 #![allow(non_snake_case, non_camel_case_types)]
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 use parquet::record::{ListAccessor, MapAccessor, RowAccessor};
@@ -47,6 +48,91 @@ fn populate_hashmap_from_parquet_map(
     Ok(())
 }
 
+/// Converts a parquet map into JSON values. Unlike `populate_hashmap_from_parquet_map`, values
+/// that are not strings (e.g. null) do not fail the conversion and are mapped to JSON null.
+fn json_map_from_parquet_map(
+    pmap: &parquet::record::Map,
+) -> Result<HashMap<String, Value>, &'static str> {
+    let keys = pmap.get_keys();
+    let values = pmap.get_values();
+    let mut map = HashMap::new();
+    for j in 0..pmap.len() {
+        let key = keys
+            .get_string(j)
+            .map_err(|_| "key for HashMap in parquet has to be a string")?;
+        let value = match values.get_string(j) {
+            Ok(v) => Value::String(v.clone()),
+            _ => Value::Null,
+        };
+        map.insert(key.clone(), value);
+    }
+
+    Ok(map)
+}
+
+/// Percent-encodes a path relative to the table root for the `path` of an add or remove action,
+/// which is a URI. Characters that are not allowed in the path of a URI, including the `%` of
+/// escaped partition values, are encoded.
+pub(crate) fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'a'..=b'z'
+            | b'A'..=b'Z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'!'
+            | b'~'
+            | b'*'
+            | b'\''
+            | b'('
+            | b')'
+            | b'/'
+            | b':'
+            | b'@'
+            | b'&'
+            | b'='
+            | b'+'
+            | b'$'
+            | b','
+            | b';' => encoded.push(b as char),
+            b => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+/// Decodes the `path` of an add or remove action to the path of the file relative to the table
+/// root. Invalid escape sequences are kept as they are.
+pub(crate) fn decode_path(path: &str) -> Cow<str> {
+    if !path.contains('%') {
+        return Cow::Borrowed(path);
+    }
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(b) => {
+                decoded.push(b);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
 fn gen_action_type_error(action: &str, field: &str, expected_type: &str) -> ActionError {
     ActionError::InvalidField(format!(
         "type for {} in {} action should be {}",
@@ -276,6 +362,23 @@ impl Add {
         Ok(re)
     }
 
+    /// Returns the remove action that removes this file from the table at `deletion_timestamp`.
+    pub(crate) fn to_remove(
+        &self,
+        deletion_timestamp: DeltaDataTypeTimestamp,
+        data_change: bool,
+    ) -> Remove {
+        Remove {
+            path: self.path.clone(),
+            deletion_timestamp,
+            data_change,
+            extended_file_metadata: Some(true),
+            partition_values: Some(self.partition_values.clone()),
+            size: Some(self.size),
+            tags: self.tags.clone(),
+        }
+    }
+
     /// Returns the serde_json representation of stats contained in the action if present.
     /// Since stats are defined as optional in the protocol, this may be None.
     pub fn get_stats(&self) -> Result<Option<Stats>, serde_json::error::Error> {
@@ -362,6 +465,18 @@ pub struct Format {
     options: Option<HashMap<String, String>>,
 }
 
+impl Format {
+    /// Creates a new format description for the given provider, e.g. `parquet`.
+    pub fn new(provider: String, options: Option<HashMap<String, String>>) -> Self {
+        Self { provider, options }
+    }
+
+    /// Returns the name of the encoding for files in the table.
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+}
+
 /// Action that describes the metadata of the table.
 /// This is a top-level action in Delta log entries.
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -700,6 +815,131 @@ impl Protocol {
     }
 }
 
+/// Action that describes the provenance of a commit, such as the operation that created it.
+/// This is a top-level action in Delta log entries.
+///
+/// The fields written by the Spark reference implementation are typed, any other key is kept
+/// in `info` so that the action round-trips unchanged.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitInfo {
+    /// The time this commit was created, in milliseconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DeltaDataTypeTimestamp>,
+    /// Id of the user that created the commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// Name of the user that created the commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+    /// Name of the operation that created the commit, e.g. `WRITE`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    /// Parameters of the operation that created the commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation_parameters: Option<HashMap<String, Value>>,
+    /// Id of the cluster that created the commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster_id: Option<String>,
+    /// Version of the table the writer read when creating the commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_version: Option<DeltaDataTypeVersion>,
+    /// Name of the isolation level the commit was checked for conflicts with. Kept as written,
+    /// since other writers may use levels unknown to this crate, see `get_isolation_level`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isolation_level: Option<String>,
+    /// Whether the commit only added new data without reading any existing data of the table.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_blind_append: Option<bool>,
+    /// Metrics of the operation that created the commit, e.g. the number of files added.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation_metrics: Option<HashMap<String, Value>>,
+    /// User-defined metadata of the commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_metadata: Option<String>,
+    /// Information about the engine that created the commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engine_info: Option<String>,
+    /// Any other key present in the action, such as `job`, `notebook` or `clientVersion`.
+    #[serde(flatten)]
+    pub info: HashMap<String, Value>,
+}
+
+impl CommitInfo {
+    /// Returns the isolation level the commit was checked for conflicts with, if it is one of
+    /// the levels known to this crate.
+    pub fn get_isolation_level(&self) -> Option<IsolationLevel> {
+        match self.isolation_level.as_deref()? {
+            "Serializable" => Some(IsolationLevel::Serializable),
+            "WriteSerializable" => Some(IsolationLevel::WriteSerializable),
+            "SnapshotIsolation" => Some(IsolationLevel::SnapshotIsolation),
+            _ => None,
+        }
+    }
+
+    fn from_parquet_record(record: &parquet::record::Row) -> Result<Self, ActionError> {
+        let mut re = Self {
+            ..Default::default()
+        };
+
+        for (i, (name, _)) in record.get_column_iter().enumerate() {
+            match name.as_str() {
+                "timestamp" => re.timestamp = record.get_long(i).ok(),
+                "userId" => re.user_id = record.get_string(i).ok().cloned(),
+                "userName" => re.user_name = record.get_string(i).ok().cloned(),
+                "operation" => re.operation = record.get_string(i).ok().cloned(),
+                "operationParameters" => match record.get_map(i) {
+                    Ok(parameters_map) => {
+                        re.operation_parameters =
+                            Some(json_map_from_parquet_map(parameters_map).map_err(|estr| {
+                                ActionError::InvalidField(format!(
+                                    "Invalid operationParameters for commitInfo action: {}",
+                                    estr,
+                                ))
+                            })?);
+                    }
+                    _ => re.operation_parameters = None,
+                },
+                "clusterId" => re.cluster_id = record.get_string(i).ok().cloned(),
+                "readVersion" => re.read_version = record.get_long(i).ok(),
+                "isolationLevel" => re.isolation_level = record.get_string(i).ok().cloned(),
+                "isBlindAppend" => re.is_blind_append = record.get_bool(i).ok(),
+                "operationMetrics" => match record.get_map(i) {
+                    Ok(metrics_map) => {
+                        re.operation_metrics =
+                            Some(json_map_from_parquet_map(metrics_map).map_err(|estr| {
+                                ActionError::InvalidField(format!(
+                                    "Invalid operationMetrics for commitInfo action: {}",
+                                    estr,
+                                ))
+                            })?);
+                    }
+                    _ => re.operation_metrics = None,
+                },
+                "userMetadata" => re.user_metadata = record.get_string(i).ok().cloned(),
+                "engineInfo" => re.engine_info = record.get_string(i).ok().cloned(),
+                _ => {
+                    if let Ok(s) = record.get_string(i) {
+                        re.info.insert(name.clone(), Value::String(s.clone()));
+                    } else if let Ok(v) = record.get_long(i) {
+                        re.info.insert(name.clone(), Value::from(v));
+                    } else if let Ok(v) = record.get_bool(i) {
+                        re.info.insert(name.clone(), Value::Bool(v));
+                    } else {
+                        log::warn!(
+                            "Unexpected field name `{}` for commitInfo action: {:?}",
+                            name,
+                            record
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(re)
+    }
+}
+
 /// Represents an action in the Delta log. The Delta log is an aggregate of all actions performed
 /// on the table, so the full list of actions is required to properly read a table.
 #[derive(Serialize, Deserialize, Debug)]
@@ -717,7 +957,7 @@ pub enum Action {
     /// Describes the minimum reader and writer versions required to read or write to the table.
     protocol(Protocol),
     /// Describes commit provenance information for the table.
-    commitInfo(Value),
+    commitInfo(CommitInfo),
 }
 
 impl Action {
@@ -762,9 +1002,7 @@ impl Action {
             "remove" => Action::remove(Remove::from_parquet_record(col_data)?),
             "txn" => Action::txn(Txn::from_parquet_record(col_data)?),
             "protocol" => Action::protocol(Protocol::from_parquet_record(col_data)?),
-            "commitInfo" => {
-                unimplemented!("FIXME: support commitInfo");
-            }
+            "commitInfo" => Action::commitInfo(CommitInfo::from_parquet_record(col_data)?),
             name => {
                 return Err(ActionError::InvalidField(format!(
                     "Unexpected action from checkpoint: {}",
@@ -798,11 +1036,130 @@ pub enum DeltaOperation {
         /// The epoch id of the written micro-batch.
         epochId: i64,
     },
+    /// Represents a Delta `Create` operation, which initializes a new table without writing any
+    /// data to it.
+    Create {
+        /// The location the table is created at.
+        location: String,
+        /// The description of the table.
+        description: Option<String>,
+        /// The columns the table is partitioned by.
+        partitionBy: Option<Vec<String>>,
+        /// The configuration the table is created with.
+        properties: HashMap<String, String>,
+    },
+    /// Represents a Delta `Delete` operation, which removes the rows matching a predicate by
+    /// removing files and adding rewritten copies of them.
+    Delete {
+        /// The predicate the deleted rows match, or none if all rows are deleted.
+        predicate: Option<String>,
+    },
+    /// Represents a Delta `Update` operation, which changes the rows matching a predicate by
+    /// removing files and adding rewritten copies of them.
+    Update {
+        /// The predicate the updated rows match, or none if all rows are updated.
+        predicate: Option<String>,
+    },
+    /// Represents a Delta `Merge` operation, which updates, deletes and inserts rows of the table
+    /// depending on whether they match the rows of a source.
+    Merge {
+        /// The condition target and source rows are matched by.
+        predicate: Option<String>,
+        /// The clauses applied to matched rows, in order.
+        matchedPredicates: Vec<MergePredicate>,
+        /// The clauses applied to source rows without a match, in order.
+        notMatchedPredicates: Vec<MergePredicate>,
+    },
+    /// Represents a Delta `RenameColumn` operation, which renames a column of a table using
+    /// column mapping.
+    RenameColumn {
+        /// The name of the column before the rename.
+        oldColumnPath: String,
+        /// The name of the column after the rename.
+        newColumnPath: String,
+    },
+    /// Represents a Delta `DropColumns` operation, which drops columns of a table using column
+    /// mapping.
+    DropColumns {
+        /// The names of the dropped columns.
+        columns: Vec<String>,
+    },
+    /// Represents a Delta `SetTableProperties` operation, which changes the configuration of a
+    /// table.
+    SetTableProperties {
+        /// The table properties that are set.
+        properties: HashMap<String, String>,
+    },
+    /// Represents a Delta `AddConstraint` operation, which adds a CHECK constraint to a table.
+    AddConstraint {
+        /// The name of the constraint.
+        name: String,
+        /// The SQL expression every row of the table has to satisfy.
+        expr: String,
+    },
+    /// Represents a Delta `DropConstraint` operation, which drops a CHECK constraint of a table.
+    DropConstraint {
+        /// The name of the constraint.
+        name: String,
+        /// The SQL expression of the dropped constraint, if it existed.
+        expr: Option<String>,
+    },
+    /// Represents a Delta `Optimize` operation, which compacts small files without changing the
+    /// data of the table.
+    Optimize {
+        /// The partition predicate limiting the compacted files, or none for the whole table.
+        predicate: Option<String>,
+        /// The columns the rows are clustered by along a Z-order curve, empty for plain
+        /// compaction.
+        zOrderBy: Vec<String>,
+    },
     // TODO: Add more operations
 }
 
+impl DeltaOperation {
+    /// Returns the name of the operation as recorded in the `operation` field of the `CommitInfo`
+    /// action, matching the names written by the Spark reference implementation.
+    pub fn name(&self) -> &str {
+        match self {
+            DeltaOperation::Write { .. } => "WRITE",
+            DeltaOperation::StreamingUpdate { .. } => "STREAMING UPDATE",
+            DeltaOperation::Create { .. } => "CREATE TABLE",
+            DeltaOperation::Delete { .. } => "DELETE",
+            DeltaOperation::Update { .. } => "UPDATE",
+            DeltaOperation::Merge { .. } => "MERGE",
+            DeltaOperation::RenameColumn { .. } => "RENAME COLUMN",
+            DeltaOperation::DropColumns { .. } => "DROP COLUMNS",
+            DeltaOperation::SetTableProperties { .. } => "SET TBLPROPERTIES",
+            DeltaOperation::AddConstraint { .. } => "ADD CONSTRAINT",
+            DeltaOperation::DropConstraint { .. } => "DROP CONSTRAINT",
+            DeltaOperation::Optimize { .. } => "OPTIMIZE",
+        }
+    }
+
+    /// Returns the parameters of the operation as recorded in the `operationParameters` field of
+    /// the `CommitInfo` action. Like Spark, string values are kept as is and any other non-null
+    /// value is stored as its JSON encoded string.
+    pub fn operation_parameters(&self) -> Result<HashMap<String, Value>, serde_json::Error> {
+        let parameters = match serde_json::to_value(self)? {
+            Value::Object(map) => map.into_iter().next().map(|(_, v)| v),
+            _ => None,
+        };
+
+        Ok(match parameters {
+            Some(Value::Object(parameters)) => parameters
+                .into_iter()
+                .map(|(k, v)| match v {
+                    Value::Null | Value::String(_) => (k, v),
+                    v => (k, Value::String(v.to_string())),
+                })
+                .collect(),
+            _ => HashMap::new(),
+        })
+    }
+}
+
 /// The SaveMode used when performing a DeltaOperation
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SaveMode {
     /// Files will be appended to the target location.
     Append,
@@ -814,6 +1171,49 @@ pub enum SaveMode {
     Ignore,
 }
 
+/// The isolation level used when checking a transaction for conflicts with concurrent commits.
+/// Tables may choose a default level with the `delta.isolationLevel` table property.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// The strongest level. Reads and writes are serializable, so files added by any concurrent
+    /// commit conflict with a transaction that read the table.
+    Serializable,
+    /// Only writes are serializable. Concurrent blind appends do not conflict with the
+    /// transaction. This is the default level.
+    WriteSerializable,
+    /// The transaction only requires a consistent snapshot. Used for commits that do not change
+    /// any data, such as a compaction.
+    SnapshotIsolation,
+}
+
+impl IsolationLevel {
+    /// Returns the name of the isolation level as written to the commit info.
+    pub fn name(&self) -> &str {
+        match self {
+            IsolationLevel::Serializable => "Serializable",
+            IsolationLevel::WriteSerializable => "WriteSerializable",
+            IsolationLevel::SnapshotIsolation => "SnapshotIsolation",
+        }
+    }
+}
+
+impl Default for IsolationLevel {
+    fn default() -> Self {
+        IsolationLevel::WriteSerializable
+    }
+}
+
+/// A clause of a `Merge` operation as recorded in its operation parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MergePredicate {
+    /// The action of the clause, one of `update`, `delete` or `insert`.
+    pub action_type: String,
+    /// The additional condition of the clause, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub predicate: Option<String>,
+}
+
 /// The OutputMode used in streaming operations.
 #[derive(Serialize, Deserialize, Debug)]
 pub enum OutputMode {
@@ -845,6 +1245,145 @@ mod tests {
         assert_eq!(add_action.stats, None);
     }
 
+    #[test]
+    fn test_encode_and_decode_path() {
+        let path = "ts=2021-02-01 10%3A00/part-00000-c000.snappy.parquet";
+        let encoded = encode_path(path);
+        assert_eq!(
+            encoded,
+            "ts=2021-02-01%2010%253A00/part-00000-c000.snappy.parquet"
+        );
+        assert_eq!(decode_path(&encoded), path);
+        assert_eq!(encode_path("city=Zürich"), "city=Z%C3%BCrich");
+        assert_eq!(decode_path("city=Z%C3%BCrich"), "city=Zürich");
+        assert_eq!(decode_path("a=50%/b%2"), "a=50%/b%2");
+    }
+
+    #[test]
+    fn test_operation_parameters() {
+        let operation = DeltaOperation::Write {
+            mode: SaveMode::Append,
+            partitionBy: Some(vec!["year".to_string()]),
+            predicate: None,
+        };
+
+        assert_eq!(operation.name(), "WRITE");
+        assert_eq!(
+            serde_json::to_value(operation.operation_parameters().unwrap()).unwrap(),
+            serde_json::json!({
+                "mode": "Append",
+                "partitionBy": "[\"year\"]",
+                "predicate": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_commit_info_round_trip() {
+        let raw = serde_json::json!({
+            "timestamp": 1564524295023i64,
+            "userId": "7",
+            "operation": "WRITE",
+            "operationParameters": {"mode": "Append", "partitionBy": "[]", "description": null},
+            "notebook": {"notebookId": "42"},
+            "readVersion": 3,
+            "isolationLevel": "WriteSerializable",
+            "isBlindAppend": true,
+            "operationMetrics": {"numFiles": "1", "numOutputRows": "10"},
+        });
+
+        let commit_info: CommitInfo = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(commit_info.timestamp, Some(1564524295023));
+        assert_eq!(commit_info.operation.as_deref(), Some("WRITE"));
+        assert_eq!(commit_info.read_version, Some(3));
+        assert_eq!(
+            commit_info.get_isolation_level(),
+            Some(IsolationLevel::WriteSerializable)
+        );
+        assert_eq!(commit_info.is_blind_append, Some(true));
+        assert_eq!(
+            commit_info.info["notebook"],
+            serde_json::json!({"notebookId": "42"})
+        );
+
+        assert_eq!(serde_json::to_value(&commit_info).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_commit_info_from_checkpoint() {
+        use arrow::datatypes::{DataType, Field, Schema as ArrowSchema};
+        use arrow::json::reader::Decoder;
+        use parquet::arrow::ArrowWriter;
+        use parquet::file::writer::InMemoryWriteableCursor;
+        use parquet::util::cursor::SliceableCursor;
+        use std::sync::Arc;
+
+        let commit_info_type = DataType::Struct(vec![
+            Field::new("timestamp", DataType::Int64, true),
+            Field::new("operation", DataType::Utf8, true),
+            Field::new("readVersion", DataType::Int64, true),
+            Field::new("isolationLevel", DataType::Utf8, true),
+            Field::new("isBlindAppend", DataType::Boolean, true),
+            Field::new("clientVersion", DataType::Utf8, true),
+        ]);
+        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+            "commitInfo",
+            commit_info_type,
+            true,
+        )]));
+        let rows = vec![
+            serde_json::json!({"commitInfo": {
+                "timestamp": 1564524295023i64,
+                "operation": "WRITE",
+                "readVersion": 3,
+                "isolationLevel": "WriteSerializable",
+                "isBlindAppend": true,
+                "clientVersion": "delta-rs.0.4.0",
+            }}),
+            // levels unknown to this crate are kept as written
+            serde_json::json!({"commitInfo": {
+                "operation": "OPTIMIZE",
+                "isolationLevel": "SERIALIZABLE_V2",
+            }}),
+        ];
+        let decoder = Decoder::new(schema.clone(), rows.len(), None);
+        let batch = decoder
+            .next_batch(&mut rows.into_iter().map(Ok))
+            .unwrap()
+            .unwrap();
+        let cursor = InMemoryWriteableCursor::default();
+        let mut writer = ArrowWriter::try_new(cursor.clone(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let preader = SerializedFileReader::new(SliceableCursor::new(cursor.data())).unwrap();
+        let commit_infos: Vec<CommitInfo> = preader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|record| CommitInfo::from_parquet_record(&record.get_group(0).unwrap()).unwrap())
+            .collect();
+
+        assert_eq!(commit_infos[0].timestamp, Some(1564524295023));
+        assert_eq!(commit_infos[0].operation.as_deref(), Some("WRITE"));
+        assert_eq!(commit_infos[0].read_version, Some(3));
+        assert_eq!(
+            commit_infos[0].get_isolation_level(),
+            Some(IsolationLevel::WriteSerializable)
+        );
+        assert_eq!(commit_infos[0].is_blind_append, Some(true));
+        assert_eq!(
+            commit_infos[0].info["clientVersion"],
+            serde_json::json!("delta-rs.0.4.0")
+        );
+
+        assert_eq!(
+            commit_infos[1].isolation_level.as_deref(),
+            Some("SERIALIZABLE_V2")
+        );
+        assert_eq!(commit_infos[1].get_isolation_level(), None);
+        assert_eq!(commit_infos[1].timestamp, None);
+    }
+
     #[test]
     fn test_load_table_stats() {
         let action = Add {
@@ -0,0 +1,198 @@
+//! Change feed of Delta tables: an async stream tailing the commits of a table.
+//!
+//! The stream yields the files added and removed, the app transactions and the commit info of
+//! every commit, in version order, starting from a given version. Once it caught up with the
+//! latest version of the table it polls the log for new commits.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use futures::stream::{self, Stream};
+use log::*;
+
+use crate::action::{Action, Add, CommitInfo, Remove, Txn};
+use crate::schema::DeltaDataTypeVersion;
+use crate::{ApplyLogError, DeltaTable, DeltaTableError};
+
+/// Time between two polls of the log once the change feed caught up with the table, unless
+/// configured otherwise.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of commits read by the change feed per poll of the log, unless configured otherwise.
+pub const DEFAULT_MAX_VERSIONS_PER_BATCH: usize = 100;
+/// Number of times the change feed reads the log again after a storage error before the stream
+/// ends with the error, unless configured otherwise.
+pub const DEFAULT_MAX_RETRIES: usize = 3;
+
+/// The changes of a single commit of a table.
+#[derive(Debug, Clone)]
+pub struct TableChange {
+    /// The version of the commit.
+    pub version: DeltaDataTypeVersion,
+    /// The files added to the table.
+    pub adds: Vec<Add>,
+    /// The files removed from the table.
+    pub removes: Vec<Remove>,
+    /// The app transactions recorded by the commit.
+    pub txns: Vec<Txn>,
+    /// The commit info of the commit, if the writer recorded one.
+    pub commit_info: Option<CommitInfo>,
+}
+
+impl TableChange {
+    /// Collects the changes of the actions committed at the given version. Adds and removes that
+    /// do not change data are dropped if `skip_non_data_change` is set.
+    fn from_actions(
+        version: DeltaDataTypeVersion,
+        actions: Vec<Action>,
+        skip_non_data_change: bool,
+    ) -> Self {
+        let mut change = Self {
+            version,
+            adds: vec![],
+            removes: vec![],
+            txns: vec![],
+            commit_info: None,
+        };
+        for action in actions {
+            match action {
+                Action::add(add) if add.data_change || !skip_non_data_change => {
+                    change.adds.push(add)
+                }
+                Action::remove(remove) if remove.data_change || !skip_non_data_change => {
+                    change.removes.push(remove)
+                }
+                Action::txn(txn) => change.txns.push(txn),
+                Action::commitInfo(commit_info) => change.commit_info = Some(commit_info),
+                _ => {}
+            }
+        }
+        change
+    }
+}
+
+/// Reads the commits of a table as a stream of `TableChange`s, starting from a given version.
+///
+/// The log is polled with `DeltaTable::update_incremental`, reading at most the configured number
+/// of commits per poll. Storage errors are retried after the poll interval. The stream never ends
+/// on its own, except after yielding an error, e.g. if the starting version was already removed
+/// from the log or the storage kept failing.
+pub struct ChangeFeed {
+    table: DeltaTable,
+    next_version: DeltaDataTypeVersion,
+    skip_non_data_change: bool,
+    max_versions_per_batch: usize,
+    max_retries: usize,
+    poll_interval: Duration,
+}
+
+impl ChangeFeed {
+    /// Creates a change feed of the table starting at the given version, inclusive.
+    pub fn new(table: DeltaTable, starting_version: DeltaDataTypeVersion) -> Self {
+        Self {
+            table,
+            next_version: starting_version,
+            skip_non_data_change: false,
+            max_versions_per_batch: DEFAULT_MAX_VERSIONS_PER_BATCH,
+            max_retries: DEFAULT_MAX_RETRIES,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets whether adds and removes with `dataChange=false`, e.g. those of an optimize, are
+    /// left out of the changes. False by default.
+    pub fn with_skip_non_data_change(mut self, skip_non_data_change: bool) -> Self {
+        self.skip_non_data_change = skip_non_data_change;
+        self
+    }
+
+    /// Sets the number of commits read per poll of the log, `DEFAULT_MAX_VERSIONS_PER_BATCH` by
+    /// default.
+    pub fn with_max_versions_per_batch(mut self, max_versions_per_batch: usize) -> Self {
+        self.max_versions_per_batch = max_versions_per_batch.max(1);
+        self
+    }
+
+    /// Sets the number of times the log is read again after a storage error before the stream
+    /// ends with the error, `DEFAULT_MAX_RETRIES` by default.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the time between two polls of the log once the feed caught up with the table,
+    /// `DEFAULT_POLL_INTERVAL` by default.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Returns the stream of the changes of every commit of the table, in version order.
+    pub fn into_stream(self) -> impl Stream<Item = Result<TableChange, DeltaTableError>> {
+        let buffer: VecDeque<TableChange> = VecDeque::new();
+        stream::unfold(Some((self, buffer)), |state| async move {
+            let (mut feed, mut buffer) = state?;
+            let mut num_retries = 0;
+            loop {
+                if let Some(change) = buffer.pop_front() {
+                    return Some((Ok(change), Some((feed, buffer))));
+                }
+                match feed.next_batch().await {
+                    Ok(changes) if changes.is_empty() => {
+                        num_retries = 0;
+                        tokio::time::sleep(feed.poll_interval).await;
+                    }
+                    Ok(changes) => buffer.extend(changes),
+                    Err(e) if is_storage_error(&e) && num_retries < feed.max_retries => {
+                        num_retries += 1;
+                        warn!(
+                            "Reading version {} failed, retry {} of {}: {}",
+                            feed.next_version, num_retries, feed.max_retries, e
+                        );
+                        tokio::time::sleep(feed.poll_interval).await;
+                    }
+                    // the stream ends after an error
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+        })
+    }
+
+    /// Reads the changes of the commits written since the last batch, up to the maximum number
+    /// of versions per batch. The table is only updated once the feed caught up with it. If
+    /// reading a commit fails after others were read, the changes read so far are returned and
+    /// the commit is read again by the next batch.
+    async fn next_batch(&mut self) -> Result<Vec<TableChange>, DeltaTableError> {
+        if self.table.version < self.next_version {
+            self.table.update_incremental().await?;
+        }
+
+        let mut changes = vec![];
+        while self.next_version <= self.table.version && changes.len() < self.max_versions_per_batch
+        {
+            let actions = match self.table.get_commit_actions(self.next_version).await {
+                Ok(actions) => actions,
+                Err(_) if !changes.is_empty() => break,
+                Err(e) => return Err(e),
+            };
+            changes.push(TableChange::from_actions(
+                self.next_version,
+                actions,
+                self.skip_non_data_change,
+            ));
+            self.next_version += 1;
+        }
+        Ok(changes)
+    }
+}
+
+/// Returns whether the error comes from the storage, either reading a commit or updating the
+/// table.
+fn is_storage_error(error: &DeltaTableError) -> bool {
+    matches!(
+        error,
+        DeltaTableError::StorageError { .. }
+            | DeltaTableError::ApplyLog {
+                source: ApplyLogError::Storage { .. },
+            }
+    )
+}
@@ -1,15 +1,25 @@
 //! Implementation for writing delta checkpoints.
+//!
+//! Writing a checkpoint also cleans up the log: tombstones older than the
+//! `delta.deletedFileRetentionDuration` table property are left out of the checkpoint, and
+//! commits and checkpoints older than the `delta.logRetentionDuration` table property are
+//! deleted once a newer checkpoint covers them.
 
 use arrow::datatypes::Schema as ArrowSchema;
 use arrow::error::ArrowError;
 use arrow::json::reader::Decoder;
+use chrono::Utc;
+use futures::StreamExt;
+use lazy_static::lazy_static;
 use log::*;
 use parquet::arrow::ArrowWriter;
 use parquet::errors::ParquetError;
 use parquet::file::writer::InMemoryWriteableCursor;
+use regex::Regex;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::time::Duration;
 
 use super::action;
 use super::delta_arrow::delta_log_schema_for_table;
@@ -17,7 +27,7 @@ use super::open_table_with_version;
 use super::schema::*;
 use super::storage;
 use super::storage::{StorageBackend, StorageError};
-use super::{CheckPoint, DeltaTableError, DeltaTableState};
+use super::{CheckPoint, DeltaTableError, DeltaTableMetaData, DeltaTableState};
 
 /// Error returned when the CheckPointWriter is unable to write a checkpoint.
 #[derive(thiserror::Error, Debug)]
@@ -57,6 +67,14 @@ pub enum CheckPointWriterError {
         #[from]
         source: StorageError,
     },
+    /// Error returned when a retention duration table property is not a valid interval.
+    #[error("Invalid interval {value} of table property {property}")]
+    InvalidRetentionDuration {
+        /// The table property
+        property: String,
+        /// The invalid value of the table property
+        value: String,
+    },
     /// Passthrough error returned by serde_json.
     #[error("serde_json::Error: {source}")]
     JSONSerialization {
@@ -72,12 +90,28 @@ impl From<CheckPointWriterError> for ArrowError {
     }
 }
 
+/// Number of actions written to a checkpoint part before a new part is started, unless
+/// configured otherwise.
+pub const DEFAULT_MAX_ACTIONS_PER_PART: usize = 1_000_000;
+
+/// Table property configuring how long commits and checkpoints are kept in the log.
+pub const LOG_RETENTION_DURATION: &str = "delta.logRetentionDuration";
+/// Table property configuring how long tombstones are kept in the table state.
+pub const DELETED_FILE_RETENTION_DURATION: &str = "delta.deletedFileRetentionDuration";
+/// Table property configuring whether expired commits and checkpoints are deleted when writing
+/// a checkpoint.
+pub const ENABLE_EXPIRED_LOG_CLEANUP: &str = "delta.enableExpiredLogCleanup";
+
+const DEFAULT_LOG_RETENTION_DURATION: Duration = Duration::from_secs(30 * 24 * 3600);
+const DEFAULT_DELETED_FILE_RETENTION_DURATION: Duration = Duration::from_secs(7 * 24 * 3600);
+
 /// Struct for writing checkpoints to the delta log.
 pub struct CheckPointWriter {
     table_uri: String,
     delta_log_uri: String,
     last_checkpoint_uri: String,
     storage: Box<dyn StorageBackend>,
+    max_actions_per_part: usize,
 }
 
 impl CheckPointWriter {
@@ -91,6 +125,7 @@ impl CheckPointWriter {
             delta_log_uri,
             last_checkpoint_uri,
             storage,
+            max_actions_per_part: DEFAULT_MAX_ACTIONS_PER_PART,
         }
     }
 
@@ -101,7 +136,15 @@ impl CheckPointWriter {
         Ok(Self::new(table_uri, storage_backend))
     }
 
-    /// Creates a new checkpoint at the specified version.
+    /// Sets the number of actions a checkpoint part may hold. Checkpoints with more actions are
+    /// split into multiple parts, `DEFAULT_MAX_ACTIONS_PER_PART` by default.
+    pub fn with_max_actions_per_part(mut self, max_actions_per_part: usize) -> Self {
+        self.max_actions_per_part = max_actions_per_part.max(1);
+        self
+    }
+
+    /// Creates a new checkpoint at the specified version. Afterwards, expired log files are
+    /// deleted as by `cleanup_expired_logs_from_state`.
     /// NOTE: This method loads a new instance of delta table to determine the state to
     /// checkpoint.
     pub async fn create_checkpoint_for_version(
@@ -111,33 +154,108 @@ impl CheckPointWriter {
         let table = open_table_with_version(self.table_uri.as_str(), version).await?;
 
         self.create_checkpoint_from_state(version, table.get_state())
-            .await
+            .await?;
+        self.cleanup_expired_logs_from_state(version, table.get_state())
+            .await?;
+
+        Ok(())
     }
 
     /// Creates a new checkpoint at the specified version from the given DeltaTableState.
+    ///
+    /// A checkpoint with more actions than the configured maximum per part is written as a
+    /// multi-part checkpoint, `NNNN.checkpoint.PPPPPPPPPP.NNNNNNNNNN.parquet` for part `P` of
+    /// `N`, and the number of parts is recorded in `_last_checkpoint`. Every part is uploaded as
+    /// soon as it is written, so only a single part is held in memory at a time.
+    ///
+    /// Tombstones older than the deleted file retention duration of the table are left out of
+    /// the checkpoint. Expired log files are left in place, see
+    /// `cleanup_expired_logs_from_state`.
     pub async fn create_checkpoint_from_state(
         &self,
         version: DeltaDataTypeVersion,
         state: &DeltaTableState,
     ) -> Result<(), CheckPointWriterError> {
-        // TODO: checkpoints _can_ be multi-part... haven't actually found a good reference for
-        // an appropriate split point yet though so only writing a single part currently.
-        // See https://github.com/delta-io/delta-rs/issues/288
+        let current_metadata = state
+            .current_metadata()
+            .ok_or(CheckPointWriterError::MissingMetaData)?;
 
-        info!("Writing parquet bytes to checkpoint buffer.");
-        let parquet_bytes = self.parquet_bytes_from_state(state)?;
+        // Collect partition fields along with their data type from the current schema.
+        // JSON add actions contain a `partitionValues` field which is a map<string, string>.
+        // When loading `partitionValues_parsed` we have to convert the stringified partition values back to the correct data type.
+        let data_types: Vec<(&str, &SchemaDataType)> = current_metadata
+            .schema
+            .get_fields()
+            .iter()
+            .filter_map(|f| {
+                if current_metadata
+                    .partition_columns
+                    .iter()
+                    .any(|s| s.as_str() == f.get_name())
+                {
+                    Some((f.get_name(), f.get_type()))
+                } else {
+                    None
+                }
+            })
+            .collect();
 
-        let size = parquet_bytes.len() as i64;
+        // Create the arrow schema that represents the Checkpoint parquet file.
+        let arrow_schema = delta_log_schema_for_table(
+            <ArrowSchema as TryFrom<&Schema>>::try_from(&current_metadata.schema)?,
+            current_metadata.partition_columns.as_slice(),
+        );
 
-        let checkpoint = CheckPoint::new(version, size, None);
+        // tombstones are only needed by vacuum until they expire
+        let deleted_file_retention = retention_duration(
+            current_metadata,
+            DELETED_FILE_RETENTION_DURATION,
+            DEFAULT_DELETED_FILE_RETENTION_DURATION,
+        )?;
+        let tombstone_cutoff = Utc::now().timestamp_millis()
+            - deleted_file_retention.as_millis() as DeltaDataTypeTimestamp;
+        let tombstones: Vec<&action::Remove> = state
+            .tombstones()
+            .iter()
+            .filter(|tombstone| tombstone.deletion_timestamp > tombstone_cutoff)
+            .collect();
 
-        let file_name = format!("{:020}.checkpoint.parquet", version);
-        let checkpoint_uri = self.storage.join_path(&self.delta_log_uri, &file_name);
+        let num_actions =
+            state.app_transaction_version().len() + tombstones.len() + state.files().len() + 2; // 1 (protocol) + 1 (metadata)
+        let num_parts = (num_actions + self.max_actions_per_part - 1) / self.max_actions_per_part;
+        let mut actions = checkpoint_actions(state, &tombstones, data_types.as_slice())?;
+        let decoder = Decoder::new(arrow_schema.clone(), self.max_actions_per_part, None);
+
+        for part in 1..=num_parts {
+            info!(
+                "Writing parquet bytes of checkpoint part {} of {} to buffer.",
+                part, num_parts
+            );
+            let parquet_bytes = parquet_bytes_from_actions(&decoder, &mut actions)?;
+
+            let file_name = if num_parts == 1 {
+                format!("{:020}.checkpoint.parquet", version)
+            } else {
+                format!(
+                    "{:020}.checkpoint.{:010}.{:010}.parquet",
+                    version, part, num_parts
+                )
+            };
+            let checkpoint_uri = self.storage.join_path(&self.delta_log_uri, &file_name);
+
+            info!("Writing checkpoint to {:?}.", checkpoint_uri);
+            self.storage
+                .put_obj(&checkpoint_uri, &parquet_bytes)
+                .await?;
+        }
 
-        info!("Writing checkpoint to {:?}.", checkpoint_uri);
-        self.storage
-            .put_obj(&checkpoint_uri, &parquet_bytes)
-            .await?;
+        let parts = if num_parts == 1 {
+            None
+        } else {
+            Some(num_parts as u32)
+        };
+        // the size of a checkpoint is the number of actions it holds
+        let checkpoint = CheckPoint::new(version, num_actions as DeltaDataTypeLong, parts);
 
         let last_checkpoint_content: Value = serde_json::to_value(&checkpoint)?;
         let last_checkpoint_content = serde_json::to_string(&last_checkpoint_content)?;
@@ -156,96 +274,258 @@ impl CheckPointWriter {
         Ok(())
     }
 
-    fn parquet_bytes_from_state(
+    /// Deletes expired log files up to the specified version as by `cleanup_expired_logs`, with
+    /// the log retention duration of the table, unless disabled by the
+    /// `delta.enableExpiredLogCleanup` table property. Returns the number of deleted files.
+    pub async fn cleanup_expired_logs_from_state(
         &self,
+        version: DeltaDataTypeVersion,
         state: &DeltaTableState,
-    ) -> Result<Vec<u8>, CheckPointWriterError> {
+    ) -> Result<usize, CheckPointWriterError> {
         let current_metadata = state
             .current_metadata()
             .ok_or(CheckPointWriterError::MissingMetaData)?;
+        let cleanup_enabled = current_metadata
+            .configuration
+            .get(ENABLE_EXPIRED_LOG_CLEANUP)
+            .map_or(true, |enabled| enabled != "false");
+        if !cleanup_enabled {
+            return Ok(0);
+        }
 
-        // Collect partition fields along with their data type from the current schema.
-        // JSON add actions contain a `partitionValues` field which is a map<string, string>.
-        // When loading `partitionValues_parsed` we have to convert the stringified partition values back to the correct data type.
-        let data_types: Vec<(&str, &SchemaDataType)> = current_metadata
-            .schema
-            .get_fields()
-            .iter()
-            .filter_map(|f| {
-                if current_metadata
-                    .partition_columns
-                    .iter()
-                    .any(|s| s.as_str() == f.get_name())
-                {
-                    Some((f.get_name(), f.get_type()))
-                } else {
-                    None
+        let log_retention = retention_duration(
+            current_metadata,
+            LOG_RETENTION_DURATION,
+            DEFAULT_LOG_RETENTION_DURATION,
+        )?;
+        self.cleanup_expired_logs(version, log_retention).await
+    }
+
+    /// Deletes the commits and checkpoints older than the retention duration that precede the
+    /// latest complete checkpoint older than the retention duration, up to the given version.
+    /// Every version within the retention duration can still be loaded from the remaining files.
+    /// Log files with versions or part numbers out of range are left alone. Returns the number of
+    /// deleted files.
+    pub async fn cleanup_expired_logs(
+        &self,
+        until_version: DeltaDataTypeVersion,
+        retention: Duration,
+    ) -> Result<usize, CheckPointWriterError> {
+        lazy_static! {
+            static ref LOG_FILE_REGEX: Regex = Regex::new(
+                r#"^*[/\\]_delta_log[/\\](\d{20})\.(json|checkpoint\.parquet|checkpoint\.(\d{10})\.(\d{10})\.parquet)$"#
+            )
+            .unwrap();
+        }
+
+        let cutoff = Utc::now().timestamp_millis() - retention.as_millis() as i64;
+        let mut expired_files = vec![];
+        let mut expired_checkpoints = HashSet::new();
+        let mut complete_checkpoints = HashSet::new();
+        // parts found per version and number of parts of the multi-part checkpoints
+        let mut checkpoint_parts: HashMap<(DeltaDataTypeVersion, u32), HashSet<u32>> =
+            HashMap::new();
+        let mut stream = self.storage.list_objs(&self.delta_log_uri).await?;
+        while let Some(obj_meta) = stream.next().await {
+            let obj_meta = obj_meta?;
+            let captures = match LOG_FILE_REGEX.captures(&obj_meta.path) {
+                Some(captures) => captures,
+                None => continue,
+            };
+            let version: DeltaDataTypeVersion = match captures[1].parse() {
+                Ok(version) => version,
+                Err(_) => {
+                    warn!(
+                        "Skipping log file {:?} with version out of range.",
+                        obj_meta.path
+                    );
+                    continue;
                 }
-            })
-            .collect();
+            };
+            if version > until_version {
+                continue;
+            }
+            let is_checkpoint = &captures[2] != "json";
+            if let (Some(part), Some(num_parts)) = (captures.get(3), captures.get(4)) {
+                match (part.as_str().parse(), num_parts.as_str().parse()) {
+                    (Ok(part), Ok(num_parts)) => {
+                        checkpoint_parts
+                            .entry((version, num_parts))
+                            .or_default()
+                            .insert(part);
+                    }
+                    _ => {
+                        warn!(
+                            "Skipping log file {:?} with part out of range.",
+                            obj_meta.path
+                        );
+                        continue;
+                    }
+                }
+            } else if is_checkpoint {
+                complete_checkpoints.insert(version);
+            }
 
-        // protocol
-        let mut jsons = std::iter::once(action::Action::protocol(action::Protocol {
-            min_reader_version: state.min_reader_version(),
-            min_writer_version: state.min_writer_version(),
-        }))
-        // metadata
-        .chain(std::iter::once(action::Action::metaData(
-            action::MetaData::try_from(current_metadata.clone())?,
-        )))
-        // txns
-        .chain(
-            state
-                .app_transaction_version()
-                .iter()
-                .map(|(app_id, version)| {
-                    action::Action::txn(action::Txn {
-                        app_id: app_id.clone(),
-                        version: *version,
-                        last_updated: None,
-                    })
-                }),
-        )
-        // removes
-        .chain(
-            state
-                .tombstones()
-                .iter()
-                .map(|f| action::Action::remove(f.clone())),
-        )
-        .map(|a| serde_json::to_value(a).map_err(ArrowError::from))
-        // adds
-        .chain(
-            state
-                .files()
-                .iter()
-                .map(|f| checkpoint_add_from_state(f, data_types.as_slice())),
-        );
+            if obj_meta.modified.timestamp_millis() > cutoff {
+                continue;
+            }
+            if is_checkpoint {
+                expired_checkpoints.insert(version);
+            }
+            expired_files.push((version, obj_meta.path));
+        }
+        for ((version, num_parts), parts) in checkpoint_parts {
+            if num_parts > 0 && (1..=num_parts).all(|part| parts.contains(&part)) {
+                complete_checkpoints.insert(version);
+            }
+        }
 
-        debug!("Preparing checkpoint parquet buffer.");
-        // Create the arrow schema that represents the Checkpoint parquet file.
-        let arrow_schema = delta_log_schema_for_table(
-            <ArrowSchema as TryFrom<&Schema>>::try_from(&current_metadata.schema)?,
-            current_metadata.partition_columns.as_slice(),
+        // files are only deleted if an expired checkpoint with all its parts replaces them
+        let checkpoint_version = match expired_checkpoints
+            .into_iter()
+            .filter(|version| complete_checkpoints.contains(version))
+            .max()
+        {
+            Some(version) => version,
+            None => return Ok(0),
+        };
+        let mut num_deleted = 0;
+        for (_, path) in expired_files
+            .into_iter()
+            .filter(|(version, _)| *version < checkpoint_version)
+        {
+            debug!("Deleting expired log file {:?}.", path);
+            match self.storage.delete_obj(&path).await {
+                Ok(_) => num_deleted += 1,
+                Err(StorageError::NotFound) => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        info!(
+            "Deleted {} expired log files before checkpoint {}.",
+            num_deleted, checkpoint_version
         );
-        debug!("Writing to checkpoint parquet buffer...");
-        // Write the Checkpoint parquet file.
-        let writeable_cursor = InMemoryWriteableCursor::default();
-        let mut writer =
-            ArrowWriter::try_new(writeable_cursor.clone(), arrow_schema.clone(), None)?;
-        let batch_size = state.app_transaction_version().len()
-            + state.tombstones().len()
-            + state.files().len()
-            + 2; // 1 (protocol) + 1 (metadata)
-        let decoder = Decoder::new(arrow_schema, batch_size, None);
-        while let Some(batch) = decoder.next_batch(&mut jsons)? {
-            writer.write(&batch)?;
+
+        Ok(num_deleted)
+    }
+}
+
+/// Returns the duration configured by the table property, or the default if it is not set.
+/// Durations are given as intervals like `interval 30 days`.
+fn retention_duration(
+    metadata: &DeltaTableMetaData,
+    property: &str,
+    default: Duration,
+) -> Result<Duration, CheckPointWriterError> {
+    match metadata.configuration.get(property) {
+        None => Ok(default),
+        Some(value) => {
+            parse_interval(value).ok_or_else(|| CheckPointWriterError::InvalidRetentionDuration {
+                property: property.to_string(),
+                value: value.clone(),
+            })
         }
-        let _ = writer.close()?;
-        debug!("Finished writing checkpoint parquet buffer.");
+    }
+}
+
+/// Parses an interval like `interval 30 days`, where the leading `interval` is optional.
+fn parse_interval(value: &str) -> Option<Duration> {
+    let mut parts = value.split_whitespace().peekable();
+    if parts
+        .peek()
+        .map_or(false, |part| part.eq_ignore_ascii_case("interval"))
+    {
+        parts.next();
+    }
+    let number: u64 = parts.next()?.parse().ok()?;
+    let unit = parts.next()?.to_ascii_lowercase();
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let duration = match unit.trim_end_matches('s') {
+        "nanosecond" => Duration::from_nanos(number),
+        "microsecond" => Duration::from_micros(number),
+        "millisecond" => Duration::from_millis(number),
+        "second" => Duration::from_secs(number),
+        "minute" => Duration::from_secs(number * 60),
+        "hour" => Duration::from_secs(number * 3600),
+        "day" => Duration::from_secs(number * 24 * 3600),
+        "week" => Duration::from_secs(number * 7 * 24 * 3600),
+        _ => return None,
+    };
+    Some(duration)
+}
 
-        Ok(writeable_cursor.data())
+/// Writes the next batch of actions read by the decoder, i.e. a single checkpoint part, to a
+/// parquet buffer.
+fn parquet_bytes_from_actions<I>(
+    decoder: &Decoder,
+    actions: &mut I,
+) -> Result<Vec<u8>, CheckPointWriterError>
+where
+    I: Iterator<Item = Result<Value, ArrowError>>,
+{
+    debug!("Writing to checkpoint parquet buffer...");
+    let writeable_cursor = InMemoryWriteableCursor::default();
+    let mut writer = ArrowWriter::try_new(writeable_cursor.clone(), decoder.schema(), None)?;
+    if let Some(batch) = decoder.next_batch(actions)? {
+        writer.write(&batch)?;
     }
+    let _ = writer.close()?;
+    debug!("Finished writing checkpoint parquet buffer.");
+
+    Ok(writeable_cursor.data())
+}
+
+/// Returns the actions of a checkpoint of the state as JSON values: the protocol, the metadata,
+/// the app transactions, the given unexpired tombstones and the active files.
+fn checkpoint_actions<'a>(
+    state: &'a DeltaTableState,
+    tombstones: &'a [&'a action::Remove],
+    data_types: &'a [(&'a str, &'a SchemaDataType)],
+) -> Result<impl Iterator<Item = Result<Value, ArrowError>> + 'a, CheckPointWriterError> {
+    let current_metadata = state
+        .current_metadata()
+        .ok_or(CheckPointWriterError::MissingMetaData)?;
+
+    // protocol
+    let actions = std::iter::once(action::Action::protocol(action::Protocol {
+        min_reader_version: state.min_reader_version(),
+        min_writer_version: state.min_writer_version(),
+    }))
+    // metadata
+    .chain(std::iter::once(action::Action::metaData(
+        action::MetaData::try_from(current_metadata.clone())?,
+    )))
+    // txns
+    .chain(
+        state
+            .app_transaction_version()
+            .iter()
+            .map(|(app_id, version)| {
+                action::Action::txn(action::Txn {
+                    app_id: app_id.clone(),
+                    version: *version,
+                    last_updated: None,
+                })
+            }),
+    )
+    // removes
+    .chain(
+        tombstones
+            .iter()
+            .map(|f| action::Action::remove((*f).clone())),
+    )
+    .map(|a| serde_json::to_value(a).map_err(ArrowError::from))
+    // adds
+    .chain(
+        state
+            .files()
+            .iter()
+            .map(move |f| checkpoint_add_from_state(f, data_types)),
+    );
+    Ok(actions)
 }
 
 fn checkpoint_add_from_state(
@@ -310,6 +590,30 @@ fn typed_partition_value_from_string(
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_interval_test() {
+        assert_eq!(
+            parse_interval("interval 30 days"),
+            Some(Duration::from_secs(30 * 24 * 3600))
+        );
+        assert_eq!(
+            parse_interval("INTERVAL 1 week"),
+            Some(Duration::from_secs(7 * 24 * 3600))
+        );
+        assert_eq!(
+            parse_interval("12 hours"),
+            Some(Duration::from_secs(12 * 3600))
+        );
+        assert_eq!(
+            parse_interval("interval 0 seconds"),
+            Some(Duration::from_secs(0))
+        );
+        assert_eq!(parse_interval("interval 30"), None);
+        assert_eq!(parse_interval("interval -1 days"), None);
+        assert_eq!(parse_interval("interval 1 fortnight"), None);
+        assert_eq!(parse_interval("interval 1 day 2 hours"), None);
+    }
+
     #[test]
     fn typed_partition_value_from_string_test() {
         let string_value: Value = "Hello World!".into();
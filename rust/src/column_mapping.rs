@@ -0,0 +1,849 @@
+//! Column mapping support for Delta tables. With column mapping, the columns of the data files,
+//! and the keys of the partition values and statistics of add actions, are named by physical
+//! names or identified by field ids recorded in the metadata of the schema fields. Columns can
+//! then be renamed or dropped by changing the schema alone, without rewriting any data file.
+//!
+//! The nested fields of structs, including structs in arrays and maps, are mapped the same way
+//! as top level columns.
+//!
+//! Tables in `ColumnMappingMode::Id` are read-only: they can be read, and their columns renamed
+//! or dropped, but no data files can be written to them, since the Parquet writer cannot record
+//! the field ids of the columns.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::{make_array, new_null_array, ArrayData, ArrayRef};
+use arrow::compute::cast;
+use arrow::datatypes::DataType as ArrowDataType;
+use arrow::error::Result as ArrowResult;
+use parquet::schema::types::{SchemaDescriptor, Type};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::action::{self, Action, DeltaOperation};
+use crate::schema::{
+    DeltaDataTypeVersion, Schema, SchemaDataType, SchemaField, SchemaTypeArray, SchemaTypeMap,
+    SchemaTypeStruct,
+};
+use crate::{DeltaTable, DeltaTableError, DeltaTableMetaData, DeltaTransactionError};
+
+/// Table property configuring the column mapping mode of a table.
+pub const COLUMN_MAPPING_MODE: &str = "delta.columnMapping.mode";
+/// Table property holding the largest field id assigned to a column of a table.
+pub const COLUMN_MAPPING_MAX_COLUMN_ID: &str = "delta.columnMapping.maxColumnId";
+/// Field metadata key holding the field id of a column.
+pub const COLUMN_MAPPING_ID: &str = "delta.columnMapping.id";
+/// Field metadata key holding the physical name of a column.
+pub const COLUMN_MAPPING_PHYSICAL_NAME: &str = "delta.columnMapping.physicalName";
+
+/// Protocol versions required by tables using column mapping.
+const COLUMN_MAPPING_MIN_READER_VERSION: i32 = 2;
+const COLUMN_MAPPING_MIN_WRITER_VERSION: i32 = 5;
+
+/// The ways the columns of a table can be mapped to the columns of its data files, as configured
+/// by the `delta.columnMapping.mode` table property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnMappingMode {
+    /// Columns are stored under their names.
+    None,
+    /// Columns are stored under the physical names of their fields.
+    Name,
+    /// Columns are identified by the field ids of their fields, falling back to their physical
+    /// names for data files without field ids. Data files cannot be written in this mode.
+    Id,
+}
+
+impl ColumnMappingMode {
+    /// Returns the value of the `delta.columnMapping.mode` table property for the mode.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ColumnMappingMode::None => "none",
+            ColumnMappingMode::Name => "name",
+            ColumnMappingMode::Id => "id",
+        }
+    }
+}
+
+impl SchemaField {
+    /// Returns the physical name of the column, which is its name unless the field metadata
+    /// records another one.
+    pub fn physical_name(&self) -> &str {
+        match self.get_metadata().get(COLUMN_MAPPING_PHYSICAL_NAME) {
+            Some(Value::String(name)) => name,
+            _ => self.get_name(),
+        }
+    }
+
+    /// Returns the field id of the column recorded in the field metadata, if any.
+    pub fn column_mapping_id(&self) -> Option<i64> {
+        self.get_metadata().get(COLUMN_MAPPING_ID)?.as_i64()
+    }
+}
+
+impl DeltaTableMetaData {
+    /// Returns the column mapping mode of the table. Fails if the `delta.columnMapping.mode`
+    /// table property has an unknown value.
+    pub fn column_mapping_mode(&self) -> Result<ColumnMappingMode, DeltaTableError> {
+        match self
+            .configuration
+            .get(COLUMN_MAPPING_MODE)
+            .map(|m| m.as_str())
+        {
+            None | Some("none") => Ok(ColumnMappingMode::None),
+            Some("name") => Ok(ColumnMappingMode::Name),
+            Some("id") => Ok(ColumnMappingMode::Id),
+            Some(mode) => Err(DeltaTableError::ColumnMapping {
+                msg: format!("Invalid {} table property: {}", COLUMN_MAPPING_MODE, mode),
+            }),
+        }
+    }
+
+    /// Returns the column mapping mode new data files are written in. Fails for tables in
+    /// `ColumnMappingMode::Id`, since the Parquet writer cannot record the field ids of columns.
+    pub(crate) fn write_column_mapping_mode(&self) -> Result<ColumnMappingMode, DeltaTableError> {
+        match self.column_mapping_mode()? {
+            ColumnMappingMode::Id => Err(DeltaTableError::ColumnMapping {
+                msg: "Writing data files to tables in column mapping mode id is not supported"
+                    .to_string(),
+            }),
+            mode => Ok(mode),
+        }
+    }
+
+    /// Returns the name the top level column is stored under in the data files, partition values
+    /// and statistics of the table. This is the name of the column itself unless the table uses
+    /// column mapping.
+    pub fn physical_name<'a>(&'a self, name: &'a str) -> &'a str {
+        match self.column_mapping_mode() {
+            Ok(ColumnMappingMode::Name) | Ok(ColumnMappingMode::Id) => self
+                .schema
+                .get_field_with_name(name)
+                .map(|field| field.physical_name())
+                .unwrap_or(name),
+            _ => name,
+        }
+    }
+
+    /// Returns the partition values of an add action keyed by the names of the partition columns.
+    pub(crate) fn logical_partition_values(
+        &self,
+        partition_values: &HashMap<String, String>,
+    ) -> HashMap<String, String> {
+        self.partition_columns
+            .iter()
+            .filter_map(|column| {
+                let value = partition_values.get(self.physical_name(column))?;
+                Some((column.clone(), value.clone()))
+            })
+            .collect()
+    }
+
+    /// Assigns field ids and physical names to the columns and nested fields added to the schema
+    /// of a table using column mapping. New columns are stored under unique physical names, which
+    /// cannot collide with the values of dropped columns left in the data files.
+    pub(crate) fn map_new_columns(&mut self) -> Result<(), DeltaTableError> {
+        if self.column_mapping_mode()? == ColumnMappingMode::None {
+            return Ok(());
+        }
+
+        let mut max_column_id = max_column_id(self);
+        let fields = self
+            .schema
+            .get_fields()
+            .iter()
+            .map(|field| {
+                map_field(field, &mut max_column_id, &|_| {
+                    format!("col-{}", Uuid::new_v4())
+                })
+            })
+            .collect();
+        self.schema = Schema::new(fields);
+        self.configuration.insert(
+            COLUMN_MAPPING_MAX_COLUMN_ID.to_string(),
+            max_column_id.to_string(),
+        );
+        Ok(())
+    }
+}
+
+impl DeltaTable {
+    /// Enables column mapping in `ColumnMappingMode::Name` mode. Every column and nested field is
+    /// assigned a field id and keeps its current name as physical name, so that the existing data
+    /// files stay readable. The protocol of the table is upgraded to the versions column mapping
+    /// requires.
+    ///
+    /// Only the name mode can be enabled on an existing table, since the existing data files do
+    /// not carry field ids.
+    pub async fn enable_column_mapping(
+        &mut self,
+        mode: ColumnMappingMode,
+    ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        self.update_incremental().await?;
+        let mut metadata = self.get_metadata()?.clone();
+        let current_mode = metadata.column_mapping_mode()?;
+        if current_mode == mode {
+            return Ok(self.version);
+        }
+        if current_mode != ColumnMappingMode::None || mode != ColumnMappingMode::Name {
+            return Err(DeltaTableError::ColumnMapping {
+                msg: format!(
+                    "Changing the column mapping mode from {} to {} is not supported",
+                    current_mode.as_str(),
+                    mode.as_str()
+                ),
+            }
+            .into());
+        }
+
+        let mut max_column_id = max_column_id(&metadata);
+        let fields = metadata
+            .schema
+            .get_fields()
+            .iter()
+            .map(|field| {
+                map_field(field, &mut max_column_id, &|field| {
+                    field.get_name().to_string()
+                })
+            })
+            .collect();
+        metadata.schema = Schema::new(fields);
+
+        let mut properties = HashMap::new();
+        properties.insert(COLUMN_MAPPING_MODE.to_string(), mode.as_str().to_string());
+        properties.insert(
+            COLUMN_MAPPING_MAX_COLUMN_ID.to_string(),
+            max_column_id.to_string(),
+        );
+        metadata.configuration.extend(properties.clone());
+
+        let protocol = action::Protocol {
+            min_reader_version: self
+                .get_min_reader_version()
+                .max(COLUMN_MAPPING_MIN_READER_VERSION),
+            min_writer_version: self
+                .get_min_writer_version()
+                .max(COLUMN_MAPPING_MIN_WRITER_VERSION),
+        };
+
+        let mut dtx = self.create_transaction(None);
+        dtx.add_actions(vec![
+            Action::protocol(protocol),
+            Action::metaData(action::MetaData::try_from(metadata)?),
+        ]);
+        dtx.commit(Some(DeltaOperation::SetTableProperties { properties }))
+            .await
+    }
+
+    /// Renames a top level column of a table using column mapping. Only the schema of the table
+    /// changes, the data files keep storing the column under its physical name.
+    /// Columns referred to by a CHECK constraint, invariant or generation expression cannot be
+    /// renamed.
+    pub async fn rename_column(
+        &mut self,
+        name: &str,
+        new_name: &str,
+    ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        self.update_incremental().await?;
+        let mut metadata = self.get_metadata()?.clone();
+        validate_column_mapping(&metadata, "renamed")?;
+        if metadata.schema.get_field_with_name(name).is_none() {
+            return Err(DeltaTableError::SchemaMismatch {
+                msg: format!("Column `{}` is not in the table schema", name),
+            }
+            .into());
+        }
+        if metadata.schema.get_field_with_name(new_name).is_some() {
+            return Err(DeltaTableError::SchemaMismatch {
+                msg: format!("Column `{}` already exists in the table schema", new_name),
+            }
+            .into());
+        }
+        metadata.validate_unreferenced_column(name, "renamed")?;
+
+        let fields = metadata
+            .schema
+            .get_fields()
+            .iter()
+            .map(|field| match field.get_name() {
+                n if n == name => with_metadata(field, new_name, field.get_metadata().clone()),
+                _ => field.clone(),
+            })
+            .collect();
+        metadata.schema = Schema::new(fields);
+        for column in metadata.partition_columns.iter_mut() {
+            if column == name {
+                *column = new_name.to_string();
+            }
+        }
+
+        let mut dtx = self.create_transaction(None);
+        dtx.add_actions(vec![Action::metaData(action::MetaData::try_from(
+            metadata,
+        )?)]);
+        dtx.commit(Some(DeltaOperation::RenameColumn {
+            oldColumnPath: name.to_string(),
+            newColumnPath: new_name.to_string(),
+        }))
+        .await
+    }
+
+    /// Drops top level columns of a table using column mapping. Only the schema of the table
+    /// changes, the values of the columns are left in the data files. Partition columns cannot be
+    /// dropped, nor columns referred to by a CHECK constraint or by the invariant or generation
+    /// expression of another column.
+    pub async fn drop_columns(
+        &mut self,
+        names: &[&str],
+    ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        self.update_incremental().await?;
+        let mut metadata = self.get_metadata()?.clone();
+        validate_column_mapping(&metadata, "dropped")?;
+        for name in names {
+            if metadata.schema.get_field_with_name(name).is_none() {
+                return Err(DeltaTableError::SchemaMismatch {
+                    msg: format!("Column `{}` is not in the table schema", name),
+                }
+                .into());
+            }
+            if metadata.partition_columns.iter().any(|c| c == name) {
+                return Err(DeltaTableError::SchemaMismatch {
+                    msg: format!("Partition column `{}` cannot be dropped", name),
+                }
+                .into());
+            }
+        }
+
+        let fields: Vec<SchemaField> = metadata
+            .schema
+            .get_fields()
+            .iter()
+            .filter(|field| !names.contains(&field.get_name()))
+            .cloned()
+            .collect();
+        if fields.is_empty() {
+            return Err(DeltaTableError::SchemaMismatch {
+                msg: "All columns of the table cannot be dropped".to_string(),
+            }
+            .into());
+        }
+        metadata.schema = Schema::new(fields);
+        // the invariants of the dropped columns are dropped with them
+        for name in names {
+            metadata.validate_unreferenced_column(name, "dropped")?;
+        }
+
+        let mut dtx = self.create_transaction(None);
+        dtx.add_actions(vec![Action::metaData(action::MetaData::try_from(
+            metadata,
+        )?)]);
+        dtx.commit(Some(DeltaOperation::DropColumns {
+            columns: names.iter().map(|n| n.to_string()).collect(),
+        }))
+        .await
+    }
+}
+
+/// A column or nested field of a table as stored in a data file.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FileColumn {
+    /// The name of the column or nested field in the data file.
+    pub(crate) name: String,
+    /// The nested fields of a struct in order of the table schema, with `None` for the ones
+    /// missing from the data file, or the element of an array.
+    pub(crate) children: Vec<Option<FileColumn>>,
+}
+
+/// Resolves the columns of the table to the columns of a data file with the given Parquet schema,
+/// in order of the table schema. Nested fields of structs are resolved the same way as top level
+/// columns. In id mode, columns are looked up by their field ids and by their physical names in
+/// files without field ids.
+pub(crate) fn data_file_columns(
+    metadata: &DeltaTableMetaData,
+    mode: ColumnMappingMode,
+    file_schema: &SchemaDescriptor,
+) -> Vec<FileColumn> {
+    let file_fields = file_schema.root_schema().get_fields();
+    metadata
+        .schema
+        .get_fields()
+        .iter()
+        .map(|field| {
+            let name = file_field_name(field, mode, file_fields);
+            let children = file_fields
+                .iter()
+                .find(|file_field| file_field.name() == name)
+                .map(|file_field| nested_file_columns(field.get_type(), mode, file_field))
+                .unwrap_or_default();
+            FileColumn {
+                name: name.to_string(),
+                children,
+            }
+        })
+        .collect()
+}
+
+/// Resolves the nested fields of a struct, or the element of an array, to the fields of the
+/// given Parquet type.
+fn nested_file_columns(
+    data_type: &SchemaDataType,
+    mode: ColumnMappingMode,
+    file_type: &Type,
+) -> Vec<Option<FileColumn>> {
+    match data_type {
+        SchemaDataType::r#struct(s) if file_type.is_group() => {
+            let file_fields = file_type.get_fields();
+            s.get_fields()
+                .iter()
+                .map(|field| {
+                    let name = file_field_name(field, mode, file_fields);
+                    let file_field = file_fields.iter().find(|f| f.name() == name)?;
+                    Some(FileColumn {
+                        name: name.to_string(),
+                        children: nested_file_columns(field.get_type(), mode, file_field),
+                    })
+                })
+                .collect()
+        }
+        SchemaDataType::array(a) => match list_element(file_type) {
+            Some(element) => vec![Some(FileColumn {
+                name: element.name().to_string(),
+                children: nested_file_columns(a.get_element_type(), mode, element),
+            })],
+            None => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+/// Returns the name of the field among the given fields of a data file.
+fn file_field_name<'a>(
+    field: &'a SchemaField,
+    mode: ColumnMappingMode,
+    file_fields: &'a [Arc<Type>],
+) -> &'a str {
+    match mode {
+        ColumnMappingMode::None => field.get_name(),
+        ColumnMappingMode::Name => field.physical_name(),
+        ColumnMappingMode::Id => field
+            .column_mapping_id()
+            .and_then(|id| {
+                file_fields
+                    .iter()
+                    .map(|f| f.get_basic_info())
+                    .find(|info| info.has_id() && info.id() as i64 == id)
+            })
+            .map(|info| info.name())
+            .unwrap_or_else(|| field.physical_name()),
+    }
+}
+
+/// Returns the element of a Parquet list in the standard three level encoding, or in the legacy
+/// two level encoding.
+fn list_element(file_type: &Type) -> Option<&Type> {
+    if !file_type.is_group() {
+        return None;
+    }
+    let repeated = file_type.get_fields().first()?;
+    if repeated.is_group()
+        && repeated.get_fields().len() == 1
+        && repeated.name() != "array"
+        && !repeated.name().ends_with("_tuple")
+    {
+        Some(repeated.get_fields()[0].as_ref())
+    } else {
+        Some(repeated.as_ref())
+    }
+}
+
+/// Converts an array read from a data file to the type of its column in the table. Nested fields
+/// of structs are taken from the fields of the data file they were resolved to, nested fields
+/// missing from the file are filled with nulls and narrower values are cast to the types of the
+/// table.
+pub(crate) fn logical_array(
+    array: &ArrayRef,
+    column: &FileColumn,
+    data_type: &ArrowDataType,
+) -> ArrowResult<ArrayRef> {
+    match (data_type, array.data_type()) {
+        (t, file_type) if t == file_type => Ok(array.clone()),
+        (ArrowDataType::Struct(_), ArrowDataType::Struct(_))
+        | (ArrowDataType::List(_), ArrowDataType::List(_)) => Ok(make_array(logical_array_data(
+            array.data(),
+            column,
+            data_type,
+        )?)),
+        _ => cast(array, data_type),
+    }
+}
+
+fn logical_array_data(
+    data: &ArrayData,
+    column: &FileColumn,
+    data_type: &ArrowDataType,
+) -> ArrowResult<ArrayData> {
+    let child_data = match (data_type, data.data_type()) {
+        (t, file_type) if t == file_type => return Ok(data.clone()),
+        (ArrowDataType::Struct(fields), ArrowDataType::Struct(file_fields)) => fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let child = column.children.get(i).and_then(|child| child.as_ref());
+                let file_index = child
+                    .and_then(|child| file_fields.iter().position(|f| f.name() == &child.name));
+                match (child, file_index) {
+                    (Some(child), Some(j)) => {
+                        logical_array_data(&data.child_data()[j], child, field.data_type())
+                    }
+                    _ => Ok(
+                        new_null_array(field.data_type(), data.offset() + data.len())
+                            .data()
+                            .clone(),
+                    ),
+                }
+            })
+            .collect::<ArrowResult<Vec<ArrayData>>>()?,
+        (ArrowDataType::List(field), ArrowDataType::List(_)) => {
+            let element = column.children.first().and_then(|child| child.as_ref());
+            match element {
+                Some(element) => vec![logical_array_data(
+                    &data.child_data()[0],
+                    element,
+                    field.data_type(),
+                )?],
+                None => return Ok(cast(&make_array(data.clone()), data_type)?.data().clone()),
+            }
+        }
+        _ => return Ok(cast(&make_array(data.clone()), data_type)?.data().clone()),
+    };
+    Ok(ArrayData::new(
+        data_type.clone(),
+        data.len(),
+        Some(data.null_count()),
+        data.null_buffer().cloned(),
+        data.offset(),
+        data.buffers().to_vec(),
+        child_data,
+    ))
+}
+
+/// Fails unless the table uses column mapping, which columns need to be renamed or dropped
+/// without rewriting the data files.
+fn validate_column_mapping(
+    metadata: &DeltaTableMetaData,
+    change: &str,
+) -> Result<(), DeltaTableError> {
+    match metadata.column_mapping_mode()? {
+        ColumnMappingMode::None => Err(DeltaTableError::ColumnMapping {
+            msg: format!(
+                "Columns can only be {} in tables using column mapping, see {}",
+                change, COLUMN_MAPPING_MODE
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the largest field id assigned to a column of the table.
+fn max_column_id(metadata: &DeltaTableMetaData) -> i64 {
+    metadata
+        .configuration
+        .get(COLUMN_MAPPING_MAX_COLUMN_ID)
+        .and_then(|id| id.parse::<i64>().ok())
+        .unwrap_or(0)
+}
+
+fn with_metadata(field: &SchemaField, name: &str, metadata: HashMap<String, Value>) -> SchemaField {
+    SchemaField::new(
+        name.to_string(),
+        field.get_type().clone(),
+        field.is_nullable(),
+        metadata,
+    )
+}
+
+/// Assigns a field id and a physical name to the field and to its nested fields that miss them.
+/// Field ids are counted up from `max_column_id`, physical names are given by `physical_name`.
+fn map_field(
+    field: &SchemaField,
+    max_column_id: &mut i64,
+    physical_name: &dyn Fn(&SchemaField) -> String,
+) -> SchemaField {
+    let mut field_metadata = field.get_metadata().clone();
+    if !field_metadata.contains_key(COLUMN_MAPPING_ID) {
+        *max_column_id += 1;
+        field_metadata.insert(COLUMN_MAPPING_ID.to_string(), Value::from(*max_column_id));
+    }
+    if !field_metadata.contains_key(COLUMN_MAPPING_PHYSICAL_NAME) {
+        field_metadata.insert(
+            COLUMN_MAPPING_PHYSICAL_NAME.to_string(),
+            Value::String(physical_name(field)),
+        );
+    }
+    SchemaField::new(
+        field.get_name().to_string(),
+        map_nested_fields(field.get_type(), max_column_id, physical_name),
+        field.is_nullable(),
+        field_metadata,
+    )
+}
+
+fn map_nested_fields(
+    data_type: &SchemaDataType,
+    max_column_id: &mut i64,
+    physical_name: &dyn Fn(&SchemaField) -> String,
+) -> SchemaDataType {
+    match data_type {
+        SchemaDataType::r#struct(s) => SchemaDataType::r#struct(SchemaTypeStruct::new(
+            s.get_fields()
+                .iter()
+                .map(|field| map_field(field, max_column_id, physical_name))
+                .collect(),
+        )),
+        SchemaDataType::array(a) => SchemaDataType::array(SchemaTypeArray::new(
+            Box::new(map_nested_fields(
+                a.get_element_type(),
+                max_column_id,
+                physical_name,
+            )),
+            a.contains_null(),
+        )),
+        SchemaDataType::map(m) => {
+            let key_type = map_nested_fields(m.get_key_type(), max_column_id, physical_name);
+            let value_type = map_nested_fields(m.get_value_type(), max_column_id, physical_name);
+            SchemaDataType::map(SchemaTypeMap::new(
+                Box::new(key_type),
+                Box::new(value_type),
+                m.get_value_contains_null(),
+            ))
+        }
+        primitive => primitive.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(mode: Option<&str>) -> DeltaTableMetaData {
+        let mut field_metadata = HashMap::new();
+        field_metadata.insert(COLUMN_MAPPING_ID.to_string(), json!(1));
+        field_metadata.insert(COLUMN_MAPPING_PHYSICAL_NAME.to_string(), json!("col-5f42"));
+        let schema = Schema::new(vec![SchemaField::new(
+            "id".to_string(),
+            SchemaDataType::primitive("string".to_string()),
+            true,
+            field_metadata,
+        )]);
+        let mut configuration = HashMap::new();
+        if let Some(mode) = mode {
+            configuration.insert(COLUMN_MAPPING_MODE.to_string(), mode.to_string());
+        }
+        DeltaTableMetaData::new(None, None, None, schema, vec![], configuration)
+    }
+
+    #[test]
+    fn physical_name_depends_on_mode() {
+        assert_eq!(metadata(None).physical_name("id"), "id");
+        assert_eq!(metadata(Some("none")).physical_name("id"), "id");
+        assert_eq!(metadata(Some("name")).physical_name("id"), "col-5f42");
+        assert_eq!(metadata(Some("id")).physical_name("id"), "col-5f42");
+        assert_eq!(metadata(Some("name")).physical_name("other"), "other");
+    }
+
+    #[test]
+    fn column_mapping_mode_rejects_unknown_modes() {
+        assert_eq!(
+            metadata(Some("id")).column_mapping_mode().unwrap(),
+            ColumnMappingMode::Id
+        );
+        assert!(matches!(
+            metadata(Some("position")).column_mapping_mode(),
+            Err(DeltaTableError::ColumnMapping { .. })
+        ));
+    }
+
+    #[test]
+    fn field_id_is_read_from_metadata() {
+        let metadata = metadata(Some("id"));
+        let field = &metadata.schema.get_fields()[0];
+        assert_eq!(field.column_mapping_id(), Some(1));
+    }
+
+    #[test]
+    fn new_columns_are_mapped() {
+        let mut metadata = metadata(Some("name"));
+        metadata
+            .configuration
+            .insert(COLUMN_MAPPING_MAX_COLUMN_ID.to_string(), "1".to_string());
+        let mut fields = metadata.schema.get_fields().clone();
+        fields.push(SchemaField::new(
+            "value".to_string(),
+            SchemaDataType::primitive("integer".to_string()),
+            true,
+            HashMap::new(),
+        ));
+        metadata.schema = Schema::new(fields);
+
+        metadata.map_new_columns().unwrap();
+
+        let field = metadata.schema.get_field_with_name("value").unwrap();
+        assert_eq!(field.column_mapping_id(), Some(2));
+        assert!(field.physical_name().starts_with("col-"));
+        assert_eq!(metadata.physical_name("id"), "col-5f42");
+        assert_eq!(
+            metadata.configuration[COLUMN_MAPPING_MAX_COLUMN_ID],
+            "2".to_string()
+        );
+    }
+
+    fn struct_field(name: &str, fields: Vec<SchemaField>) -> SchemaField {
+        SchemaField::new(
+            name.to_string(),
+            SchemaDataType::r#struct(SchemaTypeStruct::new(fields)),
+            true,
+            HashMap::new(),
+        )
+    }
+
+    fn mapped_field(name: &str, id: i64, physical_name: &str, data_type: &str) -> SchemaField {
+        let mut field_metadata = HashMap::new();
+        field_metadata.insert(COLUMN_MAPPING_ID.to_string(), json!(id));
+        field_metadata.insert(
+            COLUMN_MAPPING_PHYSICAL_NAME.to_string(),
+            json!(physical_name),
+        );
+        SchemaField::new(
+            name.to_string(),
+            SchemaDataType::primitive(data_type.to_string()),
+            true,
+            field_metadata,
+        )
+    }
+
+    #[test]
+    fn nested_columns_are_mapped() {
+        let mut metadata = metadata(Some("name"));
+        metadata
+            .configuration
+            .insert(COLUMN_MAPPING_MAX_COLUMN_ID.to_string(), "1".to_string());
+        let mut fields = metadata.schema.get_fields().clone();
+        fields.push(struct_field(
+            "address",
+            vec![SchemaField::new(
+                "city".to_string(),
+                SchemaDataType::primitive("string".to_string()),
+                true,
+                HashMap::new(),
+            )],
+        ));
+        metadata.schema = Schema::new(fields);
+
+        metadata.map_new_columns().unwrap();
+
+        let field = metadata.schema.get_field_with_name("address").unwrap();
+        assert_eq!(field.column_mapping_id(), Some(2));
+        let nested = match field.get_type() {
+            SchemaDataType::r#struct(s) => &s.get_fields()[0],
+            _ => unreachable!(),
+        };
+        assert_eq!(nested.get_name(), "city");
+        assert_eq!(nested.column_mapping_id(), Some(3));
+        assert!(nested.physical_name().starts_with("col-"));
+        assert_eq!(
+            metadata.configuration[COLUMN_MAPPING_MAX_COLUMN_ID],
+            "3".to_string()
+        );
+    }
+
+    #[test]
+    fn nested_file_columns_are_resolved_by_field_ids() {
+        use parquet::basic::{Repetition, Type as PhysicalType};
+
+        let mut metadata = metadata(Some("id"));
+        metadata.schema = Schema::new(vec![SchemaField::new(
+            "address".to_string(),
+            SchemaDataType::r#struct(SchemaTypeStruct::new(vec![
+                mapped_field("city", 3, "col-c1", "string"),
+                mapped_field("zip", 4, "col-z1", "integer"),
+            ])),
+            true,
+            vec![
+                (COLUMN_MAPPING_ID.to_string(), json!(2)),
+                (COLUMN_MAPPING_PHYSICAL_NAME.to_string(), json!("col-a1")),
+            ]
+            .into_iter()
+            .collect(),
+        )]);
+        let city = Type::primitive_type_builder("town", PhysicalType::BYTE_ARRAY)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_id(3)
+            .build()
+            .unwrap();
+        let address = Type::group_type_builder("col-a1")
+            .with_repetition(Repetition::OPTIONAL)
+            .with_fields(&mut vec![Arc::new(city)])
+            .build()
+            .unwrap();
+        let root = Type::group_type_builder("spark_schema")
+            .with_fields(&mut vec![Arc::new(address)])
+            .build()
+            .unwrap();
+
+        let columns = data_file_columns(
+            &metadata,
+            ColumnMappingMode::Id,
+            &SchemaDescriptor::new(Arc::new(root)),
+        );
+
+        assert_eq!(
+            columns,
+            vec![FileColumn {
+                name: "col-a1".to_string(),
+                children: vec![
+                    Some(FileColumn {
+                        name: "town".to_string(),
+                        children: vec![],
+                    }),
+                    None,
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn logical_array_renames_nested_fields() {
+        use arrow::array::{Array, StringArray, StructArray};
+        use arrow::datatypes::Field;
+
+        let file_array: ArrayRef = Arc::new(StructArray::from(vec![(
+            Field::new("col-c1", ArrowDataType::Utf8, true),
+            Arc::new(StringArray::from(vec![Some("Berlin"), None])) as ArrayRef,
+        )]));
+        let column = FileColumn {
+            name: "col-a1".to_string(),
+            children: vec![
+                Some(FileColumn {
+                    name: "col-c1".to_string(),
+                    children: vec![],
+                }),
+                None,
+            ],
+        };
+        let data_type = ArrowDataType::Struct(vec![
+            Field::new("city", ArrowDataType::Utf8, true),
+            Field::new("zip", ArrowDataType::Int32, true),
+        ]);
+
+        let array = logical_array(&file_array, &column, &data_type).unwrap();
+
+        assert_eq!(array.data_type(), &data_type);
+        let array = array.as_any().downcast_ref::<StructArray>().unwrap();
+        assert_eq!(
+            array.column(0).as_ref(),
+            Arc::new(StringArray::from(vec![Some("Berlin"), None])).as_ref(),
+        );
+        assert_eq!(array.column(1).null_count(), 2);
+        assert_eq!(array.column(1).data_type(), &ArrowDataType::Int32);
+    }
+}
@@ -0,0 +1,509 @@
+//! CHECK constraints and column invariants of Delta tables.
+//!
+//! CHECK constraints are SQL expressions stored in the `delta.constraints.<name>` table properties,
+//! invariants are SQL expressions stored in the `delta.invariants` metadata of schema fields. Every
+//! row written to a table has to satisfy all of them, i.e. the expressions have to evaluate to
+//! true. Expressions are evaluated with DataFusion, writing to a table with constraints or
+//! invariants therefore requires the `datafusion-ext` feature.
+
+use arrow::datatypes::Schema as ArrowSchema;
+use arrow::record_batch::RecordBatch;
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use crate::action::{self, Action, DeltaOperation};
+use crate::generated_columns::GeneratedColumn;
+use crate::schema::{DeltaDataTypeVersion, Schema, SchemaDataType, SchemaField};
+use crate::{DeltaTable, DeltaTableError, DeltaTableMetaData, DeltaTransactionError};
+
+/// Prefix of the table properties holding the CHECK constraints of a table.
+pub const CONSTRAINTS_PREFIX: &str = "delta.constraints.";
+/// Field metadata key holding the invariant of a column.
+pub const INVARIANTS: &str = "delta.invariants";
+
+/// Writer version required by tables with CHECK constraints.
+const CONSTRAINTS_MIN_WRITER_VERSION: i32 = 3;
+
+/// A CHECK constraint of a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    /// The name of the constraint, in lower case.
+    pub name: String,
+    /// The SQL expression every row of the table has to satisfy.
+    pub expression: String,
+}
+
+/// An invariant of a column of a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invariant {
+    /// The path of the column, with the names of nested fields separated by dots.
+    pub field_name: String,
+    /// The SQL expression every row of the table has to satisfy.
+    pub expression: String,
+}
+
+impl DeltaTableMetaData {
+    /// Returns the CHECK constraints of the table, ordered by name.
+    pub fn get_constraints(&self) -> Vec<Constraint> {
+        let mut constraints: Vec<Constraint> = self
+            .configuration
+            .iter()
+            .filter(|(key, _)| key.starts_with(CONSTRAINTS_PREFIX))
+            .map(|(key, expression)| Constraint {
+                name: key[CONSTRAINTS_PREFIX.len()..].to_string(),
+                expression: expression.clone(),
+            })
+            .collect();
+        constraints.sort_by(|a, b| a.name.cmp(&b.name));
+        constraints
+    }
+}
+
+impl DeltaTableMetaData {
+    /// Fails with `DeltaTableError::SchemaMismatch` if a CHECK constraint, invariant or
+    /// generation expression of the table refers to the top level column, which therefore cannot
+    /// be `change`d, e.g. renamed, without breaking every later write.
+    pub(crate) fn validate_unreferenced_column(
+        &self,
+        column: &str,
+        change: &str,
+    ) -> Result<(), DeltaTableError> {
+        for check in DataChecker::try_new(self)?.checks {
+            if refers_to_column(check.source_expression(), column) {
+                return Err(DeltaTableError::SchemaMismatch {
+                    msg: format!(
+                        "Column `{}` cannot be {}, {} refers to it",
+                        column,
+                        change,
+                        check.description()
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether the SQL expression refers to the top level column, i.e. holds its name as an
+/// identifier outside of string literals. Names are compared case-insensitively, and nested
+/// fields refer to their top level column.
+fn refers_to_column(expression: &str, column: &str) -> bool {
+    let mut chars = expression.chars().peekable();
+    let mut after_dot = false;
+    while let Some(c) = chars.next() {
+        let identifier = match c {
+            '\'' => {
+                // quotes within string literals are escaped by doubling them
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                        break;
+                    }
+                }
+                None
+            }
+            '`' | '"' => Some(chars.by_ref().take_while(|q| *q != c).collect::<String>()),
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut identifier = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    identifier.push(c);
+                }
+                Some(identifier)
+            }
+            _ => None,
+        };
+        match identifier {
+            Some(identifier) if !after_dot && identifier.eq_ignore_ascii_case(column) => {
+                return true;
+            }
+            _ => after_dot = c == '.',
+        }
+    }
+    false
+}
+
+impl Schema {
+    /// Returns the invariants recorded in the metadata of the fields of the schema, including
+    /// nested fields. Fails if the metadata of a field holds an invalid invariant.
+    pub fn get_invariants(&self) -> Result<Vec<Invariant>, DeltaTableError> {
+        let mut invariants = vec![];
+        collect_invariants("", self.get_fields(), &mut invariants)?;
+        Ok(invariants)
+    }
+}
+
+fn collect_invariants(
+    path: &str,
+    fields: &[SchemaField],
+    invariants: &mut Vec<Invariant>,
+) -> Result<(), DeltaTableError> {
+    for field in fields {
+        let field_name = format!("{}{}", path, field.get_name());
+        if let Some(invariant) = field.get_metadata().get(INVARIANTS) {
+            invariants.push(Invariant {
+                expression: invariant_expression(&field_name, invariant)?,
+                field_name: field_name.clone(),
+            });
+        }
+        if let SchemaDataType::r#struct(s) = field.get_type() {
+            collect_invariants(&format!("{}.", field_name), s.get_fields(), invariants)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the expression of an invariant, stored as `{"expression": {"expression": "<sql>"}}`,
+/// either as JSON object or as JSON encoded string.
+fn invariant_expression(field_name: &str, invariant: &Value) -> Result<String, DeltaTableError> {
+    let parsed;
+    let invariant = match invariant {
+        Value::String(s) => {
+            parsed = serde_json::from_str::<Value>(s).unwrap_or(Value::Null);
+            &parsed
+        }
+        v => v,
+    };
+    invariant
+        .get("expression")
+        .and_then(|e| e.get("expression"))
+        .and_then(|e| e.as_str())
+        .map(|e| e.to_string())
+        .ok_or_else(|| DeltaTableError::InvalidConstraint {
+            msg: format!("Invalid invariant of field `{}`: {}", field_name, invariant),
+        })
+}
+
+impl DeltaTable {
+    /// Adds a CHECK constraint to the table, which every row written to the table has to satisfy
+    /// from then on. The existing rows are validated first, the constraint is only added if all
+    /// of them satisfy it. The writer version of the table is upgraded to 3 if needed.
+    ///
+    /// Fails with `DeltaTableError::InvalidConstraint` if a constraint with the same name exists
+    /// or the expression cannot be evaluated, and with `DeltaTableError::ConstraintViolation` if
+    /// existing rows violate it.
+    pub async fn add_constraint(
+        &mut self,
+        name: &str,
+        expression: &str,
+    ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        self.update_incremental().await?;
+        let mut metadata = self.get_metadata()?.clone();
+        let key = format!("{}{}", CONSTRAINTS_PREFIX, name.to_lowercase());
+        if name.is_empty() {
+            return Err(DeltaTableError::InvalidConstraint {
+                msg: "Constraint names cannot be empty".to_string(),
+            }
+            .into());
+        }
+        if let Some(existing) = metadata.configuration.get(&key) {
+            return Err(DeltaTableError::InvalidConstraint {
+                msg: format!("Constraint `{}` already exists: {}", name, existing),
+            }
+            .into());
+        }
+
+        let checker = DataChecker {
+            checks: vec![Check::Constraint(Constraint {
+                name: name.to_lowercase(),
+                expression: expression.to_string(),
+            })],
+        };
+        // the expression is evaluated on an empty batch first, so that invalid expressions are
+        // rejected for tables without data as well
+        let schema =
+            Arc::new(ArrowSchema::try_from(&metadata.schema).map_err(DeltaTableError::from)?);
+        checker.check(&RecordBatch::new_empty(schema)).await?;
+        for add in self.get_active_add_actions().to_vec() {
+            for batch in self.read_data_file(&add).await? {
+                checker.check(&batch).await?;
+            }
+        }
+
+        metadata.configuration.insert(key, expression.to_string());
+        let protocol = action::Protocol {
+            min_reader_version: self.get_min_reader_version(),
+            min_writer_version: CONSTRAINTS_MIN_WRITER_VERSION,
+        };
+        let upgrade_protocol = self.get_min_writer_version() < CONSTRAINTS_MIN_WRITER_VERSION;
+
+        let mut dtx = self.create_transaction(None);
+        // data appended since the existing data was checked has not been checked
+        dtx.mark_read_whole_table();
+        if upgrade_protocol {
+            dtx.add_actions(vec![Action::protocol(protocol)]);
+        }
+        dtx.add_actions(vec![Action::metaData(action::MetaData::try_from(
+            metadata,
+        )?)]);
+        dtx.commit(Some(DeltaOperation::AddConstraint {
+            name: name.to_string(),
+            expr: expression.to_string(),
+        }))
+        .await
+    }
+
+    /// Drops a CHECK constraint of the table. Fails with `DeltaTableError::InvalidConstraint` if
+    /// the table has no constraint with the name, unless `if_exists` is set, in which case the
+    /// current version of the table is returned.
+    pub async fn drop_constraint(
+        &mut self,
+        name: &str,
+        if_exists: bool,
+    ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        self.update_incremental().await?;
+        let mut metadata = self.get_metadata()?.clone();
+        let key = format!("{}{}", CONSTRAINTS_PREFIX, name.to_lowercase());
+        let expression = match metadata.configuration.remove(&key) {
+            Some(expression) => expression,
+            None if if_exists => return Ok(self.version),
+            None => {
+                return Err(DeltaTableError::InvalidConstraint {
+                    msg: format!("Constraint `{}` does not exist", name),
+                }
+                .into())
+            }
+        };
+
+        let mut dtx = self.create_transaction(None);
+        dtx.add_actions(vec![Action::metaData(action::MetaData::try_from(
+            metadata,
+        )?)]);
+        dtx.commit(Some(DeltaOperation::DropConstraint {
+            name: name.to_string(),
+            expr: Some(expression),
+        }))
+        .await
+    }
+}
+
+/// A CHECK constraint, invariant or generated column the written rows are checked against.
+#[derive(Debug, Clone)]
+enum Check {
+    Constraint(Constraint),
+    Invariant(Invariant),
+    GeneratedColumn(GeneratedColumn),
+}
+
+impl Check {
+    fn expression(&self) -> String {
+        match self {
+            Check::Constraint(c) => c.expression.clone(),
+            Check::Invariant(i) => i.expression.clone(),
+            Check::GeneratedColumn(g) => g.validation_expression(),
+        }
+    }
+
+    /// Returns the expression as written in the table metadata.
+    fn source_expression(&self) -> &str {
+        match self {
+            Check::Constraint(c) => &c.expression,
+            Check::Invariant(i) => &i.expression,
+            Check::GeneratedColumn(g) => &g.expression,
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            Check::Constraint(c) => format!("CHECK constraint `{}` ({})", c.name, c.expression),
+            Check::Invariant(i) => format!("Invariant of `{}` ({})", i.field_name, i.expression),
+            Check::GeneratedColumn(g) => {
+                format!("Generated column `{}` ({})", g.name, g.expression)
+            }
+        }
+    }
+}
+
+/// Checks rows written to a table against the CHECK constraints and invariants of the table, and
+/// the values of its generated columns against their generation expressions.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DataChecker {
+    checks: Vec<Check>,
+}
+
+impl DataChecker {
+    /// Creates a checker for the constraints, invariants and generated columns of the table.
+    pub(crate) fn try_new(metadata: &DeltaTableMetaData) -> Result<Self, DeltaTableError> {
+        let mut checks: Vec<Check> = metadata
+            .get_constraints()
+            .into_iter()
+            .map(Check::Constraint)
+            .collect();
+        checks.extend(
+            metadata
+                .schema
+                .get_invariants()?
+                .into_iter()
+                .map(Check::Invariant),
+        );
+        checks.extend(
+            metadata
+                .schema
+                .get_generated_columns()?
+                .into_iter()
+                .map(Check::GeneratedColumn),
+        );
+        Ok(Self { checks })
+    }
+
+    /// Fails with `DeltaTableError::ConstraintViolation` if any row of the batch, which has the
+    /// columns of the table under their names, violates a constraint or invariant, or holds a
+    /// value of a generated column that differs from its generation expression.
+    pub(crate) async fn check(&self, batch: &RecordBatch) -> Result<(), DeltaTableError> {
+        if self.checks.is_empty() {
+            return Ok(());
+        }
+        self.check_batch(batch).await
+    }
+
+    #[cfg(feature = "datafusion-ext")]
+    async fn check_batch(&self, batch: &RecordBatch) -> Result<(), DeltaTableError> {
+        use arrow::array::{Array, UInt64Array};
+        use datafusion::datasource::MemTable;
+        use datafusion::execution::context::ExecutionContext;
+
+        let mut ctx = ExecutionContext::new();
+        let table = MemTable::try_new(batch.schema(), vec![vec![batch.clone()]])?;
+        ctx.register_table("data", Arc::new(table))?;
+
+        for check in &self.checks {
+            // rows violate the expression unless it evaluates to true
+            let sql = format!(
+                "SELECT COUNT(*) FROM data WHERE NOT ({0}) OR ({0}) IS NULL",
+                check.expression()
+            );
+            let batches = ctx
+                .sql(&sql)
+                .map_err(|e| DeltaTableError::InvalidConstraint {
+                    msg: format!("{} cannot be evaluated: {}", check.description(), e),
+                })?
+                .collect()
+                .await?;
+            let num_violations = batches
+                .get(0)
+                .and_then(|batch| batch.column(0).as_any().downcast_ref::<UInt64Array>())
+                .filter(|counts| !counts.is_empty())
+                .map_or(0, |counts| counts.value(0));
+            if num_violations > 0 {
+                return Err(DeltaTableError::ConstraintViolation {
+                    msg: format!(
+                        "{} is violated by {} rows",
+                        check.description(),
+                        num_violations
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "datafusion-ext"))]
+    async fn check_batch(&self, _batch: &RecordBatch) -> Result<(), DeltaTableError> {
+        Err(DeltaTableError::InvalidConstraint {
+            msg: format!(
+                "{} cannot be evaluated without the datafusion-ext feature",
+                self.checks[0].description()
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn field(name: &str, metadata: HashMap<String, Value>) -> SchemaField {
+        SchemaField::new(
+            name.to_string(),
+            SchemaDataType::primitive("integer".to_string()),
+            true,
+            metadata,
+        )
+    }
+
+    #[test]
+    fn invariants_are_read_from_field_metadata() {
+        let mut as_string = HashMap::new();
+        as_string.insert(
+            INVARIANTS.to_string(),
+            json!("{\"expression\":{\"expression\":\"value < 3\"}}"),
+        );
+        let mut as_object = HashMap::new();
+        as_object.insert(
+            INVARIANTS.to_string(),
+            json!({"expression": {"expression": "nested.count > 0"}}),
+        );
+        let nested = SchemaField::new(
+            "nested".to_string(),
+            SchemaDataType::r#struct(crate::schema::SchemaTypeStruct::new(vec![field(
+                "count", as_object,
+            )])),
+            true,
+            HashMap::new(),
+        );
+        let schema = Schema::new(vec![field("value", as_string), nested]);
+
+        assert_eq!(
+            schema.get_invariants().unwrap(),
+            vec![
+                Invariant {
+                    field_name: "value".to_string(),
+                    expression: "value < 3".to_string(),
+                },
+                Invariant {
+                    field_name: "nested.count".to_string(),
+                    expression: "nested.count > 0".to_string(),
+                },
+            ]
+        );
+
+        let mut invalid = HashMap::new();
+        invalid.insert(INVARIANTS.to_string(), json!({"expr": "value < 3"}));
+        assert!(matches!(
+            Schema::new(vec![field("value", invalid)]).get_invariants(),
+            Err(DeltaTableError::InvalidConstraint { .. })
+        ));
+    }
+
+    #[test]
+    fn column_references_are_found_in_expressions() {
+        assert!(refers_to_column("value > 0", "value"));
+        assert!(refers_to_column("`Value` > 0", "value"));
+        assert!(refers_to_column("nested.count > 0", "nested"));
+        assert!(refers_to_column("id = 'it''s' OR value IS NULL", "value"));
+        assert!(!refers_to_column("nested.value > 0", "value"));
+        assert!(!refers_to_column("id = 'value'", "value"));
+        assert!(!refers_to_column("id = 'it''s value'", "value"));
+        assert!(!refers_to_column("value_2 > 0", "value"));
+    }
+
+    #[test]
+    fn constraints_are_read_from_configuration() {
+        let mut configuration = HashMap::new();
+        configuration.insert(
+            "delta.constraints.positive".to_string(),
+            "value > 0".to_string(),
+        );
+        configuration.insert(
+            "delta.constraints.bounded".to_string(),
+            "value < 10".to_string(),
+        );
+        configuration.insert("delta.appendOnly".to_string(), "false".to_string());
+        let metadata =
+            DeltaTableMetaData::new(None, None, None, Schema::new(vec![]), vec![], configuration);
+
+        assert_eq!(
+            metadata.get_constraints(),
+            vec![
+                Constraint {
+                    name: "bounded".to_string(),
+                    expression: "value < 10".to_string(),
+                },
+                Constraint {
+                    name: "positive".to_string(),
+                    expression: "value > 0".to_string(),
+                },
+            ]
+        );
+    }
+}
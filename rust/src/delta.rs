@@ -3,11 +3,16 @@
 // Reference: https://github.com/delta-io/delta/blob/master/PROTOCOL.md
 //
 
+use arrow::array::{new_null_array, ArrayRef, StringArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Schema as ArrowSchema};
 use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
 use chrono::{DateTime, FixedOffset, Utc};
 use futures::StreamExt;
 use lazy_static::lazy_static;
 use log::*;
+use parquet::arrow::{ArrowReader, ParquetFileArrowReader};
 use parquet::errors::ParquetError;
 use parquet::file::{
     reader::{FileReader, SerializedFileReader},
@@ -16,10 +21,12 @@ use parquet::file::{
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::io::{BufRead, BufReader, Cursor};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{cmp::Ordering, collections::HashSet};
 use uuid::Uuid;
@@ -27,12 +34,19 @@ use uuid::Uuid;
 use crate::action::Stats;
 
 use super::action;
-use super::action::{Action, DeltaOperation};
-use super::partitions::{DeltaTablePartition, PartitionFilter};
+use super::action::{Action, DeltaOperation, IsolationLevel, SaveMode};
+use super::checkpoints::CheckPointWriter;
+use super::column_mapping;
+use super::partitions::{self, DeltaTablePartition, PartitionFilter};
 use super::schema::*;
+use super::schema_validation;
+use super::stats;
 use super::storage;
 use super::storage::{parse_uri, StorageBackend, StorageError, UriError};
 
+/// Number of rows per record batch when reading data files of the table.
+const DATA_FILE_BATCH_SIZE: usize = 8192;
+
 /// Metadata for a checkpoint file
 #[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
 pub struct CheckPoint {
@@ -145,6 +159,9 @@ pub enum DeltaTableError {
     /// Error returned when it is not a DeltaTable.
     #[error("Not a Delta table: {0}")]
     NotATable(String),
+    /// Error returned when creating a DeltaTable at a location that already contains one.
+    #[error("Delta table already exists at: {0}")]
+    TableAlreadyExists(String),
 
     /// Error returned when no metadata was found in the DeltaTable.
     #[error("No metadata found, please make sure table is loaded.")]
@@ -181,6 +198,46 @@ pub enum DeltaTableError {
         "Invalid retention period, retention for Vacuum must be greater than 1 week (168 hours)"
     )]
     InvalidVacuumRetentionPeriod,
+    /// Error returned when the column mapping of a table is invalid or does not support an
+    /// operation.
+    #[error("Column mapping error: {}", .msg)]
+    ColumnMapping {
+        /// Information about the column mapping error
+        msg: String,
+    },
+    /// Error returned when the columns given to a Z-order optimize cannot be used.
+    #[error("Invalid Z-order columns: {}", .msg)]
+    InvalidZOrder {
+        /// Information about the invalid columns
+        msg: String,
+    },
+    /// Error returned when a CHECK constraint or invariant cannot be added or evaluated.
+    #[error("Invalid constraint: {}", .msg)]
+    InvalidConstraint {
+        /// Information about the invalid constraint
+        msg: String,
+    },
+    /// Error returned when written or existing rows violate a CHECK constraint or invariant of
+    /// the table.
+    #[error("Constraint violated: {}", .msg)]
+    ConstraintViolation {
+        /// Information about the violated constraint
+        msg: String,
+    },
+    /// Error returned when a generated column is invalid or its values cannot be computed.
+    #[error("Generated column error: {}", .msg)]
+    GeneratedColumn {
+        /// Information about the generated column error
+        msg: String,
+    },
+    /// Error returned when planning or evaluating a DataFusion expression failed.
+    #[cfg(feature = "datafusion-ext")]
+    #[error("DataFusion error: {}", .source)]
+    DataFusion {
+        /// DataFusion error details returned when evaluating the expression failed.
+        #[from]
+        source: datafusion::error::DataFusionError,
+    },
     /// Generic Delta Table error
     #[error("Generic DeltaTable error: {0}")]
     Generic(String),
@@ -207,6 +264,32 @@ pub struct DeltaTableMetaData {
     pub configuration: HashMap<String, String>,
 }
 
+impl DeltaTableMetaData {
+    /// Creates the metadata of a new table with a random id and the current time as creation
+    /// time. Files are encoded as parquet unless another `format` is given.
+    pub fn new(
+        name: Option<String>,
+        description: Option<String>,
+        format: Option<action::Format>,
+        schema: Schema,
+        partition_columns: Vec<String>,
+        configuration: HashMap<String, String>,
+    ) -> Self {
+        // Err should be impossible in this case since `SystemTime::now()` is always greater than `UNIX_EPOCH`
+        let created_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name,
+            description,
+            format: format.unwrap_or_else(|| action::Format::new("parquet".to_string(), None)),
+            schema,
+            partition_columns,
+            created_time: created_time.as_millis() as DeltaDataTypeTimestamp,
+            configuration,
+        }
+    }
+}
+
 impl fmt::Display for DeltaTableMetaData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -327,7 +410,7 @@ pub struct DeltaTableState {
     // A tombstone expires when the creation timestamp of the delta file exceeds the expiration
     tombstones: Vec<action::Remove>,
     files: Vec<action::Add>,
-    commit_infos: Vec<Value>,
+    commit_infos: Vec<action::CommitInfo>,
     app_transaction_version: HashMap<String, DeltaDataTypeVersion>,
     min_reader_version: i32,
     min_writer_version: i32,
@@ -521,6 +604,33 @@ impl DeltaTable {
         self.apply_log_from_bufread(reader)
     }
 
+    /// Reads the actions committed at the given version without applying them to the table
+    /// state. Returns `DeltaTableError::InvalidVersion` if the version does not exist.
+    pub async fn get_commit_actions(
+        &self,
+        version: DeltaDataTypeVersion,
+    ) -> Result<Vec<Action>, DeltaTableError> {
+        let commit_uri = self.commit_uri_from_version(version);
+        let commit_log_bytes = match self.storage.get_obj(&commit_uri).await {
+            Ok(bytes) => bytes,
+            Err(StorageError::NotFound) => {
+                return Err(DeltaTableError::InvalidVersion(version));
+            }
+            Err(e) => {
+                return Err(DeltaTableError::from(e));
+            }
+        };
+        let reader = BufReader::new(Cursor::new(commit_log_bytes));
+
+        let mut actions = Vec::new();
+        for line in reader.lines() {
+            let line = line.map_err(ApplyLogError::from)?;
+            actions.push(serde_json::from_str(line.as_str())?);
+        }
+
+        Ok(actions)
+    }
+
     async fn restore_checkpoint(&mut self, check_point: CheckPoint) -> Result<(), DeltaTableError> {
         let checkpoint_data_paths = self.get_checkpoint_data_paths(&check_point);
         // process actions from checkpoint
@@ -728,6 +838,51 @@ impl DeltaTable {
         }
     }
 
+    /// Returns the versions of the table with their commit info, newest first, starting from the
+    /// currently loaded version. At most `limit` entries are returned if a limit is given, and the
+    /// history stops at the oldest version whose log entry is still present.
+    ///
+    /// Only the commit files are read, no table state is replayed. Versions written without a
+    /// `commitInfo` action are reported with the modification time of their log entry.
+    pub async fn history(
+        &self,
+        limit: Option<usize>,
+    ) -> Result<Vec<(DeltaDataTypeVersion, action::CommitInfo)>, DeltaTableError> {
+        let mut history = Vec::new();
+        let mut version = self.version;
+        while version >= 0 && limit.map_or(true, |limit| history.len() < limit) {
+            let actions = match self.get_commit_actions(version).await {
+                Ok(actions) => actions,
+                // older log entries may have been cleaned up
+                Err(DeltaTableError::InvalidVersion(_)) => break,
+                Err(e) => return Err(e),
+            };
+
+            let commit_info = actions.into_iter().find_map(|action| match action {
+                Action::commitInfo(commit_info) => Some(commit_info),
+                _ => None,
+            });
+            let commit_info = match commit_info {
+                Some(commit_info) => commit_info,
+                None => {
+                    let meta = self
+                        .storage
+                        .head_obj(&self.commit_uri_from_version(version))
+                        .await?;
+                    action::CommitInfo {
+                        timestamp: Some(meta.modified.timestamp_millis()),
+                        ..Default::default()
+                    }
+                }
+            };
+            history.push((version, commit_info));
+
+            version -= 1;
+        }
+
+        Ok(history)
+    }
+
     /// Returns the file list tracked in current table state filtered by provided
     /// `PartitionFilter`s.
     pub fn get_files_by_partitions(
@@ -785,7 +940,10 @@ impl DeltaTable {
         let files = self.get_files_by_partitions(filters)?;
         Ok(files
             .iter()
-            .map(|fname| self.storage.join_path(&self.table_uri, fname))
+            .map(|fname| {
+                self.storage
+                    .join_path(&self.table_uri, &action::decode_path(fname))
+            })
             .collect())
     }
 
@@ -829,7 +987,10 @@ impl DeltaTable {
         self.state
             .files
             .iter()
-            .map(|add| self.storage.join_path(&self.table_uri, &add.path))
+            .map(|add| {
+                self.storage
+                    .join_path(&self.table_uri, &action::decode_path(&add.path))
+            })
             .collect()
     }
 
@@ -922,8 +1083,14 @@ impl DeltaTable {
         retention_hours: u64,
         dry_run: bool,
     ) -> Result<Vec<String>, DeltaTableError> {
-        let expired_tombstones = self.get_stale_files(retention_hours)?;
-        let valid_files = self.get_file_set();
+        // the paths of the actions are URI-encoded, unlike the paths of the listed objects
+        let expired_tombstones: HashSet<Cow<str>> = self
+            .get_stale_files(retention_hours)?
+            .into_iter()
+            .map(action::decode_path)
+            .collect();
+        let valid_files: HashSet<Cow<str>> =
+            self.get_files_iter().map(action::decode_path).collect();
 
         let mut files_to_delete = vec![];
         let mut all_files = self.storage.list_objs(&self.table_uri).await?;
@@ -968,6 +1135,106 @@ impl DeltaTable {
         Ok(files_to_delete)
     }
 
+    /// Deletes the data of the partitions matching all of the given filters, or of the whole
+    /// table if no filters are given, as of the latest version of the table.
+    ///
+    /// Since whole files are deleted, a remove action is committed for every matching file
+    /// without reading or rewriting any data. Returns the version of the commit, or the current
+    /// version if no file matched. Rows can be deleted by arbitrary predicates with
+    /// `DeltaTable::delete` if the `datafusion-ext` feature is enabled.
+    pub async fn delete_partitions(
+        &mut self,
+        filters: &[PartitionFilter<'_, &str>],
+    ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        self.update_incremental().await?;
+
+        let mut dtx = self.create_transaction(None);
+        let removed = dtx.remove_partitions(filters)?;
+        if removed.is_empty() {
+            return Ok(dtx.read_version());
+        }
+
+        let num_deleted_rows: Option<DeltaDataTypeLong> = removed
+            .iter()
+            .map(|add| add.get_stats().ok().flatten().map(|s| s.num_records))
+            .sum();
+        if let Some(num_deleted_rows) = num_deleted_rows {
+            dtx.record_operation_metric("numDeletedRows", num_deleted_rows);
+        }
+        dtx.record_operation_metric("numAddedFiles", 0);
+        dtx.record_operation_metric("numCopiedRows", 0);
+
+        dtx.commit(Some(DeltaOperation::Delete {
+            predicate: partitions::filters_to_predicate(filters),
+        }))
+        .await
+    }
+
+    /// Reads the data file of the given add action into record batches with the schema of the
+    /// table. Partition columns are filled in from the partition values of the file, where an
+    /// empty value stands for null, and columns missing from the file are read as nulls. Columns
+    /// and nested fields are looked up by their physical names or field ids if the table uses
+    /// column mapping.
+    pub(crate) async fn read_data_file(
+        &self,
+        add: &action::Add,
+    ) -> Result<Vec<RecordBatch>, DeltaTableError> {
+        let metadata = self.get_metadata()?;
+        let mode = metadata.column_mapping_mode()?;
+        let schema = ArrowSchema::try_from(&metadata.schema)?;
+
+        let uri = self
+            .storage
+            .join_path(&self.table_uri, &action::decode_path(&add.path));
+        let data = self.storage.get_obj(&uri).await?;
+        let file_reader = SerializedFileReader::new(SliceableCursor::new(data))?;
+        // columns of the data file, in order of the table schema
+        let file_columns = column_mapping::data_file_columns(
+            metadata,
+            mode,
+            file_reader.metadata().file_metadata().schema_descr(),
+        );
+        let mut arrow_reader = ParquetFileArrowReader::new(Arc::new(file_reader));
+        let batches = arrow_reader
+            .get_record_reader(DATA_FILE_BATCH_SIZE)?
+            .collect::<Result<Vec<RecordBatch>, ArrowError>>()?;
+
+        let partition_values = metadata.logical_partition_values(&add.partition_values);
+
+        let schema = Arc::new(schema);
+        batches
+            .iter()
+            .map(|batch| {
+                let num_rows = batch.num_rows();
+                let columns = schema
+                    .fields()
+                    .iter()
+                    .zip(file_columns.iter())
+                    .map(|(field, file_column)| {
+                        if metadata.partition_columns.contains(field.name()) {
+                            return Ok(partition_value_array(
+                                partition_values.get(field.name()),
+                                field.data_type(),
+                                num_rows,
+                            )?);
+                        }
+                        // columns are missing from files written before they were added, and
+                        // have narrower types in files written before they were widened
+                        match batch.schema().index_of(&file_column.name) {
+                            Ok(i) => Ok(column_mapping::logical_array(
+                                batch.column(i),
+                                file_column,
+                                field.data_type(),
+                            )?),
+                            Err(_) => Ok(new_null_array(field.data_type(), num_rows)),
+                        }
+                    })
+                    .collect::<Result<Vec<ArrayRef>, DeltaTableError>>()?;
+                Ok(RecordBatch::try_new(schema.clone(), columns)?)
+            })
+            .collect()
+    }
+
     /// Return table schema parsed from transaction log. Return None if table hasn't been loaded or
     /// no metadata was found in the log.
     pub fn schema(&self) -> Option<&Schema> {
@@ -980,6 +1247,22 @@ impl DeltaTable {
         self.schema().ok_or(DeltaTableError::NoSchema)
     }
 
+    /// Checks the loaded state of the table against the save mode of a write. Returns `false` if
+    /// the write must not proceed, which is the case for `SaveMode::Ignore` when the table
+    /// already has data files. `SaveMode::ErrorIfExists` fails with
+    /// `DeltaTableError::TableAlreadyExists` if the table exists, i.e. has metadata, even if it
+    /// has no data files yet.
+    pub fn check_save_mode(&self, mode: SaveMode) -> Result<bool, DeltaTableError> {
+        let has_files = !self.get_active_add_actions().is_empty();
+        match mode {
+            SaveMode::ErrorIfExists if self.get_metadata().is_ok() => {
+                Err(DeltaTableError::TableAlreadyExists(self.table_uri.clone()))
+            }
+            SaveMode::Ignore => Ok(!has_files),
+            _ => Ok(true),
+        }
+    }
+
     /// Creates a new DeltaTransaction for the DeltaTable.
     /// The transaction holds a mutable reference to the DeltaTable, preventing other references
     /// until the transaction is dropped.
@@ -990,6 +1273,61 @@ impl DeltaTable {
         DeltaTransaction::new(self, options)
     }
 
+    /// Initializes a new Delta table at the table's location by committing version 0 with the
+    /// given `protocol` and `metadata`, then loads it.
+    ///
+    /// Returns `DeltaTableError::TableAlreadyExists` if the location already contains a Delta
+    /// table, including when a concurrent writer creates it first, and
+    /// `DeltaTableError::SchemaMismatch` if a partition column is missing from the schema.
+    pub async fn create(
+        &mut self,
+        metadata: DeltaTableMetaData,
+        protocol: action::Protocol,
+    ) -> Result<(), DeltaTransactionError> {
+        if let Some(column) = metadata
+            .partition_columns
+            .iter()
+            .find(|c| metadata.schema.get_field_with_name(c).is_none())
+        {
+            return Err(DeltaTableError::SchemaMismatch {
+                msg: format!("Partition column `{}` is not in the table schema", column),
+            }
+            .into());
+        }
+
+        if self.get_latest_version().await? >= 0 {
+            return Err(DeltaTableError::TableAlreadyExists(self.table_uri.clone()).into());
+        }
+
+        let operation = DeltaOperation::Create {
+            location: self.table_uri.clone(),
+            description: metadata.description.clone(),
+            partitionBy: if metadata.partition_columns.is_empty() {
+                None
+            } else {
+                Some(metadata.partition_columns.clone())
+            },
+            properties: metadata.configuration.clone(),
+        };
+
+        let mut transaction = self.create_transaction(None);
+        transaction.add_actions(vec![
+            Action::protocol(protocol),
+            Action::metaData(action::MetaData::try_from(metadata)?),
+        ]);
+        let prepared_commit = transaction.prepare_commit(Some(operation)).await?;
+
+        match self.try_commit_transaction(&prepared_commit, 0).await {
+            Ok(_) => Ok(()),
+            Err(DeltaTransactionError::VersionAlreadyExists { .. }) => {
+                // another writer created the table first, the prepared commit is left unused
+                self.storage.delete_obj(&prepared_commit.uri).await?;
+                Err(DeltaTableError::TableAlreadyExists(self.table_uri.clone()).into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Tries to commit a prepared commit file. Returns `DeltaTransactionError::VersionAlreadyExists`
     /// if the given `version` already exists. The caller should handle the retry logic itself.
     /// This is low-level transaction API. If user does not want to maintain the commit loop then
@@ -1142,6 +1480,64 @@ pub enum DeltaTransactionError {
         #[from]
         source: serde_json::Error,
     },
+
+    /// Error that indicates a commit written by a concurrent writer since the transaction's read
+    /// version conflicts with the transaction. The transaction must be retried against the new
+    /// table state.
+    #[error("Transaction conflicts with a concurrent commit: {source}")]
+    CommitConflict {
+        /// The wrapped CommitConflictError.
+        #[from]
+        source: CommitConflictError,
+    },
+}
+
+/// Describes how a commit written by a concurrent writer conflicts with a transaction.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum CommitConflictError {
+    /// The concurrent commit added files that the transaction would have read.
+    #[error("Files were added to the table by a concurrent commit at version {version}")]
+    ConcurrentAppend {
+        /// The version of the conflicting commit.
+        version: DeltaDataTypeVersion,
+    },
+    /// The concurrent commit removed a file that the transaction read.
+    #[error("File {path} read by the transaction was removed at version {version}")]
+    ConcurrentDeleteRead {
+        /// The version of the conflicting commit.
+        version: DeltaDataTypeVersion,
+        /// The path of the removed file.
+        path: String,
+    },
+    /// The concurrent commit removed a file that the transaction removes as well.
+    #[error("File {path} removed by the transaction was already removed at version {version}")]
+    ConcurrentDeleteDelete {
+        /// The version of the conflicting commit.
+        version: DeltaDataTypeVersion,
+        /// The path of the removed file.
+        path: String,
+    },
+    /// The concurrent commit changed the table metadata.
+    #[error("Table metadata was changed by a concurrent commit at version {version}")]
+    MetadataChanged {
+        /// The version of the conflicting commit.
+        version: DeltaDataTypeVersion,
+    },
+    /// The concurrent commit changed the table protocol.
+    #[error("Table protocol was changed by a concurrent commit at version {version}")]
+    ProtocolChanged {
+        /// The version of the conflicting commit.
+        version: DeltaDataTypeVersion,
+    },
+    /// The concurrent commit wrote a txn action for an application id the transaction writes as
+    /// well.
+    #[error("Application {app_id} committed a transaction concurrently at version {version}")]
+    ConcurrentTransaction {
+        /// The version of the conflicting commit.
+        version: DeltaDataTypeVersion,
+        /// The application id of the conflicting txn action.
+        app_id: String,
+    },
 }
 
 impl From<StorageError> for DeltaTransactionError {
@@ -1157,11 +1553,19 @@ impl From<StorageError> for DeltaTransactionError {
 
 const DEFAULT_DELTA_MAX_RETRY_COMMIT_ATTEMPTS: u32 = 10_000_000;
 
+/// Number of commits between checkpoints, unless configured by the `delta.checkpointInterval`
+/// table property.
+const DEFAULT_CHECKPOINT_INTERVAL: DeltaDataTypeVersion = 10;
+
 /// Options for customizing behavior of a `DeltaTransaction`
 #[derive(Debug)]
 pub struct DeltaTransactionOptions {
     /// number of retry attempts allowed when committing a transaction
     max_retry_commit_attempts: u32,
+    /// isolation level used to check for conflicts, overrides the level derived from the table
+    isolation_level: Option<IsolationLevel>,
+    /// whether a checkpoint is written after commits at multiples of the checkpoint interval
+    create_checkpoint: bool,
 }
 
 impl DeltaTransactionOptions {
@@ -1169,14 +1573,35 @@ impl DeltaTransactionOptions {
     pub fn new(max_retry_commit_attempts: u32) -> Self {
         Self {
             max_retry_commit_attempts,
+            isolation_level: None,
+            create_checkpoint: true,
         }
     }
+
+    /// Sets the isolation level used to check the transaction for conflicts with concurrent
+    /// commits. When not set, transactions that do not change data use
+    /// `IsolationLevel::SnapshotIsolation` and all others use the `delta.isolationLevel` table
+    /// property, defaulting to `IsolationLevel::WriteSerializable`.
+    pub fn with_isolation_level(mut self, isolation_level: IsolationLevel) -> Self {
+        self.isolation_level = Some(isolation_level);
+        self
+    }
+
+    /// Sets whether the transaction writes a checkpoint of the table when it commits a version
+    /// that is a multiple of the `delta.checkpointInterval` table property, which defaults to 10.
+    /// Enabled by default.
+    pub fn with_create_checkpoint(mut self, create_checkpoint: bool) -> Self {
+        self.create_checkpoint = create_checkpoint;
+        self
+    }
 }
 
 impl Default for DeltaTransactionOptions {
     fn default() -> Self {
         Self {
             max_retry_commit_attempts: DEFAULT_DELTA_MAX_RETRY_COMMIT_ATTEMPTS,
+            isolation_level: None,
+            create_checkpoint: true,
         }
     }
 }
@@ -1192,11 +1617,25 @@ impl Default for DeltaTransactionOptions {
 ///
 /// Please not that in case of non-retryable error the temporary commit file such as
 /// `_delta_log/_commit_<uuid>.json` will orphaned in storage.
+///
+/// When another writer commits first, `commit` reads every commit written since the table
+/// version the transaction was created at and fails with
+/// `DeltaTransactionError::CommitConflict` if any of them conflicts with the transaction under
+/// its isolation level. Files removed by the transaction conflict with concurrent removes of the
+/// same files; other reads should be registered with `add_read_files` or `mark_read_whole_table`.
 #[derive(Debug)]
 pub struct DeltaTransaction<'a> {
     delta_table: &'a mut DeltaTable,
     actions: Vec<Action>,
     options: DeltaTransactionOptions,
+    read_version: DeltaDataTypeVersion,
+    read_files: HashSet<String>,
+    read_whole_table: bool,
+    /// Set once the save mode of a write required the table to have no data, in which case any
+    /// concurrent append conflicts with the transaction
+    requires_empty_table: bool,
+    operation_metrics: HashMap<String, DeltaDataTypeLong>,
+    user_metadata: Option<String>,
 }
 
 impl<'a> DeltaTransaction<'a> {
@@ -1204,13 +1643,52 @@ impl<'a> DeltaTransaction<'a> {
     /// Holds a mutable reference to the delta table to prevent outside mutation while a transaction commit is in progress.
     /// Transaction behavior may be customized by passing an instance of `DeltaTransactionOptions`.
     pub fn new(delta_table: &'a mut DeltaTable, options: Option<DeltaTransactionOptions>) -> Self {
+        let read_version = delta_table.version;
         DeltaTransaction {
             delta_table,
             actions: vec![],
             options: options.unwrap_or_else(DeltaTransactionOptions::default),
+            read_version,
+            read_files: HashSet::new(),
+            read_whole_table: false,
+            requires_empty_table: false,
+            operation_metrics: HashMap::new(),
+            user_metadata: None,
         }
     }
 
+    /// The table version this transaction reads from. Commits after this version are checked
+    /// for conflicts.
+    pub fn read_version(&self) -> DeltaDataTypeVersion {
+        self.read_version
+    }
+
+    /// Registers files (relative paths from the table root) whose content this transaction
+    /// depends on. A concurrent commit removing any of them conflicts with the transaction.
+    pub fn add_read_files(&mut self, paths: Vec<String>) {
+        self.read_files.extend(paths);
+    }
+
+    /// Registers that this transaction depends on the content of the whole table, for example
+    /// because it scanned it with a predicate. Any concurrent data change conflicts with the
+    /// transaction, except blind appends under `IsolationLevel::WriteSerializable`.
+    pub fn mark_read_whole_table(&mut self) {
+        self.read_whole_table = true;
+    }
+
+    /// Records an operation metric in the `CommitInfo` action written with this transaction.
+    /// Overrides the metrics derived from the transaction actions (`numFiles`,
+    /// `numOutputBytes`, `numOutputRows` and `numRemovedFiles`) if the names match.
+    pub fn record_operation_metric(&mut self, name: &str, value: DeltaDataTypeLong) {
+        self.operation_metrics.insert(name.to_string(), value);
+    }
+
+    /// Sets user-defined metadata recorded as `userMetadata` in the `CommitInfo` action written
+    /// with this transaction.
+    pub fn set_user_metadata(&mut self, user_metadata: String) {
+        self.user_metadata = Some(user_metadata);
+    }
+
     /// Add an arbitrary "action" to the actions associated with this transaction
     pub fn add_action(&mut self, action: action::Action) {
         self.actions.push(action);
@@ -1223,9 +1701,104 @@ impl<'a> DeltaTransaction<'a> {
         }
     }
 
+    /// Checks the table state this transaction reads against the save mode of a write, see
+    /// `DeltaTable::check_save_mode`. If the write proceeds under `SaveMode::ErrorIfExists` or
+    /// `SaveMode::Ignore`, data appended by a concurrent commit conflicts with the transaction.
+    pub fn check_save_mode(&mut self, mode: SaveMode) -> Result<bool, DeltaTransactionError> {
+        let proceed = self.delta_table.check_save_mode(mode)?;
+        if proceed && matches!(mode, SaveMode::ErrorIfExists | SaveMode::Ignore) {
+            self.requires_empty_table = true;
+        }
+        Ok(proceed)
+    }
+
+    /// Replaces the data of the partitions matching all of the given filters, or the whole table
+    /// if no filters are given, with the files added by this transaction. A remove action is
+    /// added for every matching file of the table, like Spark's `replaceWhere` option does.
+    ///
+    /// This has to be called after the new files were added to the transaction, since every added
+    /// file has to match the filters as well.
+    pub fn overwrite(
+        &mut self,
+        filters: &[PartitionFilter<&str>],
+    ) -> Result<(), DeltaTransactionError> {
+        self.validate_partition_filters(filters)?;
+
+        let metadata = self.delta_table.get_metadata()?;
+        for action in &self.actions {
+            if let Action::add(add) = action {
+                if !matches_partition_filters(metadata, &add.partition_values, filters) {
+                    return Err(DeltaTableError::Generic(format!(
+                        "File {} does not match the overwrite predicate {}",
+                        add.path,
+                        filters
+                            .iter()
+                            .map(|f| f.to_string())
+                            .collect::<Vec<String>>()
+                            .join(" AND ")
+                    ))
+                    .into());
+                }
+            }
+        }
+
+        self.remove_partitions(filters)?;
+
+        Ok(())
+    }
+
+    /// Adds a remove action for every file of the table whose partition values match all of the
+    /// given filters, and returns the add actions of the removed files.
+    fn remove_partitions(
+        &mut self,
+        filters: &[PartitionFilter<&str>],
+    ) -> Result<Vec<action::Add>, DeltaTransactionError> {
+        self.validate_partition_filters(filters)?;
+
+        // Err should be impossible in this case since `SystemTime::now()` is always greater than `UNIX_EPOCH`
+        let deletion_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let deletion_timestamp = deletion_timestamp.as_millis() as DeltaDataTypeTimestamp;
+        let metadata = self.delta_table.get_metadata()?;
+        let removed: Vec<action::Add> = self
+            .delta_table
+            .get_active_add_actions()
+            .iter()
+            .filter(|add| matches_partition_filters(metadata, &add.partition_values, filters))
+            .cloned()
+            .collect();
+
+        self.add_read_files(removed.iter().map(|add| add.path.clone()).collect());
+        self.add_actions(
+            removed
+                .iter()
+                .map(|add| Action::remove(add.to_remove(deletion_timestamp, true)))
+                .collect(),
+        );
+
+        Ok(removed)
+    }
+
+    fn validate_partition_filters(
+        &self,
+        filters: &[PartitionFilter<&str>],
+    ) -> Result<(), DeltaTransactionError> {
+        let partition_columns = &self.delta_table.get_metadata()?.partition_columns;
+        validate_partition_filters(partition_columns, filters)?;
+        Ok(())
+    }
+
     /// Create a new add action and write the given bytes to the storage backend as a fully formed
     /// Parquet file
     ///
+    /// The file is validated against its Parquet footer before it is written: every column of
+    /// the table has to be stored with a type its values can be cast to losslessly, and without
+    /// null values in non-nullable fields according to the null counts of the footer. There has
+    /// to be a partition value for every partition column, and only for those.
+    ///
+    /// The statistics of the file are read from the same footer and stored in the add action,
+    /// for as many leading columns as the `delta.dataSkippingNumIndexedCols` table property
+    /// configures (32 by default).
+    ///
     /// add_file accepts two optional parameters:
     ///
     /// partitions: an ordered vec of WritablePartitionValues for the file to be added
@@ -1235,6 +1808,29 @@ impl<'a> DeltaTransaction<'a> {
         &mut self,
         bytes: &[u8],
         partitions: Option<Vec<(String, String)>>,
+    ) -> Result<(), DeltaTransactionError> {
+        let footer = stats::parquet_footer(bytes)
+            .map_err(|source| DeltaTableError::ParquetError { source })?;
+        let num_indexed_cols = match &self.delta_table.state.current_metadata {
+            Some(metadata) => {
+                schema_validation::validate_data_file(metadata, &footer, partitions.as_ref())?;
+                stats::num_indexed_cols(&metadata.configuration)?
+            }
+            None => Some(stats::DEFAULT_NUM_INDEXED_COLS),
+        };
+        let stats = stats::stats_from_footer(&footer, num_indexed_cols)
+            .map_err(|source| DeltaTableError::ParquetError { source })?;
+
+        self.add_file_with_stats(bytes, partitions, Some(stats))
+            .await
+    }
+
+    /// Same as `add_file`, with statistics the caller already collected for the file.
+    pub(crate) async fn add_file_with_stats(
+        &mut self,
+        bytes: &[u8],
+        partitions: Option<Vec<(String, String)>>,
+        stats: Option<Stats>,
     ) -> Result<(), DeltaTransactionError> {
         let mut partition_values = HashMap::new();
         if let Some(partitions) = &partitions {
@@ -1262,13 +1858,13 @@ impl<'a> DeltaTransaction<'a> {
         let modification_time = modification_time.as_millis() as i64;
 
         self.actions.push(Action::add(action::Add {
-            path,
+            path: action::encode_path(&path),
             partition_values,
             modification_time,
             size: bytes.len() as i64,
             partition_values_parsed: None,
             data_change: true,
-            stats: None,
+            stats: stats.map(|s| serde_json::to_string(&s)).transpose()?,
             stats_parsed: None,
             tags: None,
         }));
@@ -1299,48 +1895,334 @@ impl<'a> DeltaTransaction<'a> {
 
     /// Commits the given actions to the delta log.
     /// This method will retry the transaction commit based on the value of `max_retry_commit_attempts` set in `DeltaTransactionOptions`.
+    /// Before every attempt, commits written by other writers since the read version are checked
+    /// for conflicts with this transaction.
+    ///
+    /// After committing a version that is a multiple of the `delta.checkpointInterval` table
+    /// property, 10 by default, a checkpoint is written unless disabled in the
+    /// `DeltaTransactionOptions`.
     pub async fn commit(
         &mut self,
-        _operation: Option<DeltaOperation>,
+        operation: Option<DeltaOperation>,
     ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
-        // TODO: stubbing `operation` parameter (which will be necessary for writing the CommitInfo action), but leaving it unused for now.
-        // `CommitInfo` is a fairly dynamic data structure so we should work out the data structure approach separately.
-
-        // TODO: calculate isolation level to use when checking for conflicts.
-        // Leaving conflict checking unimplemented for now to get the "single writer" implementation off the ground.
-        // Leaving some commmented code in place as a guidepost for the future.
-
-        // let no_data_changed = actions.iter().all(|a| match a {
-        //     Action::add(x) => !x.dataChange,
-        //     Action::remove(x) => !x.dataChange,
-        //     _ => false,
-        // });
-        // let isolation_level = if no_data_changed {
-        //     IsolationLevel::SnapshotIsolation
-        // } else {
-        //     IsolationLevel::Serializable
-        // };
-
-        let prepared_commit = self.prepare_commit(_operation).await?;
+        let isolation_level = self.isolation_level()?;
+        let checkpoint_interval = self.checkpoint_interval()?;
+
+        let prepared_commit = self.prepare_commit(operation).await?;
 
         // try to commit in a loop in case other writers write the next version first
-        let version = self.try_commit_loop(&prepared_commit).await?;
+        let version = self
+            .try_commit_loop(&prepared_commit, isolation_level)
+            .await?;
+
+        if self.options.create_checkpoint && version > 0 && version % checkpoint_interval == 0 {
+            self.create_checkpoint(version).await;
+        }
 
         Ok(version)
     }
 
+    /// Returns the number of commits between checkpoints, configured by the
+    /// `delta.checkpointInterval` table property.
+    fn checkpoint_interval(&self) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        let configured = self
+            .delta_table
+            .state
+            .current_metadata
+            .as_ref()
+            .and_then(|m| m.configuration.get("delta.checkpointInterval"));
+
+        match configured {
+            None => Ok(DEFAULT_CHECKPOINT_INTERVAL),
+            Some(interval) => match interval.parse::<DeltaDataTypeVersion>() {
+                Ok(interval) if interval > 0 => Ok(interval),
+                _ => Err(DeltaTransactionError::from(DeltaTableError::Generic(
+                    format!(
+                        "Invalid delta.checkpointInterval table property: {}",
+                        interval
+                    ),
+                ))),
+            },
+        }
+    }
+
+    /// Writes a checkpoint of the committed version from the in-memory state of the table and
+    /// deletes the expired log files it replaces, unless another writer committed a later
+    /// version in the meantime. Since the commit itself succeeded, failing to write the
+    /// checkpoint or to clean up the log is logged instead of returned.
+    async fn create_checkpoint(&self, version: DeltaDataTypeVersion) {
+        if self.delta_table.version != version {
+            debug!(
+                "Skipping checkpoint at version {}, the table is at version {} already.",
+                version, self.delta_table.version
+            );
+            return;
+        }
+
+        let writer = match CheckPointWriter::new_for_table_uri(&self.delta_table.table_uri) {
+            Ok(writer) => writer,
+            Err(e) => {
+                error!("Failed to write checkpoint at version {}: {}", version, e);
+                return;
+            }
+        };
+        if let Err(e) = writer
+            .create_checkpoint_from_state(version, &self.delta_table.state)
+            .await
+        {
+            error!("Failed to write checkpoint at version {}: {}", version, e);
+            return;
+        }
+        if let Err(e) = writer
+            .cleanup_expired_logs_from_state(version, &self.delta_table.state)
+            .await
+        {
+            error!(
+                "Failed to clean up expired logs before checkpoint {}: {}",
+                version, e
+            );
+        }
+    }
+
+    /// Returns the isolation level used to check this transaction for conflicts.
+    pub fn isolation_level(&self) -> Result<IsolationLevel, DeltaTransactionError> {
+        if let Some(isolation_level) = self.options.isolation_level {
+            return Ok(isolation_level);
+        }
+
+        let no_data_changed = self.actions.iter().all(|a| match a {
+            Action::add(x) => !x.data_change,
+            Action::remove(x) => !x.data_change,
+            _ => false,
+        });
+        if no_data_changed {
+            return Ok(IsolationLevel::SnapshotIsolation);
+        }
+
+        let configured = self
+            .delta_table
+            .state
+            .current_metadata
+            .as_ref()
+            .and_then(|m| m.configuration.get("delta.isolationLevel"));
+
+        match configured.map(|s| s.as_str()) {
+            None => Ok(IsolationLevel::default()),
+            Some("Serializable") => Ok(IsolationLevel::Serializable),
+            Some("WriteSerializable") => Ok(IsolationLevel::WriteSerializable),
+            Some("SnapshotIsolation") => Ok(IsolationLevel::SnapshotIsolation),
+            Some(other) => Err(DeltaTransactionError::from(DeltaTableError::Generic(
+                format!("Invalid delta.isolationLevel table property: {}", other),
+            ))),
+        }
+    }
+
+    /// Checks the actions of a commit written by another writer at `version` for conflicts with
+    /// this transaction, following the rules of the Delta protocol reference implementation.
+    fn check_for_conflicts(
+        &self,
+        version: DeltaDataTypeVersion,
+        winning_actions: &[Action],
+        isolation_level: IsolationLevel,
+    ) -> Result<(), CommitConflictError> {
+        let mut removed_paths = HashSet::new();
+        let mut written_app_ids = HashSet::new();
+        let mut metadata_changed = false;
+        for action in &self.actions {
+            match action {
+                Action::remove(r) => {
+                    removed_paths.insert(r.path.as_str());
+                }
+                Action::txn(t) => {
+                    written_app_ids.insert(t.app_id.as_str());
+                }
+                Action::metaData(_) => {
+                    metadata_changed = true;
+                }
+                _ => {}
+            }
+        }
+
+        let winning_is_blind_append = winning_actions.iter().find_map(|a| match a {
+            Action::commitInfo(v) => v.is_blind_append,
+            _ => None,
+        });
+        let winning_is_blind_append = winning_is_blind_append.unwrap_or_else(|| {
+            !winning_actions
+                .iter()
+                .any(|a| matches!(a, Action::remove(_) | Action::metaData(_)))
+        });
+
+        let reads_table =
+            self.read_whole_table || !self.read_files.is_empty() || !removed_paths.is_empty();
+        let check_added_files = self.requires_empty_table
+            || match isolation_level {
+                IsolationLevel::Serializable => reads_table,
+                IsolationLevel::WriteSerializable => {
+                    reads_table && (!winning_is_blind_append || metadata_changed)
+                }
+                IsolationLevel::SnapshotIsolation => false,
+            };
+
+        for action in winning_actions {
+            match action {
+                Action::protocol(_) => {
+                    return Err(CommitConflictError::ProtocolChanged { version });
+                }
+                Action::metaData(_) => {
+                    return Err(CommitConflictError::MetadataChanged { version });
+                }
+                _ => {}
+            }
+        }
+
+        for action in winning_actions {
+            if let Action::add(add) = action {
+                if add.data_change && check_added_files {
+                    return Err(CommitConflictError::ConcurrentAppend { version });
+                }
+            }
+        }
+
+        for action in winning_actions {
+            if let Action::remove(remove) = action {
+                let path = remove.path.as_str();
+                if self.read_files.contains(path) || (self.read_whole_table && remove.data_change) {
+                    return Err(CommitConflictError::ConcurrentDeleteRead {
+                        version,
+                        path: remove.path.clone(),
+                    });
+                }
+            }
+        }
+
+        for action in winning_actions {
+            if let Action::remove(remove) = action {
+                if removed_paths.contains(remove.path.as_str()) {
+                    return Err(CommitConflictError::ConcurrentDeleteDelete {
+                        version,
+                        path: remove.path.clone(),
+                    });
+                }
+            }
+        }
+
+        for action in winning_actions {
+            if let Action::txn(txn) = action {
+                if written_app_ids.contains(txn.app_id.as_str()) {
+                    return Err(CommitConflictError::ConcurrentTransaction {
+                        version,
+                        app_id: txn.app_id.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the transaction only adds new data without depending on any existing
+    /// data of the table.
+    fn is_blind_append(&self) -> bool {
+        !self.read_whole_table
+            && !self.requires_empty_table
+            && self.read_files.is_empty()
+            && self.actions.iter().all(|a| !matches!(a, Action::remove(_)))
+    }
+
+    /// Builds the `CommitInfo` action recording the provenance of this transaction.
+    fn commit_info(
+        &self,
+        operation: Option<&DeltaOperation>,
+    ) -> Result<action::CommitInfo, DeltaTransactionError> {
+        let mut num_files: DeltaDataTypeLong = 0;
+        let mut num_output_bytes: DeltaDataTypeLong = 0;
+        let mut num_output_rows: Option<DeltaDataTypeLong> = Some(0);
+        let mut num_removed_files: DeltaDataTypeLong = 0;
+        for action in &self.actions {
+            match action {
+                Action::add(add) => {
+                    num_files += 1;
+                    num_output_bytes += add.size;
+                    num_output_rows = match (num_output_rows, add.get_stats()) {
+                        (Some(rows), Ok(Some(stats))) => Some(rows + stats.num_records),
+                        _ => None,
+                    };
+                }
+                Action::remove(_) => {
+                    num_removed_files += 1;
+                }
+                _ => {}
+            }
+        }
+
+        let mut operation_metrics = HashMap::new();
+        operation_metrics.insert("numFiles".to_string(), num_files);
+        operation_metrics.insert("numOutputBytes".to_string(), num_output_bytes);
+        if let Some(num_output_rows) = num_output_rows {
+            operation_metrics.insert("numOutputRows".to_string(), num_output_rows);
+        }
+        if num_removed_files > 0 {
+            operation_metrics.insert("numRemovedFiles".to_string(), num_removed_files);
+        }
+        operation_metrics.extend(self.operation_metrics.clone());
+        // metric values are recorded as strings, the same way Spark writes them
+        let operation_metrics: HashMap<String, Value> = operation_metrics
+            .into_iter()
+            .map(|(k, v)| (k, Value::String(v.to_string())))
+            .collect();
+
+        // Err should be impossible in this case since `SystemTime::now()` is always greater than `UNIX_EPOCH`
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        let mut info = HashMap::new();
+        info.insert(
+            "clientVersion".to_string(),
+            Value::String(format!("delta-rs.{}", crate_version())),
+        );
+
+        Ok(action::CommitInfo {
+            timestamp: Some(timestamp.as_millis() as i64),
+            operation: operation.map(|op| op.name().to_string()),
+            operation_parameters: operation.map(|op| op.operation_parameters()).transpose()?,
+            // nothing was read before the table was created
+            read_version: match operation {
+                Some(DeltaOperation::Create { .. }) => None,
+                _ => Some(self.read_version),
+            },
+            isolation_level: Some(self.isolation_level()?.name().to_string()),
+            is_blind_append: Some(self.is_blind_append()),
+            operation_metrics: Some(operation_metrics),
+            user_metadata: self.user_metadata.clone(),
+            info,
+            ..Default::default()
+        })
+    }
+
     /// Low-level transaction API. Creates a temporary commit file. Once created,
     /// the transaction object could be dropped and the actual commit could be executed
     /// with `DeltaTable.try_commit_transaction`.
+    ///
+    /// Unless one was added explicitly, a `CommitInfo` action describing the given `operation`
+    /// is written at the beginning of the log entry.
     pub async fn prepare_commit(
         &self,
-        _operation: Option<DeltaOperation>,
+        operation: Option<DeltaOperation>,
     ) -> Result<PreparedCommit, DeltaTransactionError> {
         let token = Uuid::new_v4().to_string();
 
-        // TODO: create a CommitInfo action and prepend it to actions.
+        let commit_info = if self
+            .actions
+            .iter()
+            .any(|a| matches!(a, Action::commitInfo(_)))
+        {
+            None
+        } else {
+            Some(Action::commitInfo(self.commit_info(operation.as_ref())?))
+        };
+
         // Serialize all actions that are part of this log entry.
-        let log_entry = log_entry_from_actions(&self.actions)?;
+        let log_entry = log_entry_from_actions(commit_info.iter().chain(self.actions.iter()))?;
 
         let file_name = format!("_commit_{}.json", token);
         let uri = self
@@ -1359,11 +2241,20 @@ impl<'a> DeltaTransaction<'a> {
     async fn try_commit_loop(
         &mut self,
         commit: &PreparedCommit,
+        isolation_level: IsolationLevel,
     ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
         let mut attempt_number: u32 = 0;
+        let mut checked_version = self.read_version;
         loop {
             self.delta_table.update_incremental().await?;
 
+            // check every version committed by other writers since the last attempt
+            while checked_version < self.delta_table.version {
+                checked_version += 1;
+                let winning_actions = self.delta_table.get_commit_actions(checked_version).await?;
+                self.check_for_conflicts(checked_version, &winning_actions, isolation_level)?;
+            }
+
             let version = self.delta_table.version + 1;
 
             match self
@@ -1397,6 +2288,56 @@ impl<'a> DeltaTransaction<'a> {
     }
 }
 
+/// Returns an array repeating the partition value `num_rows` times, converted from its string
+/// representation to the given type. Missing and empty values are nulls.
+pub(crate) fn partition_value_array(
+    value: Option<&String>,
+    data_type: &DataType,
+    num_rows: usize,
+) -> Result<ArrayRef, ArrowError> {
+    match value {
+        Some(value) if !value.is_empty() => {
+            let values: ArrayRef = Arc::new(StringArray::from(vec![value.as_str(); num_rows]));
+            cast(&values, data_type)
+        }
+        _ => Ok(new_null_array(data_type, num_rows)),
+    }
+}
+
+/// Checks that all of the given filters refer to partition columns.
+pub(crate) fn validate_partition_filters(
+    partition_columns: &[String],
+    filters: &[PartitionFilter<&str>],
+) -> Result<(), DeltaTableError> {
+    if let Some(filter) = filters
+        .iter()
+        .find(|f| !partition_columns.iter().any(|c| c == f.key))
+    {
+        return Err(DeltaTableError::InvalidPartitionFilter {
+            partition_filter: filter.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns whether the partition values of a file match all of the given filters, which refer to
+/// the partition columns by name whatever the column mapping of the table.
+pub(crate) fn matches_partition_filters(
+    metadata: &DeltaTableMetaData,
+    partition_values: &HashMap<String, String>,
+    filters: &[PartitionFilter<&str>],
+) -> bool {
+    let partitions: Vec<DeltaTablePartition> = metadata
+        .partition_columns
+        .iter()
+        .filter_map(|key| {
+            let value = partition_values.get(metadata.physical_name(key))?;
+            Some(DeltaTablePartition { key, value })
+        })
+        .collect();
+    filters.iter().all(|f| f.match_partitions(&partitions))
+}
+
 /// Holds the uri to prepared commit temporary file created with `DeltaTransaction.prepare_commit`.
 /// Once created, the actual commit could be executed with `DeltaTransaction.try_commit`.
 #[derive(Debug)]
@@ -1404,7 +2345,9 @@ pub struct PreparedCommit {
     uri: String,
 }
 
-fn log_entry_from_actions(actions: &[Action]) -> Result<String, serde_json::Error> {
+fn log_entry_from_actions<'a>(
+    actions: impl IntoIterator<Item = &'a Action>,
+) -> Result<String, serde_json::Error> {
     let mut jsons = Vec::<String>::new();
 
     for action in actions {
@@ -1560,11 +2503,7 @@ mod tests {
     async fn parquet_filename() {
         let mut table = open_table("./tests/data/simple_table").await.unwrap();
 
-        let txn = DeltaTransaction {
-            delta_table: &mut table,
-            actions: vec![],
-            options: DeltaTransactionOptions::default(),
-        };
+        let txn = DeltaTransaction::new(&mut table, None);
 
         let partitions = vec![
             (String::from("col1"), String::from("a")),
@@ -1,12 +1,16 @@
 //! Conversion between Delta Table schema and Arrow schema
 
+use crate::column_mapping::ColumnMappingMode;
 use crate::schema;
+use arrow::array::{make_array, ArrayData, ArrayRef};
 use arrow::datatypes::{
     DataType as ArrowDataType, Field as ArrowField, Schema as ArrowSchema, SchemaRef, TimeUnit,
 };
 use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
 impl TryFrom<&schema::Schema> for ArrowSchema {
@@ -23,6 +27,104 @@ impl TryFrom<&schema::Schema> for ArrowSchema {
     }
 }
 
+/// Converts the schema into the Arrow schema of the data files of a table using the given column
+/// mapping mode, in which columns and the nested fields of structs are named by their physical
+/// names.
+pub fn physical_arrow_schema(
+    s: &schema::Schema,
+    mode: ColumnMappingMode,
+) -> Result<ArrowSchema, ArrowError> {
+    let fields = s
+        .get_fields()
+        .iter()
+        .map(|field| physical_arrow_field(field, mode))
+        .collect::<Result<Vec<ArrowField>, ArrowError>>()?;
+
+    Ok(ArrowSchema::new(fields))
+}
+
+fn physical_arrow_field(
+    field: &schema::SchemaField,
+    mode: ColumnMappingMode,
+) -> Result<ArrowField, ArrowError> {
+    let name = match mode {
+        ColumnMappingMode::None => field.get_name(),
+        _ => field.physical_name(),
+    };
+    Ok(ArrowField::new(
+        name,
+        physical_arrow_type(field.get_type(), mode)?,
+        field.is_nullable(),
+    ))
+}
+
+fn physical_arrow_type(
+    t: &schema::SchemaDataType,
+    mode: ColumnMappingMode,
+) -> Result<ArrowDataType, ArrowError> {
+    match t {
+        schema::SchemaDataType::r#struct(s) => Ok(ArrowDataType::Struct(
+            s.get_fields()
+                .iter()
+                .map(|f| physical_arrow_field(f, mode))
+                .collect::<Result<Vec<ArrowField>, ArrowError>>()?,
+        )),
+        schema::SchemaDataType::array(a) => Ok(ArrowDataType::List(Box::new(ArrowField::new(
+            "element",
+            physical_arrow_type(a.get_element_type(), mode)?,
+            a.contains_null(),
+        )))),
+        t => ArrowDataType::try_from(t),
+    }
+}
+
+/// Creates a record batch of the physical schema of the data files from the columns of a batch
+/// with the schema of the table. The nested fields of struct columns are renamed to their
+/// physical names.
+pub(crate) fn physical_batch(
+    schema: SchemaRef,
+    columns: &[ArrayRef],
+) -> Result<RecordBatch, ArrowError> {
+    let columns = columns
+        .iter()
+        .zip(schema.fields())
+        .map(|(column, field)| {
+            if column.data_type() == field.data_type() {
+                column.clone()
+            } else {
+                make_array(relabel_array_data(column.data(), field.data_type()))
+            }
+        })
+        .collect();
+    RecordBatch::try_new(schema, columns)
+}
+
+/// Returns the array data with the given type, which only differs in the names of nested fields.
+fn relabel_array_data(data: &ArrayData, data_type: &ArrowDataType) -> ArrayData {
+    let child_data = match data_type {
+        ArrowDataType::Struct(fields) => fields
+            .iter()
+            .zip(data.child_data())
+            .map(|(field, child)| relabel_array_data(child, field.data_type()))
+            .collect(),
+        ArrowDataType::List(field) => data
+            .child_data()
+            .iter()
+            .map(|child| relabel_array_data(child, field.data_type()))
+            .collect(),
+        _ => return data.clone(),
+    };
+    ArrayData::new(
+        data_type.clone(),
+        data.len(),
+        Some(data.null_count()),
+        data.null_buffer().cloned(),
+        data.offset(),
+        data.buffers().to_vec(),
+        child_data,
+    )
+}
+
 impl TryFrom<&schema::SchemaField> for ArrowField {
     type Error = ArrowError;
 
@@ -137,6 +239,73 @@ impl TryFrom<&schema::SchemaDataType> for ArrowDataType {
     }
 }
 
+impl TryFrom<&ArrowSchema> for schema::Schema {
+    type Error = ArrowError;
+
+    fn try_from(s: &ArrowSchema) -> Result<Self, ArrowError> {
+        let fields = s
+            .fields()
+            .iter()
+            .map(|field| <schema::SchemaField as TryFrom<&ArrowField>>::try_from(field))
+            .collect::<Result<Vec<schema::SchemaField>, ArrowError>>()?;
+
+        Ok(schema::Schema::new(fields))
+    }
+}
+
+impl TryFrom<&ArrowField> for schema::SchemaField {
+    type Error = ArrowError;
+
+    fn try_from(f: &ArrowField) -> Result<Self, ArrowError> {
+        Ok(schema::SchemaField::new(
+            f.name().to_string(),
+            schema::SchemaDataType::try_from(f.data_type())?,
+            f.is_nullable(),
+            HashMap::new(),
+        ))
+    }
+}
+
+impl TryFrom<&ArrowDataType> for schema::SchemaDataType {
+    type Error = ArrowError;
+
+    fn try_from(t: &ArrowDataType) -> Result<Self, ArrowError> {
+        let primitive = |name: &str| Ok(schema::SchemaDataType::primitive(name.to_string()));
+        match t {
+            ArrowDataType::Utf8 => primitive("string"),
+            ArrowDataType::Int64 => primitive("long"),
+            ArrowDataType::Int32 => primitive("integer"),
+            ArrowDataType::Int16 => primitive("short"),
+            ArrowDataType::Int8 => primitive("byte"),
+            ArrowDataType::Float32 => primitive("float"),
+            ArrowDataType::Float64 => primitive("double"),
+            ArrowDataType::Boolean => primitive("boolean"),
+            ArrowDataType::Binary => primitive("binary"),
+            ArrowDataType::Decimal(p, s) => primitive(&format!("decimal({},{})", p, s)),
+            ArrowDataType::Date32 => primitive("date"),
+            ArrowDataType::Timestamp(_, None) => primitive("timestamp"),
+            ArrowDataType::Struct(fields) => Ok(schema::SchemaDataType::r#struct(
+                schema::SchemaTypeStruct::new(
+                    fields
+                        .iter()
+                        .map(|f| <schema::SchemaField as TryFrom<&ArrowField>>::try_from(f))
+                        .collect::<Result<Vec<schema::SchemaField>, ArrowError>>()?,
+                ),
+            )),
+            ArrowDataType::List(field) => {
+                Ok(schema::SchemaDataType::array(schema::SchemaTypeArray::new(
+                    Box::new(schema::SchemaDataType::try_from(field.data_type())?),
+                    field.is_nullable(),
+                )))
+            }
+            s => Err(ArrowError::SchemaError(format!(
+                "Invalid data type for Delta Lake: {:?}",
+                s
+            ))),
+        }
+    }
+}
+
 pub(crate) fn delta_log_schema_for_table(
     table_schema: ArrowSchema,
     partition_columns: &[String],
@@ -251,7 +420,6 @@ pub(crate) fn delta_log_schema_for_table(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
 
     #[test]
     fn delta_log_schema_for_table_test() {
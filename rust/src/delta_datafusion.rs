@@ -21,19 +21,775 @@
 //! ```
 
 use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use arrow::datatypes::Schema as ArrowSchema;
-use datafusion::datasource::datasource::{ColumnStatistics, Statistics};
+use arrow::array::{new_null_array, Array, ArrayRef, BooleanArray, Date32Array, StringArray};
+use arrow::compute::{cast, filter_record_batch};
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema, SchemaRef};
+use arrow::error::Result as ArrowResult;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::datasource::datasource::{
+    ColumnStatistics, Statistics, TableProviderFilterPushDown,
+};
 use datafusion::datasource::TableProvider;
-use datafusion::logical_plan::{combine_filters, Expr};
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::ExecutionContext;
+use datafusion::logical_plan::{col, combine_filters, when, Expr, Operator};
+use datafusion::optimizer::utils::expr_to_column_names;
 use datafusion::physical_plan::parquet::{ParquetExec, ParquetPartition, RowGroupPredicateBuilder};
-use datafusion::physical_plan::ExecutionPlan;
+use datafusion::physical_plan::planner::DefaultPhysicalPlanner;
+use datafusion::physical_plan::{
+    ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream, SendableRecordBatchStream,
+};
 use datafusion::scalar::ScalarValue;
+use futures::{Stream, StreamExt};
+use parquet::arrow::parquet_to_arrow_schema;
+use parquet::file::reader::{FileReader, SerializedFileReader};
 
+use crate::action::{self, Action, DeltaOperation};
+use crate::column_mapping::{self, ColumnMappingMode, FileColumn};
 use crate::delta;
-use crate::schema;
+use crate::schema::{self, DeltaDataTypeLong, DeltaDataTypeTimestamp, DeltaDataTypeVersion};
+use crate::writer::DataFileWriter;
+use crate::{DeltaTableError, DeltaTransactionError};
+
+impl delta::DeltaTable {
+    /// Deletes the rows matching the predicate from the table, as of its latest version.
+    ///
+    /// If the predicate only references partition columns, it is evaluated against the partition
+    /// values of every file and the matching files are removed as a whole. Otherwise the files
+    /// that may contain matching rows according to their statistics are read, and the ones
+    /// containing matching rows are removed, with the rest of their rows copied to new files.
+    /// Rows for which the predicate evaluates to null are kept, like in SQL.
+    ///
+    /// Returns the version of the commit, or the current version if no row matched. The commit
+    /// info records the `numRemovedFiles`, `numAddedFiles`, `numDeletedRows` and `numCopiedRows`
+    /// metrics of the delete.
+    pub async fn delete(
+        &mut self,
+        predicate: Expr,
+    ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        self.update_incremental().await?;
+
+        let schema = <ArrowSchema as TryFrom<&schema::Schema>>::try_from(self.get_schema()?)
+            .map_err(DeltaTableError::from)?;
+        let partition_columns = self.get_metadata()?.partition_columns.clone();
+
+        let mut columns = HashSet::new();
+        expr_to_column_names(&predicate, &mut columns).map_err(DeltaTableError::from)?;
+        let partitions_only =
+            !partition_columns.is_empty() && columns.iter().all(|c| partition_columns.contains(c));
+
+        let operation = DeltaOperation::Delete {
+            predicate: Some(SqlExpr(&predicate).to_string()),
+        };
+
+        if partitions_only {
+            let partition_schema = ArrowSchema::new(
+                schema
+                    .fields()
+                    .iter()
+                    .filter(|f| partition_columns.contains(f.name()))
+                    .cloned()
+                    .collect(),
+            );
+            let physical_expr = create_physical_expr(&predicate, &partition_schema)?;
+            let mut removed_files = vec![];
+            let mut num_deleted_rows: Option<DeltaDataTypeLong> = Some(0);
+            for add in self.get_active_add_actions() {
+                let batch = partition_values_batch(self.get_metadata()?, &partition_schema, add)?;
+                if is_true(&evaluate_predicate(physical_expr.as_ref(), &batch)?, 0) {
+                    num_deleted_rows = match (num_deleted_rows, add.get_stats()) {
+                        (Some(rows), Ok(Some(stats))) => Some(rows + stats.num_records),
+                        _ => None,
+                    };
+                    removed_files.push(add.clone());
+                }
+            }
+
+            let mut metrics = vec![("numAddedFiles", 0), ("numCopiedRows", 0)];
+            if let Some(num_deleted_rows) = num_deleted_rows {
+                metrics.push(("numDeletedRows", num_deleted_rows));
+            }
+            return self
+                .commit_rewrite(vec![], removed_files, vec![], operation, &metrics)
+                .await;
+        }
+
+        let physical_expr = create_physical_expr(&predicate, &schema)?;
+        let mut writer = DataFileWriter::try_new(self)?;
+        let files = self.files_matching_predicate(&predicate)?;
+        let read_files: Vec<String> = files.iter().map(|add| add.path.clone()).collect();
+        let rewrite = self
+            .rewrite_files(&files, &mut writer, |batch| {
+                let matches = evaluate_predicate(physical_expr.as_ref(), batch)?;
+                let keep: BooleanArray = (0..batch.num_rows())
+                    .map(|i| Some(!is_true(&matches, i)))
+                    .collect();
+                let copied = filter_record_batch(batch, &keep)?;
+                let num_deleted = batch.num_rows() - copied.num_rows();
+                Ok((copied, num_deleted))
+            })
+            .await?;
+        let added_files = writer.close().await?;
+
+        let metrics = [
+            ("numAddedFiles", added_files.len() as DeltaDataTypeLong),
+            ("numDeletedRows", rewrite.num_changed_rows),
+            ("numCopiedRows", rewrite.num_written_rows),
+        ];
+        self.commit_rewrite(
+            read_files,
+            rewrite.removed_files,
+            added_files,
+            operation,
+            &metrics,
+        )
+        .await
+    }
+
+    /// Updates the rows matching the predicate, or all rows if there is none, as of the latest
+    /// version of the table. Every column in `assignments` is set to the value of its expression,
+    /// which is evaluated against the row before the update and cast to the type of the column.
+    ///
+    /// Only the files that may contain matching rows according to their partition values and
+    /// statistics are read. The ones containing matching rows are removed and rewritten with the
+    /// assignments applied, in a single transaction. Rows for which the predicate evaluates to
+    /// null are not updated, like in SQL.
+    ///
+    /// Returns the version of the commit, or the current version if no row matched. The commit
+    /// info records the `numRemovedFiles`, `numAddedFiles`, `numUpdatedRows` and `numCopiedRows`
+    /// metrics of the update.
+    ///
+    /// Not to be confused with `DeltaTable::update`, which loads the latest version of the table.
+    pub async fn update_rows(
+        &mut self,
+        predicate: Option<Expr>,
+        assignments: HashMap<String, Expr>,
+    ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        self.update_incremental().await?;
+
+        let schema = <ArrowSchema as TryFrom<&schema::Schema>>::try_from(self.get_schema()?)
+            .map_err(DeltaTableError::from)?;
+        if let Some(column) = assignments
+            .keys()
+            .find(|c| schema.field_with_name(c).is_err())
+        {
+            return Err(DeltaTableError::SchemaMismatch {
+                msg: format!(
+                    "Cannot update column {} missing from the table schema",
+                    column
+                ),
+            }
+            .into());
+        }
+
+        let condition = predicate.clone().unwrap_or_else(true_expr);
+        let condition = create_physical_expr(&condition, &schema)?;
+        // generated columns that are not assigned are left out of the rewritten rows, so that
+        // the writer computes them from the updated values
+        let generated_columns = self.get_schema()?.get_generated_columns()?;
+        let rewritten_fields: Vec<Field> = schema
+            .fields()
+            .iter()
+            .filter(|field| {
+                assignments.contains_key(field.name())
+                    || generated_columns.iter().all(|c| &c.name != field.name())
+            })
+            .cloned()
+            .collect();
+        let projections = rewritten_fields
+            .iter()
+            .map(|field| {
+                let expr = match assignments.get(field.name()) {
+                    Some(value) => {
+                        let value = Expr::Cast {
+                            expr: Box::new(value.clone()),
+                            data_type: field.data_type().clone(),
+                        };
+                        match &predicate {
+                            Some(predicate) => {
+                                when(predicate.clone(), value).otherwise(col(field.name()))?
+                            }
+                            None => value,
+                        }
+                    }
+                    None => col(field.name()),
+                };
+                create_physical_expr(&expr, &schema)
+            })
+            .collect::<Result<Vec<Arc<dyn PhysicalExpr>>, DeltaTableError>>()?;
+
+        let schema = Arc::new(ArrowSchema::new(rewritten_fields));
+        let mut writer = DataFileWriter::try_new(self)?;
+        let files = match &predicate {
+            Some(predicate) => self.files_matching_predicate(predicate)?,
+            None => self.get_active_add_actions().iter().collect(),
+        };
+        let read_files: Vec<String> = files.iter().map(|add| add.path.clone()).collect();
+        let rewrite = self
+            .rewrite_files(&files, &mut writer, |batch| {
+                let matches = evaluate_predicate(condition.as_ref(), batch)?;
+                let num_updated = (0..batch.num_rows())
+                    .filter(|i| is_true(&matches, *i))
+                    .count();
+                if num_updated == 0 {
+                    return Ok((batch.clone(), 0));
+                }
+
+                let columns = projections
+                    .iter()
+                    .map(|expr| Ok(expr.evaluate(batch)?.into_array(batch.num_rows())))
+                    .collect::<Result<Vec<ArrayRef>, DeltaTableError>>()?;
+                Ok((RecordBatch::try_new(schema.clone(), columns)?, num_updated))
+            })
+            .await?;
+        let added_files = writer.close().await?;
+
+        let metrics = [
+            ("numAddedFiles", added_files.len() as DeltaDataTypeLong),
+            ("numUpdatedRows", rewrite.num_changed_rows),
+            (
+                "numCopiedRows",
+                rewrite.num_written_rows - rewrite.num_changed_rows,
+            ),
+        ];
+        let operation = DeltaOperation::Update {
+            predicate: predicate.as_ref().map(|p| SqlExpr(p).to_string()),
+        };
+        self.commit_rewrite(
+            read_files,
+            rewrite.removed_files,
+            added_files,
+            operation,
+            &metrics,
+        )
+        .await
+    }
+
+    /// Returns the active files that may contain rows matching the predicate, based on their
+    /// partition values and the min and max values in their statistics. Parts of the predicate
+    /// that cannot be checked against these are assumed to match, as are files without
+    /// statistics.
+    pub(crate) fn files_matching_predicate(
+        &self,
+        predicate: &Expr,
+    ) -> Result<Vec<&action::Add>, DeltaTableError> {
+        let files: Vec<&action::Add> = self.get_active_add_actions().iter().collect();
+        let schema = <ArrowSchema as TryFrom<&schema::Schema>>::try_from(self.get_schema()?)?;
+        let metadata = self.get_metadata()?;
+        let partition_columns = &metadata.partition_columns;
+
+        // filters on generated partition columns are derived from the filters on the columns
+        // they are generated from, so that files are pruned by their partition values
+        let generated_columns = metadata.schema.get_generated_columns()?;
+        let mut predicate = predicate.clone();
+        for column in generated_columns
+            .iter()
+            .filter(|column| partition_columns.contains(&column.name))
+        {
+            if let Some(source) = column.date_source_column() {
+                for filter in date_partition_filters(&predicate, &schema, source, &column.name) {
+                    predicate = predicate.and(filter);
+                }
+            }
+        }
+
+        let mut stats_columns = HashMap::new();
+        let pruning_expr = pruning_expr(&predicate, &schema, partition_columns, &mut stats_columns);
+        if stats_columns.is_empty() {
+            return Ok(files);
+        }
+
+        let stats = files
+            .iter()
+            .map(|add| add.get_stats().ok().flatten())
+            .collect::<Vec<Option<action::Stats>>>();
+        let mut fields = vec![];
+        let mut columns = vec![];
+        for (name, stats_column) in stats_columns {
+            let (data_type, values): (&DataType, Vec<Option<String>>) = match stats_column {
+                StatsColumn::PartitionValue(field) => (
+                    field.data_type(),
+                    files
+                        .iter()
+                        .map(|add| {
+                            add.partition_values
+                                .get(metadata.physical_name(field.name()))
+                                .filter(|v| !v.is_empty())
+                                .cloned()
+                        })
+                        .collect(),
+                ),
+                StatsColumn::Min(field) => (
+                    field.data_type(),
+                    stats
+                        .iter()
+                        .map(|s| {
+                            let min_values = &s.as_ref()?.min_values;
+                            stats_value(min_values.get(metadata.physical_name(field.name()))?)
+                        })
+                        .collect(),
+                ),
+                StatsColumn::Max(field) => (
+                    field.data_type(),
+                    stats
+                        .iter()
+                        .map(|s| {
+                            let max_values = &s.as_ref()?.max_values;
+                            stats_value(max_values.get(metadata.physical_name(field.name()))?)
+                        })
+                        .collect(),
+                ),
+                StatsColumn::NullCount(field) => (
+                    &DataType::Int64,
+                    stats
+                        .iter()
+                        .map(|s| {
+                            let null_count = &s.as_ref()?.null_count;
+                            let null_count =
+                                null_count.get(metadata.physical_name(field.name()))?;
+                            null_count.as_value().map(|v| v.to_string())
+                        })
+                        .collect(),
+                ),
+                StatsColumn::NumRecords => (
+                    &DataType::Int64,
+                    stats
+                        .iter()
+                        .map(|s| s.as_ref().map(|s| s.num_records.to_string()))
+                        .collect(),
+                ),
+            };
+            let values: ArrayRef = Arc::new(StringArray::from(
+                values
+                    .iter()
+                    .map(|v| v.as_deref())
+                    .collect::<Vec<Option<&str>>>(),
+            ));
+            // values that cannot be converted are unknown, which keeps the files
+            let values =
+                cast(&values, data_type).unwrap_or_else(|_| new_null_array(data_type, files.len()));
+            fields.push(Field::new(&name, data_type.clone(), true));
+            columns.push(values);
+        }
+
+        let stats_schema = ArrowSchema::new(fields);
+        let batch = RecordBatch::try_new(Arc::new(stats_schema.clone()), columns)?;
+        let pruning_expr = create_physical_expr(&pruning_expr, &stats_schema)?;
+        let may_match = evaluate_predicate(pruning_expr.as_ref(), &batch)?;
+        let may_match = may_match.as_any().downcast_ref::<BooleanArray>().unwrap();
+
+        Ok(files
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| may_match.is_null(*i) || may_match.value(*i))
+            .map(|(_, add)| add)
+            .collect())
+    }
+
+    /// Reads the given files and passes each of their record batches to `rewrite`, which returns
+    /// the rows to keep and the number of rows it changed. Files with changed rows are rewritten
+    /// with the returned rows, the others are left untouched.
+    async fn rewrite_files<F>(
+        &self,
+        files: &[&action::Add],
+        writer: &mut DataFileWriter,
+        mut rewrite: F,
+    ) -> Result<RewriteSummary, DeltaTableError>
+    where
+        F: FnMut(&RecordBatch) -> Result<(RecordBatch, usize), DeltaTableError>,
+    {
+        let mut summary = RewriteSummary {
+            removed_files: vec![],
+            num_changed_rows: 0,
+            num_written_rows: 0,
+        };
+
+        for add in files {
+            let mut rewritten_batches = vec![];
+            let mut num_changed_rows = 0;
+            for batch in self.read_data_file(add).await? {
+                let (rewritten, num_changed) = rewrite(&batch)?;
+                num_changed_rows += num_changed;
+                rewritten_batches.push(rewritten);
+            }
+            if num_changed_rows == 0 {
+                continue;
+            }
+
+            for batch in rewritten_batches.iter().filter(|b| b.num_rows() > 0) {
+                summary.num_written_rows += batch.num_rows() as DeltaDataTypeLong;
+                writer.write(batch).await?;
+            }
+            summary.num_changed_rows += num_changed_rows as DeltaDataTypeLong;
+            summary.removed_files.push((*add).clone());
+        }
+
+        Ok(summary)
+    }
+
+    /// Commits the removal of the rewritten files together with the files replacing them or
+    /// added otherwise. Nothing is committed if no file was removed or added.
+    pub(crate) async fn commit_rewrite(
+        &mut self,
+        read_files: Vec<String>,
+        removed_files: Vec<action::Add>,
+        added_files: Vec<action::Add>,
+        operation: DeltaOperation,
+        metrics: &[(&str, DeltaDataTypeLong)],
+    ) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        if removed_files.is_empty() && added_files.is_empty() {
+            return Ok(self.version);
+        }
+
+        // Err should be impossible in this case since `SystemTime::now()` is always greater than `UNIX_EPOCH`
+        let deletion_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let deletion_timestamp = deletion_timestamp.as_millis() as DeltaDataTypeTimestamp;
+
+        let mut dtx = self.create_transaction(None);
+        dtx.add_read_files(read_files);
+        dtx.add_actions(
+            removed_files
+                .iter()
+                .map(|add| Action::remove(add.to_remove(deletion_timestamp, true)))
+                .collect(),
+        );
+        dtx.add_actions(added_files.into_iter().map(Action::add).collect());
+        for (name, value) in metrics {
+            dtx.record_operation_metric(name, *value);
+        }
+
+        dtx.commit(Some(operation)).await
+    }
+}
+
+/// The outcome of `DeltaTable::rewrite_files`.
+struct RewriteSummary {
+    removed_files: Vec<action::Add>,
+    num_changed_rows: DeltaDataTypeLong,
+    num_written_rows: DeltaDataTypeLong,
+}
+
+/// Name of the column holding the number of records of every file in the batch a pruning
+/// expression is evaluated on.
+const NUM_RECORDS_COLUMN: &str = "num_records";
+
+/// A column of the batch a pruning expression is evaluated on, with one row per file.
+enum StatsColumn<'a> {
+    PartitionValue(&'a Field),
+    Min(&'a Field),
+    Max(&'a Field),
+    NullCount(&'a Field),
+    NumRecords,
+}
+
+/// Rewrites the predicate into an expression over the partition values and the min, max and null
+/// count statistics of files, which is false only for files that cannot contain matching rows.
+/// The columns the expression references are collected into `stats_columns`.
+fn pruning_expr<'a>(
+    predicate: &Expr,
+    schema: &'a ArrowSchema,
+    partition_columns: &[String],
+    stats_columns: &mut HashMap<String, StatsColumn<'a>>,
+) -> Expr {
+    let mut columns = HashSet::new();
+    if expr_to_column_names(predicate, &mut columns).is_err() {
+        return true_expr();
+    }
+    if !columns.is_empty() && columns.iter().all(|c| partition_columns.contains(c)) {
+        for name in columns {
+            if let Ok(field) = schema.field_with_name(&name) {
+                stats_columns.insert(name, StatsColumn::PartitionValue(field));
+            }
+        }
+        return predicate.clone();
+    }
+
+    let (column, op, value) = match predicate {
+        Expr::IsNull(expr) | Expr::IsNotNull(expr) => {
+            let field = match expr.as_ref() {
+                Expr::Column(column) => match schema.field_with_name(column) {
+                    Ok(field) => field,
+                    Err(_) => return true_expr(),
+                },
+                _ => return true_expr(),
+            };
+            let null_count_name = format!("null_count({})", field.name());
+            let null_count = col(&null_count_name);
+            stats_columns.insert(null_count_name, StatsColumn::NullCount(field));
+            return match predicate {
+                Expr::IsNull(_) => null_count.gt(Expr::Literal(ScalarValue::Int64(Some(0)))),
+                _ => {
+                    stats_columns.insert(NUM_RECORDS_COLUMN.to_string(), StatsColumn::NumRecords);
+                    null_count.lt(col(NUM_RECORDS_COLUMN))
+                }
+            };
+        }
+        Expr::BinaryExpr { left, op, right } => match (left.as_ref(), right.as_ref()) {
+            (_, _) if *op == Operator::And => {
+                return pruning_expr(left, schema, partition_columns, stats_columns).and(
+                    pruning_expr(right, schema, partition_columns, stats_columns),
+                );
+            }
+            (_, _) if *op == Operator::Or => {
+                return pruning_expr(left, schema, partition_columns, stats_columns).or(
+                    pruning_expr(right, schema, partition_columns, stats_columns),
+                );
+            }
+            (Expr::Column(column), Expr::Literal(value)) => (column, op.clone(), value),
+            (Expr::Literal(value), Expr::Column(column)) => match op {
+                Operator::Lt => (column, Operator::Gt, value),
+                Operator::LtEq => (column, Operator::GtEq, value),
+                Operator::Gt => (column, Operator::Lt, value),
+                Operator::GtEq => (column, Operator::LtEq, value),
+                op => (column, op.clone(), value),
+            },
+            _ => return true_expr(),
+        },
+        _ => return true_expr(),
+    };
+
+    let field = match schema.field_with_name(column) {
+        Ok(field) => field,
+        Err(_) => return true_expr(),
+    };
+    let min_name = format!("min({})", column);
+    let max_name = format!("max({})", column);
+    let min = || col(&min_name);
+    let max = || col(&max_name);
+    let value = || Expr::Literal(value.clone());
+    let expr = match op {
+        Operator::Eq => min().lt_eq(value()).and(max().gt_eq(value())),
+        Operator::Lt => min().lt(value()),
+        Operator::LtEq => min().lt_eq(value()),
+        Operator::Gt => max().gt(value()),
+        Operator::GtEq => max().gt_eq(value()),
+        _ => return true_expr(),
+    };
+    if matches!(op, Operator::Eq | Operator::Lt | Operator::LtEq) {
+        stats_columns.insert(min_name.clone(), StatsColumn::Min(field));
+    }
+    if matches!(op, Operator::Eq | Operator::Gt | Operator::GtEq) {
+        stats_columns.insert(max_name.clone(), StatsColumn::Max(field));
+    }
+    expr
+}
+
+/// Derives filters on a partition column generated by `CAST(<source> AS DATE)` from the
+/// comparisons of the source column with literals in the conjunction of the predicate. Since the
+/// cast preserves the order of the values, `source < value` implies
+/// `partition <= CAST(value AS DATE)` for example.
+fn date_partition_filters(
+    predicate: &Expr,
+    schema: &ArrowSchema,
+    source: &str,
+    partition_column: &str,
+) -> Vec<Expr> {
+    let (column, op, value) = match predicate {
+        Expr::BinaryExpr { left, op, right } => match (left.as_ref(), right.as_ref()) {
+            (_, _) if *op == Operator::And => {
+                let mut filters = date_partition_filters(left, schema, source, partition_column);
+                filters.extend(date_partition_filters(
+                    right,
+                    schema,
+                    source,
+                    partition_column,
+                ));
+                return filters;
+            }
+            (Expr::Column(column), Expr::Literal(value)) => (column, op.clone(), value),
+            (Expr::Literal(value), Expr::Column(column)) => match op {
+                Operator::Lt => (column, Operator::Gt, value),
+                Operator::LtEq => (column, Operator::GtEq, value),
+                Operator::Gt => (column, Operator::Lt, value),
+                Operator::GtEq => (column, Operator::LtEq, value),
+                op => (column, op.clone(), value),
+            },
+            _ => return vec![],
+        },
+        _ => return vec![],
+    };
+    if column != source {
+        return vec![];
+    }
+    let source_type = match schema.field_with_name(source) {
+        Ok(field) => field.data_type(),
+        Err(_) => return vec![],
+    };
+
+    // literals are converted to the type of the source column first, e.g. strings to timestamps
+    let date = cast(&value.to_array(), source_type)
+        .and_then(|value| cast(&value, &DataType::Date32))
+        .ok()
+        .and_then(|date| {
+            let date = date.as_any().downcast_ref::<Date32Array>()?;
+            if date.is_valid(0) {
+                Some(date.value(0))
+            } else {
+                None
+            }
+        });
+    let date = match date {
+        Some(date) => Expr::Literal(ScalarValue::Date32(Some(date))),
+        None => return vec![],
+    };
+    let partition = col(partition_column);
+    match op {
+        Operator::Eq => vec![partition.eq(date)],
+        Operator::Lt | Operator::LtEq => vec![partition.lt_eq(date)],
+        Operator::Gt | Operator::GtEq => vec![partition.gt_eq(date)],
+        _ => vec![],
+    }
+}
+
+pub(crate) fn true_expr() -> Expr {
+    Expr::Literal(ScalarValue::Boolean(Some(true)))
+}
+
+/// Displays an expression as SQL, e.g. `value > 2 AND id = 'A'`, for the predicates of the
+/// operations recorded in the commit info.
+pub(crate) struct SqlExpr<'a>(pub(crate) &'a Expr);
+
+impl SqlExpr<'_> {
+    /// Writes a nested expression, with parentheses if it is an operation itself.
+    fn fmt_operand(f: &mut fmt::Formatter<'_>, expr: &Expr) -> fmt::Result {
+        match expr {
+            Expr::BinaryExpr { .. } | Expr::Between { .. } | Expr::InList { .. } => {
+                write!(f, "({})", SqlExpr(expr))
+            }
+            _ => write!(f, "{}", SqlExpr(expr)),
+        }
+    }
+
+    fn fmt_list(f: &mut fmt::Formatter<'_>, exprs: &[Expr]) -> fmt::Result {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", SqlExpr(expr))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SqlExpr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Expr::Alias(expr, _) => write!(f, "{}", SqlExpr(expr)),
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::Literal(ScalarValue::Utf8(Some(s)))
+            | Expr::Literal(ScalarValue::LargeUtf8(Some(s))) => {
+                write!(f, "'{}'", s.replace('\'', "''"))
+            }
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::BinaryExpr { left, op, right } => {
+                Self::fmt_operand(f, left)?;
+                write!(f, " {} ", op)?;
+                Self::fmt_operand(f, right)
+            }
+            Expr::Not(expr) => {
+                write!(f, "NOT ")?;
+                Self::fmt_operand(f, expr)
+            }
+            Expr::IsNull(expr) => {
+                Self::fmt_operand(f, expr)?;
+                write!(f, " IS NULL")
+            }
+            Expr::IsNotNull(expr) => {
+                Self::fmt_operand(f, expr)?;
+                write!(f, " IS NOT NULL")
+            }
+            Expr::Negative(expr) => {
+                write!(f, "-")?;
+                Self::fmt_operand(f, expr)
+            }
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                Self::fmt_operand(f, expr)?;
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, " {}BETWEEN ", not)?;
+                Self::fmt_operand(f, low)?;
+                write!(f, " AND ")?;
+                Self::fmt_operand(f, high)
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                Self::fmt_operand(f, expr)?;
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, " {}IN (", not)?;
+                Self::fmt_list(f, list)?;
+                write!(f, ")")
+            }
+            Expr::Case {
+                expr,
+                when_then_expr,
+                else_expr,
+            } => {
+                write!(f, "CASE")?;
+                if let Some(expr) = expr {
+                    write!(f, " {}", SqlExpr(expr))?;
+                }
+                for (when, then) in when_then_expr {
+                    write!(f, " WHEN {} THEN {}", SqlExpr(when), SqlExpr(then))?;
+                }
+                if let Some(else_expr) = else_expr {
+                    write!(f, " ELSE {}", SqlExpr(else_expr))?;
+                }
+                write!(f, " END")
+            }
+            Expr::Cast { expr, data_type } => {
+                write!(f, "CAST({} AS ", SqlExpr(expr))?;
+                match data_type {
+                    DataType::Boolean => write!(f, "BOOLEAN"),
+                    DataType::Int8 => write!(f, "TINYINT"),
+                    DataType::Int16 => write!(f, "SMALLINT"),
+                    DataType::Int32 => write!(f, "INT"),
+                    DataType::Int64 => write!(f, "BIGINT"),
+                    DataType::Float32 => write!(f, "FLOAT"),
+                    DataType::Float64 => write!(f, "DOUBLE"),
+                    DataType::Utf8 | DataType::LargeUtf8 => write!(f, "STRING"),
+                    DataType::Date32 => write!(f, "DATE"),
+                    DataType::Timestamp(_, _) => write!(f, "TIMESTAMP"),
+                    other => write!(f, "{:?}", other),
+                }?;
+                write!(f, ")")
+            }
+            Expr::ScalarFunction { fun, args } => {
+                write!(f, "{}(", fun)?;
+                Self::fmt_list(f, args)?;
+                write!(f, ")")
+            }
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// Returns the string representation of a min or max value of the statistics, which is cast to
+/// the type of the column.
+fn stats_value(stat: &action::ColumnValueStat) -> Option<String> {
+    match stat.as_value()? {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
 
 impl TableProvider for delta::DeltaTable {
     fn schema(&self) -> Arc<ArrowSchema> {
@@ -52,69 +808,91 @@ impl TableProvider for delta::DeltaTable {
         filters: &[Expr],
         limit: Option<usize>,
     ) -> datafusion::error::Result<Arc<dyn ExecutionPlan>> {
-        let schema = <ArrowSchema as TryFrom<&schema::Schema>>::try_from(
-            delta::DeltaTable::schema(&self).unwrap(),
-        )?;
+        let metadata = self
+            .get_metadata()
+            .map_err(|e| DataFusionError::Execution(e.to_string()))?;
+        let mode = metadata
+            .column_mapping_mode()
+            .map_err(|e| DataFusionError::Execution(e.to_string()))?;
+        let schema = <ArrowSchema as TryFrom<&schema::Schema>>::try_from(&metadata.schema)?;
+        let partition_columns = &metadata.partition_columns;
         let filenames = self.get_file_uris();
 
-        let partitions = filenames
-            .into_iter()
-            .zip(self.get_active_add_actions())
-            .map(|(fname, action)| {
-                let statistics = if let Ok(Some(statistics)) = action.get_stats() {
-                    Statistics {
-                        num_rows: Some(statistics.num_records as usize),
-                        total_byte_size: Some(action.size as usize),
-                        column_statistics: Some(
-                            self.schema()
-                                .unwrap()
-                                .get_fields()
-                                .iter()
-                                .map(|field| ColumnStatistics {
-                                    null_count: statistics
-                                        .null_count
-                                        .get(field.get_name())
-                                        .and_then(|f| f.as_value().map(|v| v as usize)),
-                                    max_value: statistics
-                                        .max_values
-                                        .get(field.get_name())
-                                        .and_then(|f| to_scalar_value(f.as_value()?)),
-                                    min_value: statistics
-                                        .min_values
-                                        .get(field.get_name())
-                                        .and_then(|f| to_scalar_value(f.as_value()?)),
-                                    distinct_count: None, // TODO: distinct
-                                })
-                                .collect(),
-                        ),
-                    }
-                } else {
-                    Statistics::default()
-                };
+        let projection = projection
+            .clone()
+            .unwrap_or_else(|| (0..schema.fields().len()).collect());
+        let projected_schema = ArrowSchema::new(
+            projection
+                .iter()
+                .map(|i| schema.field(*i).clone())
+                .collect(),
+        );
+
+        // files whose statistics or partition values rule out the filters are never opened, the
+        // scan falls back to all files if they cannot be evaluated
+        let matching_files: Option<HashSet<&str>> = combine_filters(filters)
+            .and_then(|predicate| self.files_matching_predicate(&predicate).ok())
+            .map(|files| files.into_iter().map(|add| add.path.as_str()).collect());
 
-                Ok(ParquetPartition::new(vec![fname], statistics))
+        // row groups can only be pruned by the filters on columns stored in the files, under
+        // their logical names
+        let file_filters: Vec<Expr> = filters
+            .iter()
+            .filter(|filter| {
+                let mut columns = HashSet::new();
+                expr_to_column_names(filter, &mut columns).is_ok()
+                    && columns.iter().all(|c| !partition_columns.contains(c))
             })
-            .collect::<datafusion::error::Result<_>>()?;
+            .cloned()
+            .collect();
+        let predicate_expr = match mode {
+            ColumnMappingMode::None => combine_filters(&file_filters),
+            _ => None,
+        };
 
-        let predicate_builder = combine_filters(filters).and_then(|predicate_expr| {
-            RowGroupPredicateBuilder::try_new(&predicate_expr, schema.clone()).ok()
-        });
+        // the data files are only opened when their partitions are executed, since the columns
+        // of every file are resolved from its own schema
+        let files = filenames
+            .into_iter()
+            .zip(self.get_active_add_actions())
+            .filter(|(_, action)| match &matching_files {
+                Some(files) => files.contains(action.path.as_str()),
+                None => true,
+            })
+            .map(|(path, action)| FileScan {
+                path,
+                add: action.clone(),
+                partition_values: metadata.logical_partition_values(&action.partition_values),
+            })
+            .collect();
 
-        Ok(Arc::new(ParquetExec::new(
-            partitions,
-            schema,
-            projection.clone(),
-            predicate_builder,
+        Ok(Arc::new(DeltaScan {
+            schema: Arc::new(projected_schema),
+            table_schema: Arc::new(schema),
+            projection,
+            metadata: Arc::new(metadata.clone()),
+            mode,
+            predicate: predicate_expr,
             batch_size,
             limit,
-        )))
+            files,
+        }))
     }
 
     fn as_any(&self) -> &dyn Any {
         self
     }
 
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> datafusion::error::Result<TableProviderFilterPushDown> {
+        // files are pruned by the filters, the rows of the remaining files still need filtering
+        Ok(TableProviderFilterPushDown::Inexact)
+    }
+
     fn statistics(&self) -> Statistics {
+        let metadata = self.get_metadata().unwrap();
         self.get_active_add_actions()
             .iter()
             .fold(
@@ -150,14 +928,16 @@ impl TableProvider for delta::DeltaTable {
                                 .map(|(field, stats)| ColumnStatistics {
                                     null_count: new_stats
                                         .null_count
-                                        .get(field.get_name())
+                                        .get(metadata.physical_name(field.get_name()))
                                         .and_then(|x| {
                                             let null_count_acc = stats.null_count?;
                                             let null_count = x.as_value()? as usize;
                                             Some(null_count_acc + null_count)
                                         }),
-                                    max_value: new_stats.max_values.get(field.get_name()).and_then(
-                                        |x| {
+                                    max_value: new_stats
+                                        .max_values
+                                        .get(metadata.physical_name(field.get_name()))
+                                        .and_then(|x| {
                                             let old_stats = stats.clone();
                                             let max_value = to_scalar_value(x.as_value()?);
 
@@ -175,10 +955,11 @@ impl TableProvider for delta::DeltaTable {
                                                 (Some(max_value), None) => Some(max_value),
                                                 (None, old) => old,
                                             }
-                                        },
-                                    ),
-                                    min_value: new_stats.min_values.get(field.get_name()).and_then(
-                                        |x| {
+                                        }),
+                                    min_value: new_stats
+                                        .min_values
+                                        .get(metadata.physical_name(field.get_name()))
+                                        .and_then(|x| {
                                             let old_stats = stats.clone();
                                             let min_value = to_scalar_value(x.as_value()?);
 
@@ -196,8 +977,7 @@ impl TableProvider for delta::DeltaTable {
                                                 (Some(min_value), None) => Some(min_value),
                                                 (None, old) => old,
                                             }
-                                        },
-                                    ),
+                                        }),
                                     distinct_count: None, // TODO: distinct
                                 })
                                 .collect()
@@ -259,3 +1039,324 @@ fn left_larger_than_right(
         ),
     }
 }
+
+/// Plans the given expression against the schema for evaluation on record batches.
+pub(crate) fn create_physical_expr(
+    expr: &Expr,
+    schema: &ArrowSchema,
+) -> Result<Arc<dyn PhysicalExpr>, DeltaTableError> {
+    let ctx_state = ExecutionContext::new().state.lock().unwrap().clone();
+    Ok(DefaultPhysicalPlanner::default().create_physical_expr(expr, schema, &ctx_state)?)
+}
+
+/// Evaluates a boolean expression on every row of the batch.
+pub(crate) fn evaluate_predicate(
+    predicate: &dyn PhysicalExpr,
+    batch: &RecordBatch,
+) -> Result<ArrayRef, DeltaTableError> {
+    let result = predicate.evaluate(batch)?.into_array(batch.num_rows());
+    match result.as_any().downcast_ref::<BooleanArray>() {
+        Some(_) => Ok(result),
+        None => Err(DeltaTableError::Generic(format!(
+            "Predicate evaluates to {:?} instead of a boolean",
+            result.data_type()
+        ))),
+    }
+}
+
+/// Returns whether the boolean array is true at the given row. Null is not true.
+pub(crate) fn is_true(array: &ArrayRef, row: usize) -> bool {
+    let array = array.as_any().downcast_ref::<BooleanArray>().unwrap();
+    array.is_valid(row) && array.value(row)
+}
+
+/// Returns a batch with a single row holding the typed partition values of the file.
+fn partition_values_batch(
+    metadata: &delta::DeltaTableMetaData,
+    schema: &ArrowSchema,
+    add: &action::Add,
+) -> Result<RecordBatch, DeltaTableError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let value = add
+                .partition_values
+                .get(metadata.physical_name(field.name()))
+                .filter(|v| !v.is_empty())
+                .map(|v| v.as_str());
+            let values = StringArray::from(vec![value]);
+            Ok(cast(&(Arc::new(values) as ArrayRef), field.data_type())?)
+        })
+        .collect::<Result<Vec<ArrayRef>, DeltaTableError>>()?;
+    Ok(RecordBatch::try_new(Arc::new(schema.clone()), columns)?)
+}
+
+/// Resolves the projected columns of the table to the columns of a data file with the given
+/// schema, where the columns of the table are found under the given names. Returns the indices
+/// of the file columns to read, in the order they are stored, along with the source of every
+/// projected column.
+fn resolve_scan_columns(
+    schema: &ArrowSchema,
+    projection: &[usize],
+    partition_columns: &[String],
+    file_schema: &ArrowSchema,
+    file_columns: &[FileColumn],
+) -> (Vec<usize>, Vec<ScanColumn>) {
+    let file_index = |i: usize| -> Option<usize> {
+        if partition_columns.contains(schema.field(i).name()) {
+            None
+        } else {
+            file_schema.index_of(&file_columns[i].name).ok()
+        }
+    };
+
+    // the Parquet reader returns the columns in the order of the file
+    let mut file_projection: Vec<usize> =
+        projection.iter().filter_map(|i| file_index(*i)).collect();
+    file_projection.sort_unstable();
+    file_projection.dedup();
+
+    let columns = projection
+        .iter()
+        .map(|i| {
+            let field = schema.field(*i);
+            if partition_columns.contains(field.name()) {
+                return ScanColumn::PartitionValue(field.clone());
+            }
+            match file_index(*i).and_then(|index| file_projection.iter().position(|p| *p == index))
+            {
+                Some(position) => ScanColumn::File(position, file_columns[*i].clone()),
+                None => ScanColumn::Missing(field.clone()),
+            }
+        })
+        .collect();
+
+    if file_projection.is_empty() && !file_schema.fields().is_empty() {
+        // the file is still read for its number of rows
+        file_projection.push(0);
+    }
+    (file_projection, columns)
+}
+
+/// A column of the rows returned by a `DeltaScan`.
+#[derive(Debug, Clone)]
+enum ScanColumn {
+    /// The column at the given index of the rows read from the data file, along with the fields of
+    /// the data file its nested fields are stored in.
+    File(usize, FileColumn),
+    /// A partition column, filled in from the partition values of the data file.
+    PartitionValue(Field),
+    /// A column missing from the data file, filled in with nulls.
+    Missing(Field),
+}
+
+/// The scan of a single data file by a `DeltaScan`.
+#[derive(Debug, Clone)]
+struct FileScan {
+    path: String,
+    add: action::Add,
+    partition_values: HashMap<String, String>,
+}
+
+/// Execution plan scanning the data files of a Delta table, with one partition per data file.
+/// Since Delta does not store the values of partition columns in the data files, they are added
+/// to the rows read from every file as constant columns, with the values of the file converted
+/// to the types of the schema. Columns missing from a data file are read as nulls.
+#[derive(Debug, Clone)]
+pub struct DeltaScan {
+    schema: SchemaRef,
+    table_schema: SchemaRef,
+    projection: Vec<usize>,
+    metadata: Arc<delta::DeltaTableMetaData>,
+    mode: ColumnMappingMode,
+    predicate: Option<Expr>,
+    batch_size: usize,
+    limit: Option<usize>,
+    files: Vec<FileScan>,
+}
+
+impl DeltaScan {
+    /// Creates the Parquet scan of the data file, reading the columns the projected columns of
+    /// the table are resolved to in the schema of the file.
+    async fn file_scan(
+        &self,
+        file: &FileScan,
+    ) -> datafusion::error::Result<(ParquetExec, Vec<ScanColumn>)> {
+        let path = file.path.clone();
+        let metadata = self.metadata.clone();
+        let mode = self.mode;
+        let (file_schema, file_columns) = tokio::task::spawn_blocking(
+            move || -> datafusion::error::Result<(ArrowSchema, Vec<FileColumn>)> {
+                let file_reader = SerializedFileReader::new(File::open(&path)?)?;
+                let file_metadata = file_reader.metadata().file_metadata();
+                let file_schema = parquet_to_arrow_schema(
+                    file_metadata.schema_descr(),
+                    file_metadata.key_value_metadata(),
+                )?;
+                let file_columns = column_mapping::data_file_columns(
+                    &metadata,
+                    mode,
+                    file_metadata.schema_descr(),
+                );
+                Ok((file_schema, file_columns))
+            },
+        )
+        .await
+        .map_err(|e| DataFusionError::Execution(e.to_string()))??;
+        let (file_projection, columns) = resolve_scan_columns(
+            &self.table_schema,
+            &self.projection,
+            &self.metadata.partition_columns,
+            &file_schema,
+            &file_columns,
+        );
+
+        let statistics = file_statistics(&file.add, &file_schema);
+        let predicate_builder = self.predicate.as_ref().and_then(|predicate_expr| {
+            RowGroupPredicateBuilder::try_new(predicate_expr, file_schema.clone()).ok()
+        });
+        let parquet_scan = ParquetExec::new(
+            vec![ParquetPartition::new(vec![file.path.clone()], statistics)],
+            file_schema,
+            Some(file_projection),
+            predicate_builder,
+            self.batch_size,
+            self.limit,
+        );
+        Ok((parquet_scan, columns))
+    }
+}
+
+/// Returns the statistics of the data file from the stats of its add action, keyed by the names
+/// of the columns in the file.
+fn file_statistics(add: &action::Add, file_schema: &ArrowSchema) -> Statistics {
+    if let Ok(Some(statistics)) = add.get_stats() {
+        Statistics {
+            num_rows: Some(statistics.num_records as usize),
+            total_byte_size: Some(add.size as usize),
+            column_statistics: Some(
+                file_schema
+                    .fields()
+                    .iter()
+                    .map(|field| ColumnStatistics {
+                        null_count: statistics
+                            .null_count
+                            .get(field.name())
+                            .and_then(|f| f.as_value().map(|v| v as usize)),
+                        max_value: statistics
+                            .max_values
+                            .get(field.name())
+                            .and_then(|f| to_scalar_value(f.as_value()?)),
+                        min_value: statistics
+                            .min_values
+                            .get(field.name())
+                            .and_then(|f| to_scalar_value(f.as_value()?)),
+                        distinct_count: None, // TODO: distinct
+                    })
+                    .collect(),
+            ),
+        }
+    } else {
+        Statistics::default()
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for DeltaScan {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.files.len())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        // the Parquet scans of the data files are only created on execution
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> datafusion::error::Result<Arc<dyn ExecutionPlan>> {
+        if children.is_empty() {
+            Ok(Arc::new(self.clone()))
+        } else {
+            Err(DataFusionError::Internal(format!(
+                "Children cannot be replaced in {:?}",
+                self
+            )))
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> datafusion::error::Result<SendableRecordBatchStream> {
+        let file = self.files.get(partition).ok_or_else(|| {
+            DataFusionError::Internal(format!("Invalid partition {} of {:?}", partition, self))
+        })?;
+        let (parquet_scan, columns) = self.file_scan(file).await?;
+        Ok(Box::pin(DeltaScanStream {
+            input: parquet_scan.execute(0).await?,
+            schema: self.schema.clone(),
+            columns,
+            partition_values: file.partition_values.clone(),
+        }))
+    }
+}
+
+/// Stream of the rows read from one data file, with the partition and missing columns added.
+struct DeltaScanStream {
+    input: SendableRecordBatchStream,
+    schema: SchemaRef,
+    columns: Vec<ScanColumn>,
+    partition_values: HashMap<String, String>,
+}
+
+impl DeltaScanStream {
+    fn add_columns(&self, batch: &RecordBatch) -> ArrowResult<RecordBatch> {
+        let num_rows = batch.num_rows();
+        let columns = self
+            .columns
+            .iter()
+            .zip(self.schema.fields())
+            .map(|(column, output_field)| match column {
+                // files written before a column was widened hold narrower values
+                ScanColumn::File(i, file_column) => column_mapping::logical_array(
+                    batch.column(*i),
+                    file_column,
+                    output_field.data_type(),
+                ),
+                ScanColumn::PartitionValue(field) => delta::partition_value_array(
+                    self.partition_values.get(field.name()),
+                    field.data_type(),
+                    num_rows,
+                ),
+                ScanColumn::Missing(field) => Ok(new_null_array(field.data_type(), num_rows)),
+            })
+            .collect::<ArrowResult<Vec<ArrayRef>>>()?;
+        RecordBatch::try_new(self.schema.clone(), columns)
+    }
+}
+
+impl Stream for DeltaScanStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.input.poll_next_unpin(cx);
+        poll.map(|batch| batch.map(|batch| self.add_columns(&batch?)))
+    }
+}
+
+impl RecordBatchStream for DeltaScanStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
@@ -0,0 +1,302 @@
+//! Generated columns of Delta tables.
+//!
+//! The values of a generated column are computed from the other columns of the same row by the
+//! SQL expression stored in the `delta.generationExpression` metadata of its schema field, e.g.
+//! an `event_date` column generated by `CAST(event_ts AS DATE)`. Writers compute the values of
+//! generated columns missing from the written rows, or null in them, and reject rows whose values
+//! differ from the computed ones. Filters on the column a date partition column is generated
+//! from are turned into filters on the partition column by the DataFusion scans. Expressions are
+//! evaluated with DataFusion, writing to a table with generated columns therefore requires the
+//! `datafusion-ext` feature.
+
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use serde_json::Value;
+
+use crate::schema::{Schema, SchemaField};
+use crate::{DeltaTableError, DeltaTableMetaData};
+
+/// Field metadata key holding the generation expression of a column.
+pub const GENERATION_EXPRESSION: &str = "delta.generationExpression";
+
+/// A generated column of a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedColumn {
+    /// The name of the column.
+    pub name: String,
+    /// The SQL expression computing the value of the column from the other columns of the row.
+    pub expression: String,
+}
+
+impl GeneratedColumn {
+    /// Returns an expression that is true for rows holding the generated value of the column,
+    /// where null values are equal to each other.
+    pub(crate) fn validation_expression(&self) -> String {
+        format!(
+            "({0} IS NULL AND ({1}) IS NULL) OR {0} = ({1})",
+            self.name, self.expression
+        )
+    }
+
+    /// Returns the column the generated column is computed from, if it is generated by
+    /// `CAST(<column> AS DATE)`. Such columns preserve the order of their source column, which
+    /// allows filters on the source column to be turned into filters on the generated column.
+    pub(crate) fn date_source_column(&self) -> Option<&str> {
+        let expression = self.expression.trim();
+        let upper = expression.to_ascii_uppercase();
+        if !upper.starts_with("CAST(") || !upper.ends_with(')') {
+            return None;
+        }
+        let inner = &expression["CAST(".len()..expression.len() - 1];
+        let as_index = inner.to_ascii_uppercase().rfind(" AS ")?;
+        if inner[as_index + " AS ".len()..].trim().to_ascii_uppercase() != "DATE" {
+            return None;
+        }
+        let column = inner[..as_index].trim().trim_matches('`');
+        if column.is_empty() || !column.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        Some(column)
+    }
+}
+
+impl SchemaField {
+    /// Returns the generation expression of the field, if it is a generated column.
+    pub fn generation_expression(&self) -> Option<&str> {
+        self.get_metadata()
+            .get(GENERATION_EXPRESSION)
+            .and_then(|expression| expression.as_str())
+    }
+}
+
+impl Schema {
+    /// Returns the generated columns of the schema, in the order of the fields. Fails if the
+    /// metadata of a field holds an invalid generation expression.
+    pub fn get_generated_columns(&self) -> Result<Vec<GeneratedColumn>, DeltaTableError> {
+        self.get_fields()
+            .iter()
+            .filter_map(|field| {
+                let expression = field.get_metadata().get(GENERATION_EXPRESSION)?;
+                Some(match expression {
+                    Value::String(expression) if !expression.trim().is_empty() => {
+                        Ok(GeneratedColumn {
+                            name: field.get_name().to_string(),
+                            expression: expression.clone(),
+                        })
+                    }
+                    _ => Err(DeltaTableError::GeneratedColumn {
+                        msg: format!(
+                            "Invalid generation expression of column `{}`: {}",
+                            field.get_name(),
+                            expression
+                        ),
+                    }),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Computes the generated columns missing from the rows written to a table.
+#[derive(Debug, Clone)]
+pub(crate) struct ColumnGenerator {
+    /// Arrow schema of the table, holding the types of the generated columns
+    schema: SchemaRef,
+    columns: Vec<GeneratedColumn>,
+}
+
+impl ColumnGenerator {
+    /// Creates a generator for the generated columns of the table with the given Arrow schema.
+    pub(crate) fn try_new(
+        metadata: &DeltaTableMetaData,
+        schema: SchemaRef,
+    ) -> Result<Self, DeltaTableError> {
+        Ok(Self {
+            schema,
+            columns: metadata.schema.get_generated_columns()?,
+        })
+    }
+
+    /// Returns the generated columns of the table.
+    pub(crate) fn columns(&self) -> &[GeneratedColumn] {
+        &self.columns
+    }
+
+    /// Returns the batch with the generated columns it misses appended, computed from its other
+    /// columns and cast to the types of the table. The null values of generated columns present
+    /// in the batch are replaced by the computed ones, row by row. Their other values are left
+    /// untouched and checked by the `DataChecker` of the table.
+    pub(crate) async fn generate(
+        &self,
+        batch: &RecordBatch,
+    ) -> Result<RecordBatch, DeltaTableError> {
+        let missing: Vec<&GeneratedColumn> = self
+            .columns
+            .iter()
+            .filter(|column| match batch.schema().index_of(&column.name) {
+                Ok(i) => batch.column(i).null_count() > 0,
+                Err(_) => true,
+            })
+            .collect();
+        if missing.is_empty() {
+            return Ok(batch.clone());
+        }
+        self.generate_columns(batch, &missing).await
+    }
+
+    #[cfg(feature = "datafusion-ext")]
+    async fn generate_columns(
+        &self,
+        batch: &RecordBatch,
+        missing: &[&GeneratedColumn],
+    ) -> Result<RecordBatch, DeltaTableError> {
+        use arrow::array::{new_null_array, Array, ArrayRef, UInt32Array};
+        use arrow::compute::{cast, concat, take};
+        use arrow::datatypes::Schema as ArrowSchema;
+        use datafusion::datasource::MemTable;
+        use datafusion::execution::context::ExecutionContext;
+        use std::sync::Arc;
+
+        let mut ctx = ExecutionContext::new();
+        let table = MemTable::try_new(batch.schema(), vec![vec![batch.clone()]])?;
+        ctx.register_table("data", Arc::new(table))?;
+
+        let mut fields = batch.schema().fields().clone();
+        let mut columns = batch.columns().to_vec();
+        for (i, column) in missing.iter().enumerate() {
+            let field = self.schema.field_with_name(&column.name)?;
+            let sql = format!("SELECT {} AS generated_{} FROM data", column.expression, i);
+            let batches = ctx
+                .sql(&sql)
+                .map_err(|e| DeltaTableError::GeneratedColumn {
+                    msg: format!(
+                        "Generated column `{}` ({}) cannot be computed: {}",
+                        column.name, column.expression, e
+                    ),
+                })?
+                .collect()
+                .await?;
+            let values: Vec<&dyn Array> = batches.iter().map(|b| b.column(0).as_ref()).collect();
+            let values: ArrayRef = if values.is_empty() {
+                new_null_array(field.data_type(), 0)
+            } else {
+                concat(&values)?
+            };
+            let values = cast(&values, field.data_type())?;
+            match batch.schema().index_of(&column.name) {
+                Ok(j) => {
+                    // keep the supplied values and take the computed ones for the null rows
+                    let supplied = batch.column(j);
+                    let indices: Vec<u32> = (0..batch.num_rows())
+                        .map(|row| {
+                            if supplied.is_valid(row) {
+                                row as u32
+                            } else {
+                                (batch.num_rows() + row) as u32
+                            }
+                        })
+                        .collect();
+                    let supplied = cast(supplied, field.data_type())?;
+                    let candidates = concat(&[supplied.as_ref(), values.as_ref()])?;
+                    columns[j] = take(candidates.as_ref(), &UInt32Array::from(indices), None)?;
+                    fields[j] = field.clone();
+                }
+                Err(_) => {
+                    columns.push(values);
+                    fields.push(field.clone());
+                }
+            }
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(ArrowSchema::new(fields)),
+            columns,
+        )?)
+    }
+
+    #[cfg(not(feature = "datafusion-ext"))]
+    async fn generate_columns(
+        &self,
+        _batch: &RecordBatch,
+        missing: &[&GeneratedColumn],
+    ) -> Result<RecordBatch, DeltaTableError> {
+        Err(DeltaTableError::GeneratedColumn {
+            msg: format!(
+                "Generated column `{}` cannot be computed without the datafusion-ext feature",
+                missing[0].name
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::SchemaDataType;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn generated(name: &str, expression: Value) -> SchemaField {
+        let mut metadata = HashMap::new();
+        metadata.insert(GENERATION_EXPRESSION.to_string(), expression);
+        SchemaField::new(
+            name.to_string(),
+            SchemaDataType::primitive("date".to_string()),
+            true,
+            metadata,
+        )
+    }
+
+    #[test]
+    fn generated_columns_are_read_from_field_metadata() {
+        let schema = Schema::new(vec![
+            SchemaField::new(
+                "event_ts".to_string(),
+                SchemaDataType::primitive("timestamp".to_string()),
+                true,
+                HashMap::new(),
+            ),
+            generated("event_date", json!("CAST(event_ts AS DATE)")),
+        ]);
+
+        assert_eq!(
+            schema.get_generated_columns().unwrap(),
+            vec![GeneratedColumn {
+                name: "event_date".to_string(),
+                expression: "CAST(event_ts AS DATE)".to_string(),
+            }]
+        );
+        assert_eq!(schema.get_fields()[0].generation_expression(), None);
+
+        let schema = Schema::new(vec![generated("event_date", json!(1))]);
+        assert!(matches!(
+            schema.get_generated_columns(),
+            Err(DeltaTableError::GeneratedColumn { .. })
+        ));
+    }
+
+    #[test]
+    fn date_source_column() {
+        let column = |expression: &str| GeneratedColumn {
+            name: "event_date".to_string(),
+            expression: expression.to_string(),
+        };
+
+        assert_eq!(
+            column("CAST(event_ts AS DATE)").date_source_column(),
+            Some("event_ts")
+        );
+        assert_eq!(
+            column(" cast( `event_ts` as date ) ").date_source_column(),
+            Some("event_ts")
+        );
+        assert_eq!(
+            column("CAST(event_ts AS STRING)").date_source_column(),
+            None
+        );
+        assert_eq!(
+            column("CAST(event_ts + 1 AS DATE)").date_source_column(),
+            None
+        );
+        assert_eq!(column("YEAR(event_ts)").date_source_column(), None);
+    }
+}
@@ -58,16 +58,25 @@ extern crate serde_json;
 extern crate thiserror;
 
 pub mod action;
+pub mod change_feed;
 pub mod checkpoints;
+pub mod column_mapping;
+pub mod constraints;
 mod delta;
 pub mod delta_arrow;
+pub mod generated_columns;
+pub mod optimize;
 pub mod partitions;
 mod schema;
+mod schema_validation;
+mod stats;
 pub mod storage;
 pub mod writer;
 
 #[cfg(feature = "datafusion-ext")]
 pub mod delta_datafusion;
+#[cfg(feature = "datafusion-ext")]
+pub mod merge;
 
 #[cfg(feature = "rust-dataframe-ext")]
 mod delta_dataframe;
@@ -0,0 +1,687 @@
+//! MERGE support for Delta tables: rows of a table are updated, deleted or inserted depending on
+//! whether they match the rows of a source, with the matching done by DataFusion joins.
+//!
+//! Expressions of the clauses refer to the columns of the table by their name and to the columns
+//! of the source by their name prefixed with `source.`.
+//!
+//! Example:
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//! use datafusion::logical_plan::col;
+//! use datafusion::physical_plan::SendableRecordBatchStream;
+//! use deltalake::merge::MergeBuilder;
+//!
+//! async fn upsert(table: &mut deltalake::DeltaTable, changes: SendableRecordBatchStream) {
+//!     let mut assignments = HashMap::new();
+//!     assignments.insert("value".to_string(), col("source.value"));
+//!     let mut values = assignments.clone();
+//!     values.insert("id".to_string(), col("source.id"));
+//!
+//!     MergeBuilder::new(table, changes)
+//!         .on("id", "id")
+//!         .when_matched_update(None, assignments)
+//!         .when_not_matched_insert(None, values)
+//!         .execute()
+//!         .await
+//!         .unwrap();
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, Int32Array, StringArray, UInt64Array};
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use datafusion::datasource::MemTable;
+use datafusion::execution::context::ExecutionContext;
+use datafusion::logical_plan::{col, lit, max, min, when, Expr, JoinType};
+use datafusion::physical_plan::{PhysicalExpr, SendableRecordBatchStream};
+use datafusion::scalar::ScalarValue;
+use futures::StreamExt;
+
+use crate::action::{self, DeltaOperation, MergePredicate};
+use crate::delta_datafusion::{
+    create_physical_expr, evaluate_predicate, is_true, true_expr, SqlExpr,
+};
+use crate::schema::{self, DeltaDataTypeLong, DeltaDataTypeVersion};
+use crate::writer::DataFileWriter;
+use crate::{DeltaTable, DeltaTableError, DeltaTransactionError};
+
+/// Prefix of the source columns in the expressions of merge clauses.
+pub const SOURCE_PREFIX: &str = "source.";
+
+/// Columns added to the rows of the table while merging.
+const PATH_COLUMN: &str = "__delta_rs_path";
+const ROW_ID_COLUMN: &str = "__delta_rs_row_id";
+const ACTION_COLUMN: &str = "__delta_rs_merge_action";
+
+enum MatchedAction {
+    Update(HashMap<String, Expr>),
+    Delete,
+}
+
+struct MatchedClause {
+    condition: Option<Expr>,
+    action: MatchedAction,
+}
+
+struct NotMatchedClause {
+    condition: Option<Expr>,
+    values: HashMap<String, Expr>,
+}
+
+/// Builds and executes a MERGE of a source into a Delta table.
+///
+/// Target rows are matched with source rows by the equality of the columns given to `on`. Each
+/// matched row is updated or deleted by the first `when_matched_*` clause whose condition it
+/// satisfies, and each source row without a match is inserted by the first
+/// `when_not_matched_insert` clause whose condition it satisfies. Rows no clause applies to are
+/// left as they are.
+///
+/// Only the files of the table that may contain the keys of the source according to their
+/// statistics are read, and only the ones with modified rows are rewritten. The rewritten and
+/// inserted files are committed in a single transaction, which is checked for conflicts with
+/// concurrent commits like any other transaction.
+pub struct MergeBuilder<'a> {
+    table: &'a mut DeltaTable,
+    source: SendableRecordBatchStream,
+    keys: Vec<(String, String)>,
+    matched: Vec<MatchedClause>,
+    not_matched: Vec<NotMatchedClause>,
+}
+
+impl<'a> MergeBuilder<'a> {
+    /// Creates a merge of the record batches of `source` into `table`.
+    pub fn new(table: &'a mut DeltaTable, source: SendableRecordBatchStream) -> Self {
+        Self {
+            table,
+            source,
+            keys: vec![],
+            matched: vec![],
+            not_matched: vec![],
+        }
+    }
+
+    /// Matches target and source rows by the equality of a target column with a source column.
+    /// May be called multiple times for composite keys.
+    pub fn on(mut self, target_column: &str, source_column: &str) -> Self {
+        self.keys
+            .push((target_column.to_string(), source_column.to_string()));
+        self
+    }
+
+    /// Sets the columns in `assignments` to the value of their expression for the matched rows
+    /// satisfying the condition, if any.
+    pub fn when_matched_update(
+        mut self,
+        condition: Option<Expr>,
+        assignments: HashMap<String, Expr>,
+    ) -> Self {
+        self.matched.push(MatchedClause {
+            condition,
+            action: MatchedAction::Update(assignments),
+        });
+        self
+    }
+
+    /// Deletes the matched rows satisfying the condition, if any.
+    pub fn when_matched_delete(mut self, condition: Option<Expr>) -> Self {
+        self.matched.push(MatchedClause {
+            condition,
+            action: MatchedAction::Delete,
+        });
+        self
+    }
+
+    /// Inserts a row for every source row without a match that satisfies the condition, if any.
+    /// Columns of the table missing from `values` are set to null.
+    pub fn when_not_matched_insert(
+        mut self,
+        condition: Option<Expr>,
+        values: HashMap<String, Expr>,
+    ) -> Self {
+        self.not_matched
+            .push(NotMatchedClause { condition, values });
+        self
+    }
+
+    /// Executes the merge against the latest version of the table and returns the version of
+    /// the commit, or the current version if nothing changed.
+    ///
+    /// Fails with a `DeltaTableError::SchemaMismatch` if a clause refers to a column missing from
+    /// the table, and with a `DeltaTableError::Generic` if multiple source rows match the same
+    /// target row a clause applies to.
+    pub async fn execute(mut self) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        if self.keys.is_empty() {
+            return Err(DeltaTableError::Generic(
+                "MERGE requires at least one pair of columns to match rows on".to_string(),
+            )
+            .into());
+        }
+
+        self.table.update_incremental().await?;
+        let table_schema =
+            <ArrowSchema as TryFrom<&schema::Schema>>::try_from(self.table.get_schema()?)
+                .map_err(DeltaTableError::from)?;
+        self.validate_columns(&table_schema)?;
+
+        let source_batches = self.collect_source().await?;
+        let num_source_rows: usize = source_batches.iter().map(|b| b.num_rows()).sum();
+        if num_source_rows == 0 {
+            return Ok(self.table.version);
+        }
+
+        let mut ctx = ExecutionContext::new();
+        let source_schema = source_batches[0].schema();
+        let source = ctx
+            .read_table(Arc::new(
+                MemTable::try_new(source_schema, vec![source_batches])
+                    .map_err(DeltaTableError::from)?,
+            ))
+            .map_err(DeltaTableError::from)?;
+
+        // only files that may contain the keys of the source take part in the join
+        let mut key_range = vec![];
+        for (target_key, source_key) in &self.keys {
+            let aggregate = source
+                .aggregate(
+                    &[],
+                    &[
+                        min(col(&source_column(source_key))),
+                        max(col(&source_column(source_key))),
+                    ],
+                )
+                .map_err(DeltaTableError::from)?
+                .collect()
+                .await
+                .map_err(DeltaTableError::from)?;
+            let min_value = ScalarValue::try_from_array(aggregate[0].column(0), 0)
+                .map_err(DeltaTableError::from)?;
+            let max_value = ScalarValue::try_from_array(aggregate[0].column(1), 0)
+                .map_err(DeltaTableError::from)?;
+            key_range.push(
+                col(target_key)
+                    .gt_eq(Expr::Literal(min_value))
+                    .and(col(target_key).lt_eq(Expr::Literal(max_value))),
+            );
+        }
+        let key_range = key_range
+            .into_iter()
+            .fold(true_expr(), |acc, expr| acc.and(expr));
+        let candidate_files: Vec<action::Add> = self
+            .table
+            .files_matching_predicate(&key_range)?
+            .into_iter()
+            .cloned()
+            .collect();
+
+        let target_schema = Arc::new(with_merge_columns(&table_schema));
+        let mut target_batches = vec![];
+        let mut next_row_id = 0u64;
+        for add in &candidate_files {
+            for batch in self.table.read_data_file(add).await? {
+                let num_rows = batch.num_rows();
+                let mut columns = batch.columns().to_vec();
+                columns.push(Arc::new(StringArray::from(vec![
+                    add.path.as_str();
+                    num_rows
+                ])));
+                columns.push(Arc::new(UInt64Array::from(
+                    (next_row_id..next_row_id + num_rows as u64).collect::<Vec<u64>>(),
+                )));
+                next_row_id += num_rows as u64;
+                target_batches.push(
+                    RecordBatch::try_new(target_schema.clone(), columns)
+                        .map_err(DeltaTableError::from)?,
+                );
+            }
+        }
+        let target = ctx
+            .read_table(Arc::new(
+                MemTable::try_new(target_schema, vec![target_batches])
+                    .map_err(DeltaTableError::from)?,
+            ))
+            .map_err(DeltaTableError::from)?;
+
+        let target_keys: Vec<&str> = self.keys.iter().map(|(t, _)| t.as_str()).collect();
+        let source_keys: Vec<String> = self.keys.iter().map(|(_, s)| source_column(s)).collect();
+        let source_keys: Vec<&str> = source_keys.iter().map(|s| s.as_str()).collect();
+
+        let mut writer = DataFileWriter::try_new(self.table)?;
+        let table_schema = Arc::new(table_schema);
+
+        // every target row of the candidate files, joined with its matching source rows
+        let joined = target
+            .join(source.clone(), JoinType::Left, &target_keys, &source_keys)
+            .map_err(DeltaTableError::from)?
+            .collect()
+            .await
+            .map_err(DeltaTableError::from)?;
+        let matched = self.apply_matched_clauses(&joined, source_keys[0], &table_schema)?;
+        for batch in matched.batches.iter().filter(|b| b.num_rows() > 0) {
+            writer.write(batch).await?;
+        }
+
+        // every source row, joined with its matching target rows
+        let mut num_inserted_rows = 0;
+        if !self.not_matched.is_empty() {
+            let joined = target
+                .join(source, JoinType::Right, &target_keys, &source_keys)
+                .map_err(DeltaTableError::from)?
+                .collect()
+                .await
+                .map_err(DeltaTableError::from)?;
+            for batch in self.apply_not_matched_clauses(&joined, &table_schema)? {
+                num_inserted_rows += batch.num_rows();
+                if batch.num_rows() > 0 {
+                    writer.write(&batch).await?;
+                }
+            }
+        }
+
+        let added_files = writer.close().await?;
+        let removed_files: Vec<action::Add> = candidate_files
+            .iter()
+            .filter(|add| matched.touched_files.contains(&add.path))
+            .cloned()
+            .collect();
+        let read_files = candidate_files.iter().map(|add| add.path.clone()).collect();
+
+        let metrics = [
+            ("numSourceRows", num_source_rows as DeltaDataTypeLong),
+            (
+                "numTargetRowsInserted",
+                num_inserted_rows as DeltaDataTypeLong,
+            ),
+            ("numTargetRowsUpdated", matched.num_updated_rows),
+            ("numTargetRowsDeleted", matched.num_deleted_rows),
+            ("numTargetRowsCopied", matched.num_copied_rows),
+            (
+                "numTargetFilesAdded",
+                added_files.len() as DeltaDataTypeLong,
+            ),
+            (
+                "numTargetFilesRemoved",
+                removed_files.len() as DeltaDataTypeLong,
+            ),
+        ];
+        let operation = self.operation();
+        self.table
+            .commit_rewrite(read_files, removed_files, added_files, operation, &metrics)
+            .await
+    }
+
+    fn validate_columns(&self, table_schema: &ArrowSchema) -> Result<(), DeltaTableError> {
+        let target_columns = self
+            .keys
+            .iter()
+            .map(|(t, _)| t)
+            .chain(self.matched.iter().flat_map(|c| match &c.action {
+                MatchedAction::Update(assignments) => assignments.keys().collect::<Vec<_>>(),
+                MatchedAction::Delete => vec![],
+            }))
+            .chain(self.not_matched.iter().flat_map(|c| c.values.keys()));
+        for column in target_columns {
+            if table_schema.field_with_name(column).is_err() {
+                return Err(DeltaTableError::SchemaMismatch {
+                    msg: format!(
+                        "Cannot merge into column {} missing from the table schema",
+                        column
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the source, with its columns renamed to be prefixed by `source.`.
+    async fn collect_source(&mut self) -> Result<Vec<RecordBatch>, DeltaTableError> {
+        let schema = self.source.schema();
+        let schema: SchemaRef = Arc::new(ArrowSchema::new(
+            schema
+                .fields()
+                .iter()
+                .map(|f| Field::new(&source_column(f.name()), f.data_type().clone(), true))
+                .collect(),
+        ));
+        if let Some((_, key)) = self
+            .keys
+            .iter()
+            .find(|(_, key)| schema.field_with_name(&source_column(key)).is_err())
+        {
+            return Err(DeltaTableError::Generic(format!(
+                "Column {} to match rows on is missing from the source",
+                key
+            )));
+        }
+
+        let mut batches = vec![];
+        while let Some(batch) = self.source.next().await {
+            let batch = batch?;
+            batches.push(RecordBatch::try_new(
+                schema.clone(),
+                batch.columns().to_vec(),
+            )?);
+        }
+        Ok(batches)
+    }
+
+    /// Applies the matched clauses to the target rows of the left join. Returns the rows of the
+    /// files with updated or deleted rows, which replace these files.
+    fn apply_matched_clauses(
+        &self,
+        joined: &[RecordBatch],
+        source_key: &str,
+        table_schema: &SchemaRef,
+    ) -> Result<MatchedRows, DeltaTableError> {
+        // the index of the clause applied to every row, or -1 if none applies
+        let mut actions = vec![];
+        let mut matches_per_row: HashMap<u64, (usize, bool)> = HashMap::new();
+        let mut touched_files = HashSet::new();
+        for batch in joined {
+            let schema = batch.schema();
+            let key_values = batch.column(schema.index_of(source_key)?);
+            let paths = string_column(batch, PATH_COLUMN)?;
+            let row_ids = row_id_column(batch)?;
+            let conditions = self
+                .matched
+                .iter()
+                .map(|c| condition_values(c.condition.as_ref(), batch))
+                .collect::<Result<Vec<Option<ArrayRef>>, DeltaTableError>>()?;
+
+            let batch_actions: Int32Array = (0..batch.num_rows())
+                .map(|row| {
+                    if key_values.is_null(row) {
+                        return Some(-1);
+                    }
+                    let action = conditions
+                        .iter()
+                        .position(|c| c.as_ref().map_or(true, |c| is_true(c, row)))
+                        .map_or(-1, |i| i as i32);
+                    let entry = matches_per_row.entry(row_ids.value(row)).or_default();
+                    entry.0 += 1;
+                    entry.1 |= action >= 0;
+                    if action >= 0 {
+                        touched_files.insert(paths.value(row).to_string());
+                    }
+                    Some(action)
+                })
+                .collect();
+            actions.push(batch_actions);
+        }
+
+        if matches_per_row
+            .values()
+            .any(|(count, modified)| *count > 1 && *modified)
+        {
+            return Err(DeltaTableError::Generic(
+                "Cannot perform MERGE as multiple source rows matched and attempted to modify the \
+                 same target row"
+                    .to_string(),
+            ));
+        }
+
+        let deletes: Vec<i32> = self
+            .matched
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| matches!(c.action, MatchedAction::Delete))
+            .map(|(i, _)| i as i32)
+            .collect();
+        let generated_columns = self.table.get_schema()?.get_generated_columns()?;
+        let projections: Vec<Expr> = table_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let generated = generated_columns.iter().any(|c| &c.name == field.name());
+                let mut case = None;
+                for (i, clause) in self.matched.iter().enumerate() {
+                    if let MatchedAction::Update(assignments) = &clause.action {
+                        let value = match assignments.get(field.name()) {
+                            Some(value) => Some(cast(value.clone(), field.data_type())),
+                            // generated values of updated rows are computed again by the writer
+                            None if generated => Some(null(field.data_type())),
+                            None => None,
+                        };
+                        if let Some(value) = value {
+                            let condition = col(ACTION_COLUMN).eq(lit(i as i32));
+                            case = Some(match case {
+                                None => when(condition, value),
+                                Some(case) => case.when(condition, value),
+                            });
+                        }
+                    }
+                }
+                match case {
+                    Some(case) => Ok(case.otherwise(col(field.name()))?),
+                    None => Ok(col(field.name())),
+                }
+            })
+            .collect::<Result<Vec<Expr>, DeltaTableError>>()?;
+
+        let mut rows = MatchedRows {
+            batches: vec![],
+            touched_files,
+            num_updated_rows: 0,
+            num_deleted_rows: 0,
+            num_copied_rows: 0,
+        };
+        let mut written_row_ids = HashSet::new();
+        for (batch, actions) in joined.iter().zip(actions) {
+            let paths = string_column(batch, PATH_COLUMN)?;
+            let row_ids = row_id_column(batch)?;
+            let keep: BooleanArray = (0..batch.num_rows())
+                .map(|row| {
+                    let action = actions.value(row);
+                    if !rows.touched_files.contains(paths.value(row)) {
+                        Some(false)
+                    } else if deletes.contains(&action) {
+                        rows.num_deleted_rows += 1;
+                        Some(false)
+                    } else if !written_row_ids.insert(row_ids.value(row)) {
+                        // a target row joined with multiple source rows no clause applies to
+                        Some(false)
+                    } else {
+                        if action >= 0 {
+                            rows.num_updated_rows += 1;
+                        } else {
+                            rows.num_copied_rows += 1;
+                        }
+                        Some(true)
+                    }
+                })
+                .collect();
+
+            let batch = with_action_column(batch, actions)?;
+            let batch = filter_record_batch(&batch, &keep)?;
+            rows.batches
+                .push(project(&batch, &projections, table_schema)?);
+        }
+
+        Ok(rows)
+    }
+
+    /// Applies the not matched clauses to the source rows of the right join. Returns the rows to
+    /// insert.
+    fn apply_not_matched_clauses(
+        &self,
+        joined: &[RecordBatch],
+        table_schema: &SchemaRef,
+    ) -> Result<Vec<RecordBatch>, DeltaTableError> {
+        let projections: Vec<Expr> = table_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let mut case = None;
+                for (i, clause) in self.not_matched.iter().enumerate() {
+                    let condition = col(ACTION_COLUMN).eq(lit(i as i32));
+                    let value = match clause.values.get(field.name()) {
+                        Some(value) => cast(value.clone(), field.data_type()),
+                        None => null(field.data_type()),
+                    };
+                    case = Some(match case {
+                        None => when(condition, value),
+                        Some(case) => case.when(condition, value),
+                    });
+                }
+                match case {
+                    Some(case) => Ok(case.otherwise(null(field.data_type()))?),
+                    None => Ok(null(field.data_type())),
+                }
+            })
+            .collect::<Result<Vec<Expr>, DeltaTableError>>()?;
+
+        joined
+            .iter()
+            .map(|batch| {
+                let paths = string_column(batch, PATH_COLUMN)?;
+                let conditions = self
+                    .not_matched
+                    .iter()
+                    .map(|c| condition_values(c.condition.as_ref(), batch))
+                    .collect::<Result<Vec<Option<ArrayRef>>, DeltaTableError>>()?;
+                let actions: Int32Array = (0..batch.num_rows())
+                    .map(|row| {
+                        if paths.is_valid(row) {
+                            return Some(-1);
+                        }
+                        let action = conditions
+                            .iter()
+                            .position(|c| c.as_ref().map_or(true, |c| is_true(c, row)));
+                        Some(action.map_or(-1, |i| i as i32))
+                    })
+                    .collect();
+                let keep: BooleanArray = actions.iter().map(|a| a.map(|a| a >= 0)).collect();
+
+                let batch = with_action_column(batch, actions)?;
+                let batch = filter_record_batch(&batch, &keep)?;
+                project(&batch, &projections, table_schema)
+            })
+            .collect()
+    }
+
+    fn operation(&self) -> DeltaOperation {
+        let predicate = self
+            .keys
+            .iter()
+            .map(|(t, s)| format!("{} = {}", t, source_column(s)))
+            .collect::<Vec<String>>()
+            .join(" AND ");
+        let merge_predicate = |action_type: &str, condition: &Option<Expr>| MergePredicate {
+            action_type: action_type.to_string(),
+            predicate: condition.as_ref().map(|c| SqlExpr(c).to_string()),
+        };
+
+        DeltaOperation::Merge {
+            predicate: Some(predicate),
+            matchedPredicates: self
+                .matched
+                .iter()
+                .map(|c| match c.action {
+                    MatchedAction::Update(_) => merge_predicate("update", &c.condition),
+                    MatchedAction::Delete => merge_predicate("delete", &c.condition),
+                })
+                .collect(),
+            notMatchedPredicates: self
+                .not_matched
+                .iter()
+                .map(|c| merge_predicate("insert", &c.condition))
+                .collect(),
+        }
+    }
+}
+
+/// The rows replacing the files with rows modified by the matched clauses.
+struct MatchedRows {
+    batches: Vec<RecordBatch>,
+    touched_files: HashSet<String>,
+    num_updated_rows: DeltaDataTypeLong,
+    num_deleted_rows: DeltaDataTypeLong,
+    num_copied_rows: DeltaDataTypeLong,
+}
+
+fn source_column(name: &str) -> String {
+    format!("{}{}", SOURCE_PREFIX, name)
+}
+
+fn cast(expr: Expr, data_type: &DataType) -> Expr {
+    Expr::Cast {
+        expr: Box::new(expr),
+        data_type: data_type.clone(),
+    }
+}
+
+fn null(data_type: &DataType) -> Expr {
+    cast(Expr::Literal(ScalarValue::Utf8(None)), data_type)
+}
+
+/// Returns the schema of the table with the nullable columns identifying the file and the row
+/// every target row is read from.
+fn with_merge_columns(schema: &ArrowSchema) -> ArrowSchema {
+    let mut fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .map(|f| Field::new(f.name(), f.data_type().clone(), true))
+        .collect();
+    fields.push(Field::new(PATH_COLUMN, DataType::Utf8, true));
+    fields.push(Field::new(ROW_ID_COLUMN, DataType::UInt64, true));
+    ArrowSchema::new(fields)
+}
+
+fn with_action_column(
+    batch: &RecordBatch,
+    actions: Int32Array,
+) -> Result<RecordBatch, DeltaTableError> {
+    let mut fields = batch.schema().fields().clone();
+    fields.push(Field::new(ACTION_COLUMN, DataType::Int32, true));
+    let mut columns = batch.columns().to_vec();
+    columns.push(Arc::new(actions));
+    Ok(RecordBatch::try_new(
+        Arc::new(ArrowSchema::new(fields)),
+        columns,
+    )?)
+}
+
+fn condition_values(
+    condition: Option<&Expr>,
+    batch: &RecordBatch,
+) -> Result<Option<ArrayRef>, DeltaTableError> {
+    condition
+        .map(|c| {
+            let c = create_physical_expr(c, &batch.schema())?;
+            evaluate_predicate(c.as_ref(), batch)
+        })
+        .transpose()
+}
+
+fn project(
+    batch: &RecordBatch,
+    projections: &[Expr],
+    schema: &SchemaRef,
+) -> Result<RecordBatch, DeltaTableError> {
+    let columns = projections
+        .iter()
+        .map(|expr| {
+            let expr: Arc<dyn PhysicalExpr> = create_physical_expr(expr, &batch.schema())?;
+            Ok(expr.evaluate(batch)?.into_array(batch.num_rows()))
+        })
+        .collect::<Result<Vec<ArrayRef>, DeltaTableError>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+fn string_column<'b>(
+    batch: &'b RecordBatch,
+    name: &str,
+) -> Result<&'b StringArray, DeltaTableError> {
+    let column = batch.column(batch.schema().index_of(name)?);
+    Ok(column.as_any().downcast_ref::<StringArray>().unwrap())
+}
+
+fn row_id_column(batch: &RecordBatch) -> Result<&UInt64Array, DeltaTableError> {
+    let column = batch.column(batch.schema().index_of(ROW_ID_COLUMN)?);
+    Ok(column.as_any().downcast_ref::<UInt64Array>().unwrap())
+}
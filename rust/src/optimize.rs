@@ -0,0 +1,394 @@
+//! OPTIMIZE support for Delta tables: small data files of a partition are compacted into larger
+//! ones.
+//!
+//! Besides plain bin-packing, the rows of a partition can be clustered along a Z-order curve
+//! over several columns while they are rewritten, which keeps the min and max statistics of the
+//! written files narrow on all of these columns at once.
+//!
+//! Compaction only rearranges existing rows, so the `remove` and `add` actions it commits are
+//! marked with `dataChange = false` and streaming readers of the table can skip the commit.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use arrow::array::{build_compare, Array, ArrayRef, UInt32Array};
+use arrow::compute::{concat, sort_to_indices, take, SortOptions};
+use arrow::record_batch::RecordBatch;
+
+use crate::action::{self, Action, DeltaOperation};
+use crate::delta::{matches_partition_filters, validate_partition_filters};
+use crate::partitions::{filters_to_predicate, PartitionFilter};
+use crate::schema::{DeltaDataTypeLong, DeltaDataTypeTimestamp};
+use crate::writer::{DataFileWriter, DEFAULT_TARGET_FILE_SIZE};
+use crate::{DeltaTable, DeltaTableError, DeltaTransactionError};
+
+/// Number of rows of the batches the Z-ordered rows are written in, so that the written files can
+/// be rolled over at the target size.
+const ZORDER_BATCH_SIZE: usize = 8192;
+
+/// Metrics of an optimize run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Metrics {
+    /// Number of files added by the compaction.
+    pub num_files_added: DeltaDataTypeLong,
+    /// Number of files removed by the compaction.
+    pub num_files_removed: DeltaDataTypeLong,
+    /// Total size in bytes of the added files.
+    pub num_bytes_added: DeltaDataTypeLong,
+    /// Total size in bytes of the removed files.
+    pub num_bytes_removed: DeltaDataTypeLong,
+    /// Number of partitions in which files were compacted.
+    pub num_partitions_optimized: DeltaDataTypeLong,
+    /// Number of files matching the partition filters.
+    pub total_considered_files: DeltaDataTypeLong,
+    /// Number of matching files that were left as they are.
+    pub total_files_skipped: DeltaDataTypeLong,
+}
+
+impl DeltaTable {
+    /// Compacts the small data files of the partitions matching the given filters, or of the
+    /// whole table if no filter is given.
+    ///
+    /// Within every partition, the files smaller than `target_size` bytes (128 MiB by default)
+    /// are grouped into bins of up to `target_size` bytes and each bin is rewritten as one file.
+    /// Files that already reach the target size and bins holding a single file are skipped.
+    ///
+    /// Nothing is committed if there is nothing to compact.
+    pub async fn optimize(
+        &mut self,
+        filters: &[PartitionFilter<'_, &str>],
+        target_size: Option<usize>,
+    ) -> Result<Metrics, DeltaTransactionError> {
+        self.optimize_files(filters, &[], target_size).await
+    }
+
+    /// Rewrites all data files of the partitions matching the given filters, or of the whole
+    /// table if no filter is given, with the rows of every partition sorted along a Z-order
+    /// curve over the given columns. The sorted rows are written into files of up to
+    /// `target_size` bytes (128 MiB by default).
+    ///
+    /// The Z-order columns have to be non-partition columns of the table. Since the rows of a
+    /// partition are sorted as a whole, all of them are held in memory while it is rewritten.
+    pub async fn optimize_zorder(
+        &mut self,
+        filters: &[PartitionFilter<'_, &str>],
+        columns: &[&str],
+        target_size: Option<usize>,
+    ) -> Result<Metrics, DeltaTransactionError> {
+        let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        if columns.is_empty() {
+            return Err(DeltaTableError::InvalidZOrder {
+                msg: "at least one column is required".to_string(),
+            }
+            .into());
+        }
+        self.optimize_files(filters, &columns, target_size).await
+    }
+
+    async fn optimize_files(
+        &mut self,
+        filters: &[PartitionFilter<'_, &str>],
+        zorder_columns: &[String],
+        target_size: Option<usize>,
+    ) -> Result<Metrics, DeltaTransactionError> {
+        self.update_incremental().await?;
+        let partition_columns = &self.get_metadata()?.partition_columns;
+        validate_partition_filters(partition_columns, filters)?;
+        let schema = self.get_schema()?;
+        for column in zorder_columns {
+            if partition_columns.contains(column) || schema.get_field_with_name(column).is_none() {
+                return Err(DeltaTableError::InvalidZOrder {
+                    msg: format!("{} is not a non-partition column of the table", column),
+                }
+                .into());
+            }
+        }
+        let target_size = target_size.unwrap_or(DEFAULT_TARGET_FILE_SIZE);
+        let zorder = !zorder_columns.is_empty();
+
+        let mut metrics = Metrics::default();
+        let mut partitions: BTreeMap<Vec<(String, String)>, Vec<action::Add>> = BTreeMap::new();
+        let metadata = self.get_metadata()?;
+        for add in self.get_active_add_actions() {
+            if !matches_partition_filters(metadata, &add.partition_values, filters) {
+                continue;
+            }
+            metrics.total_considered_files += 1;
+            if !zorder && add.size as usize >= target_size {
+                continue;
+            }
+            let mut partition: Vec<(String, String)> = add
+                .partition_values
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            partition.sort();
+            partitions.entry(partition).or_default().push(add.clone());
+        }
+
+        let mut removed_files = Vec::new();
+        let mut added_files = Vec::new();
+        for (_, files) in partitions {
+            if zorder {
+                metrics.num_partitions_optimized += 1;
+                let mut writer = DataFileWriter::try_new(self)?
+                    .with_target_file_size(target_size)
+                    .with_data_change(false);
+                let mut batches = Vec::new();
+                for add in files.iter() {
+                    batches.extend(self.read_data_file(add).await?);
+                }
+                for batch in zorder_batches(&batches, zorder_columns)? {
+                    writer.write(&batch).await?;
+                }
+                added_files.extend(writer.close().await?);
+                removed_files.extend(files);
+                continue;
+            }
+
+            let bins: Vec<Vec<action::Add>> = pack_files(files, target_size)
+                .into_iter()
+                .filter(|bin| bin.len() > 1)
+                .collect();
+            if bins.is_empty() {
+                continue;
+            }
+            metrics.num_partitions_optimized += 1;
+
+            for bin in bins {
+                let mut writer = DataFileWriter::try_new(self)?
+                    .with_target_file_size(target_size)
+                    .with_data_change(false);
+                for add in bin.iter() {
+                    for batch in self.read_data_file(add).await? {
+                        writer.write(&batch).await?;
+                    }
+                }
+                added_files.extend(writer.close().await?);
+                removed_files.extend(bin);
+            }
+        }
+
+        if removed_files.is_empty() {
+            metrics.total_files_skipped = metrics.total_considered_files;
+            return Ok(metrics);
+        }
+
+        metrics.num_files_removed = removed_files.len() as DeltaDataTypeLong;
+        metrics.num_files_added = added_files.len() as DeltaDataTypeLong;
+        metrics.num_bytes_removed = removed_files.iter().map(|add| add.size).sum();
+        metrics.num_bytes_added = added_files.iter().map(|add| add.size).sum();
+        metrics.total_files_skipped = metrics.total_considered_files - metrics.num_files_removed;
+
+        // Err should be impossible in this case since `SystemTime::now()` is always greater than `UNIX_EPOCH`
+        let deletion_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let deletion_timestamp = deletion_timestamp.as_millis() as DeltaDataTypeTimestamp;
+
+        let mut dtx = self.create_transaction(None);
+        dtx.add_read_files(removed_files.iter().map(|add| add.path.clone()).collect());
+        dtx.add_actions(
+            removed_files
+                .iter()
+                .map(|add| Action::remove(add.to_remove(deletion_timestamp, false)))
+                .collect(),
+        );
+        dtx.add_actions(added_files.into_iter().map(Action::add).collect());
+        dtx.record_operation_metric("numAddedFiles", metrics.num_files_added);
+        dtx.record_operation_metric("numRemovedBytes", metrics.num_bytes_removed);
+        dtx.record_operation_metric("numAddedBytes", metrics.num_bytes_added);
+        dtx.commit(Some(DeltaOperation::Optimize {
+            predicate: filters_to_predicate(filters),
+            zOrderBy: zorder_columns.to_vec(),
+        }))
+        .await?;
+
+        Ok(metrics)
+    }
+}
+
+/// Packs the files into bins of at most `target_size` bytes, taking the files from the smallest
+/// to the largest and starting a new bin whenever the next file does not fit anymore.
+fn pack_files(mut files: Vec<action::Add>, target_size: usize) -> Vec<Vec<action::Add>> {
+    files.sort_by_key(|add| add.size);
+
+    let mut bins = Vec::new();
+    let mut bin: Vec<action::Add> = Vec::new();
+    let mut bin_size = 0;
+    for add in files {
+        let size = add.size as usize;
+        if !bin.is_empty() && bin_size + size > target_size {
+            bins.push(std::mem::take(&mut bin));
+            bin_size = 0;
+        }
+        bin_size += size;
+        bin.push(add);
+    }
+    if !bin.is_empty() {
+        bins.push(bin);
+    }
+    bins
+}
+
+/// Sorts the rows of the batches along a Z-order curve over the given columns and returns them
+/// in batches of up to `ZORDER_BATCH_SIZE` rows.
+fn zorder_batches(
+    batches: &[RecordBatch],
+    columns: &[String],
+) -> Result<Vec<RecordBatch>, DeltaTableError> {
+    let schema = match batches.first() {
+        Some(batch) => batch.schema(),
+        None => return Ok(vec![]),
+    };
+    let arrays = (0..schema.fields().len())
+        .map(|i| {
+            let column: Vec<&dyn Array> = batches.iter().map(|b| b.column(i).as_ref()).collect();
+            concat(&column)
+        })
+        .collect::<Result<Vec<ArrayRef>, _>>()?;
+    let zorder_arrays = columns
+        .iter()
+        .map(|c| Ok(arrays[schema.index_of(c)?].clone()))
+        .collect::<Result<Vec<ArrayRef>, DeltaTableError>>()?;
+
+    zorder_indices(&zorder_arrays)?
+        .chunks(ZORDER_BATCH_SIZE)
+        .map(|chunk| {
+            let indices = UInt32Array::from(chunk.to_vec());
+            let columns = arrays
+                .iter()
+                .map(|a| take(a.as_ref(), &indices, None))
+                .collect::<Result<Vec<ArrayRef>, _>>()?;
+            Ok(RecordBatch::try_new(schema.clone(), columns)?)
+        })
+        .collect()
+}
+
+/// Returns the row indices of the arrays in the order of the Z-order curve over them.
+///
+/// Every value is replaced by its rank among the distinct values of its array, scaled to the
+/// range of `u32`, so that all arrays contribute evenly to the curve whatever their type and
+/// distribution. The key of a row interleaves the bits of its ranks, most significant first.
+fn zorder_indices(arrays: &[ArrayRef]) -> Result<Vec<u32>, DeltaTableError> {
+    let num_rows = arrays.first().map(|a| a.len()).unwrap_or(0);
+    let ranks = arrays
+        .iter()
+        .map(scaled_ranks)
+        .collect::<Result<Vec<Vec<u32>>, DeltaTableError>>()?;
+
+    let mut keys: Vec<(Vec<u8>, u32)> = (0..num_rows)
+        .map(|row| {
+            let values: Vec<u32> = ranks.iter().map(|r| r[row]).collect();
+            (interleave_bits(&values), row as u32)
+        })
+        .collect();
+    keys.sort();
+
+    Ok(keys.into_iter().map(|(_, row)| row).collect())
+}
+
+/// Returns the rank of every value among the distinct values of the array, with nulls ranked
+/// first, scaled to the range of `u32`.
+fn scaled_ranks(array: &ArrayRef) -> Result<Vec<u32>, DeltaTableError> {
+    let options = SortOptions {
+        descending: false,
+        nulls_first: true,
+    };
+    let indices = sort_to_indices(array, Some(options))?;
+    let compare = build_compare(array.as_ref(), array.as_ref())?;
+    let is_equal = |a: usize, b: usize| match (array.is_null(a), array.is_null(b)) {
+        (true, true) => true,
+        (false, false) => compare(a, b) == Ordering::Equal,
+        _ => false,
+    };
+
+    let mut ranks = vec![0u64; array.len()];
+    let mut rank = 0u64;
+    for i in 0..indices.len() {
+        let row = indices.value(i) as usize;
+        if i > 0 && !is_equal(indices.value(i - 1) as usize, row) {
+            rank += 1;
+        }
+        ranks[row] = rank;
+    }
+
+    let max_rank = rank.max(1);
+    Ok(ranks
+        .into_iter()
+        .map(|r| (r * u32::MAX as u64 / max_rank) as u32)
+        .collect())
+}
+
+/// Interleaves the bits of the values, taking the most significant bit of every value first.
+fn interleave_bits(values: &[u32]) -> Vec<u8> {
+    let mut key = vec![0u8; values.len() * 4];
+    let mut position = 0;
+    for bit in (0..32).rev() {
+        for value in values {
+            if (value >> bit) & 1 == 1 {
+                key[position / 8] |= 0x80 >> (position % 8);
+            }
+            position += 1;
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, StringArray};
+    use std::sync::Arc;
+
+    fn add(path: &str, size: DeltaDataTypeLong) -> action::Add {
+        action::Add {
+            path: path.to_string(),
+            size,
+            ..Default::default()
+        }
+    }
+
+    fn paths(bins: &[Vec<action::Add>]) -> Vec<Vec<&str>> {
+        bins.iter()
+            .map(|bin| bin.iter().map(|add| add.path.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn pack_files_fills_bins_up_to_target_size() {
+        let files = vec![add("d", 40), add("a", 10), add("c", 30), add("b", 20)];
+
+        let bins = pack_files(files, 60);
+
+        assert_eq!(paths(&bins), vec![vec!["a", "b", "c"], vec!["d"]]);
+    }
+
+    #[test]
+    fn pack_files_keeps_one_bin_for_small_files() {
+        let files = vec![add("a", 1), add("b", 1), add("c", 1)];
+
+        let bins = pack_files(files, 100);
+
+        assert_eq!(paths(&bins), vec![vec!["a", "b", "c"]]);
+    }
+
+    #[test]
+    fn interleave_bits_alternates_between_values() {
+        assert_eq!(interleave_bits(&[u32::MAX, 0]), vec![0xAA; 8]);
+        assert_eq!(interleave_bits(&[0, u32::MAX]), vec![0x55; 8]);
+        assert_eq!(
+            interleave_bits(&[1 << 31, 1 << 30]),
+            vec![0x90, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn zorder_indices_follow_the_curve() {
+        let x: ArrayRef = Arc::new(Int32Array::from(vec![7, 3, 7, 3]));
+        let y: ArrayRef = Arc::new(StringArray::from(vec![Some("b"), Some("b"), None, None]));
+
+        let indices = zorder_indices(&[x, y]).unwrap();
+
+        assert_eq!(indices, vec![3, 1, 2, 0]);
+    }
+}
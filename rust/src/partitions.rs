@@ -1,6 +1,7 @@
 //! Delta Table partition handling logic.
 
 use std::convert::TryFrom;
+use std::fmt;
 
 use crate::DeltaTableError;
 
@@ -26,6 +27,43 @@ pub struct PartitionFilter<'a, T> {
     pub value: PartitionValue<T>,
 }
 
+/// Formats the filter as a SQL predicate, e.g. `year = '2021'` or `month IN ('01', '02')`.
+impl<'a, T: fmt::Display> fmt::Display for PartitionFilter<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |values: &Vec<T>| {
+            values
+                .iter()
+                .map(|v| format!("'{}'", v))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match &self.value {
+            PartitionValue::Equal(value) => write!(f, "{} = '{}'", self.key, value),
+            PartitionValue::NotEqual(value) => write!(f, "{} != '{}'", self.key, value),
+            PartitionValue::In(values) => write!(f, "{} IN ({})", self.key, list(values)),
+            PartitionValue::NotIn(values) => write!(f, "{} NOT IN ({})", self.key, list(values)),
+        }
+    }
+}
+
+/// Returns the SQL predicate matching all of the given filters, as recorded in the commit info,
+/// or none if there are no filters.
+pub(crate) fn filters_to_predicate<T: fmt::Display>(
+    filters: &[PartitionFilter<'_, T>],
+) -> Option<String> {
+    if filters.is_empty() {
+        None
+    } else {
+        Some(
+            filters
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
+                .join(" AND "),
+        )
+    }
+}
+
 /// Partition filters methods for filtering the DeltaTable partitions.
 impl<'a> PartitionFilter<'a, &str> {
     /// Indicates if a DeltaTable partition matches with the partition filter by key and value.
@@ -1,6 +1,8 @@
 #![allow(non_snake_case, non_camel_case_types)]
 
+use crate::DeltaTableError;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 
 /// Type alias for a string expected to match a GUID/UUID format
@@ -24,6 +26,14 @@ pub struct SchemaTypeStruct {
 }
 
 impl SchemaTypeStruct {
+    /// Create a new struct type from its fields.
+    pub fn new(fields: Vec<SchemaField>) -> Self {
+        Self {
+            r#type: String::from("struct"),
+            fields,
+        }
+    }
+
     /// Returns the list of fields contained within the column struct.
     pub fn get_fields(&self) -> &Vec<SchemaField> {
         &self.fields
@@ -40,10 +50,25 @@ pub struct SchemaField {
     nullable: bool,
     // A JSON map containing information about this column. Keys prefixed with Delta are reserved
     // for the implementation.
-    metadata: HashMap<String, String>,
+    metadata: HashMap<String, Value>,
 }
 
 impl SchemaField {
+    /// Create a new schema field.
+    pub fn new(
+        name: String,
+        r#type: SchemaDataType,
+        nullable: bool,
+        metadata: HashMap<String, Value>,
+    ) -> Self {
+        Self {
+            name,
+            r#type,
+            nullable,
+            metadata,
+        }
+    }
+
     /// The column name of the schema field.
     pub fn get_name(&self) -> &str {
         &self.name
@@ -60,7 +85,7 @@ impl SchemaField {
     }
 
     /// Additional metadata about the column/field.
-    pub fn get_metadata(&self) -> &HashMap<String, String> {
+    pub fn get_metadata(&self) -> &HashMap<String, Value> {
         &self.metadata
     }
 }
@@ -78,6 +103,15 @@ pub struct SchemaTypeArray {
 }
 
 impl SchemaTypeArray {
+    /// Create a new array type from its element type.
+    pub fn new(element_type: Box<SchemaDataType>, contains_null: bool) -> Self {
+        Self {
+            r#type: String::from("array"),
+            elementType: element_type,
+            containsNull: contains_null,
+        }
+    }
+
     /// The data type of each element contained in the array.
     pub fn get_element_type(&self) -> &SchemaDataType {
         &self.elementType
@@ -99,6 +133,20 @@ pub struct SchemaTypeMap {
 }
 
 impl SchemaTypeMap {
+    /// Create a new map type from its key and value types.
+    pub fn new(
+        key_type: Box<SchemaDataType>,
+        value_type: Box<SchemaDataType>,
+        value_contains_null: bool,
+    ) -> Self {
+        Self {
+            r#type: String::from("map"),
+            keyType: key_type,
+            valueType: value_type,
+            valueContainsNull: value_contains_null,
+        }
+    }
+
     /// The type of element used for the key of this map, represented as a string containing the
     /// name of a primitive type, a struct definition, an array definition or a map definition
     pub fn get_key_type(&self) -> &SchemaDataType {
@@ -154,8 +202,274 @@ pub struct Schema {
 }
 
 impl Schema {
+    /// Create a new table schema from its top level fields.
+    pub fn new(fields: Vec<SchemaField>) -> Self {
+        Self {
+            r#type: String::from("struct"),
+            fields,
+        }
+    }
+
     /// Returns the list of fields that make up the schema definition of the table.
     pub fn get_fields(&self) -> &Vec<SchemaField> {
         &self.fields
     }
+
+    /// Returns the top level field with the given name, if any.
+    pub fn get_field_with_name(&self, name: &str) -> Option<&SchemaField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Merges the schema of data written to the table into the table schema. Columns missing
+    /// from the table are appended as nullable columns, and narrower numeric types are widened to
+    /// the types of the data, e.g. `integer` to `long`. Columns of the table missing from the
+    /// data have to be nullable.
+    ///
+    /// Returns `DeltaTableError::SchemaMismatch` listing every column that cannot be merged.
+    pub fn merge(&self, other: &Schema) -> Result<Schema, DeltaTableError> {
+        let mut errors = vec![];
+        let fields = merge_fields("", &self.fields, &other.fields, &mut errors);
+        if errors.is_empty() {
+            Ok(Schema::new(fields))
+        } else {
+            Err(DeltaTableError::SchemaMismatch {
+                msg: format!(
+                    "Schema of the data cannot be merged into the table schema:\n{}",
+                    errors.join("\n")
+                ),
+            })
+        }
+    }
+}
+
+/// Merges the fields of a struct, recording the columns that cannot be merged in `errors`.
+fn merge_fields(
+    path: &str,
+    fields: &[SchemaField],
+    other: &[SchemaField],
+    errors: &mut Vec<String>,
+) -> Vec<SchemaField> {
+    let mut merged: Vec<SchemaField> = fields
+        .iter()
+        .map(|field| {
+            let column = format!("{}{}", path, field.name);
+            match other.iter().find(|f| f.name == field.name) {
+                Some(other_field) => SchemaField {
+                    r#type: merge_types(&column, &field.r#type, &other_field.r#type, errors),
+                    ..field.clone()
+                },
+                None => {
+                    if !field.nullable {
+                        errors.push(format!(
+                            "  - `{}`: non-nullable column is missing from the data",
+                            column
+                        ));
+                    }
+                    field.clone()
+                }
+            }
+        })
+        .collect();
+
+    // existing rows have no values for new columns
+    merged.extend(
+        other
+            .iter()
+            .filter(|f| fields.iter().all(|field| field.name != f.name))
+            .map(|f| SchemaField {
+                nullable: true,
+                ..f.clone()
+            }),
+    );
+    merged
+}
+
+fn merge_types(
+    column: &str,
+    data_type: &SchemaDataType,
+    other: &SchemaDataType,
+    errors: &mut Vec<String>,
+) -> SchemaDataType {
+    match (data_type, other) {
+        (SchemaDataType::r#struct(s), SchemaDataType::r#struct(o)) => {
+            SchemaDataType::r#struct(SchemaTypeStruct::new(merge_fields(
+                &format!("{}.", column),
+                &s.fields,
+                &o.fields,
+                errors,
+            )))
+        }
+        (SchemaDataType::array(a), SchemaDataType::array(o)) => {
+            SchemaDataType::array(SchemaTypeArray::new(
+                Box::new(merge_types(
+                    &format!("{}.element", column),
+                    &a.elementType,
+                    &o.elementType,
+                    errors,
+                )),
+                a.containsNull,
+            ))
+        }
+        (SchemaDataType::map(m), SchemaDataType::map(o)) => {
+            SchemaDataType::map(SchemaTypeMap::new(
+                Box::new(merge_types(
+                    &format!("{}.key", column),
+                    &m.keyType,
+                    &o.keyType,
+                    errors,
+                )),
+                Box::new(merge_types(
+                    &format!("{}.value", column),
+                    &m.valueType,
+                    &o.valueType,
+                    errors,
+                )),
+                m.valueContainsNull,
+            ))
+        }
+        (SchemaDataType::primitive(p), SchemaDataType::primitive(o)) => {
+            match (widening_rank(p), widening_rank(o)) {
+                _ if p == o => data_type.clone(),
+                (Some((family, rank)), Some((other_family, other_rank)))
+                    if family == other_family =>
+                {
+                    if other_rank > rank {
+                        other.clone()
+                    } else {
+                        data_type.clone()
+                    }
+                }
+                _ => {
+                    errors.push(format!(
+                        "  - `{}`: type {} cannot be merged with type {}",
+                        column,
+                        type_name(data_type),
+                        type_name(other)
+                    ));
+                    data_type.clone()
+                }
+            }
+        }
+        _ => {
+            errors.push(format!(
+                "  - `{}`: type {} cannot be merged with type {}",
+                column,
+                type_name(data_type),
+                type_name(other)
+            ));
+            data_type.clone()
+        }
+    }
+}
+
+/// Returns the family of numeric types a primitive type can be widened within, along with its
+/// rank in the family.
+fn widening_rank(primitive: &str) -> Option<(u8, u8)> {
+    match primitive {
+        "byte" => Some((0, 0)),
+        "short" => Some((0, 1)),
+        "integer" => Some((0, 2)),
+        "long" => Some((0, 3)),
+        "float" => Some((1, 0)),
+        "double" => Some((1, 1)),
+        _ => None,
+    }
+}
+
+fn type_name(data_type: &SchemaDataType) -> &str {
+    match data_type {
+        SchemaDataType::primitive(p) => p,
+        SchemaDataType::r#struct(_) => "struct",
+        SchemaDataType::array(_) => "array",
+        SchemaDataType::map(_) => "map",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, data_type: &str, nullable: bool) -> SchemaField {
+        SchemaField::new(
+            name.to_string(),
+            SchemaDataType::primitive(data_type.to_string()),
+            nullable,
+            HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn merge_adds_columns_and_widens_types() {
+        let schema = Schema::new(vec![
+            field("id", "string", false),
+            field("value", "integer", true),
+        ]);
+        let data = Schema::new(vec![
+            field("value", "long", true),
+            field("id", "string", false),
+            field("extra", "double", false),
+        ]);
+
+        let merged = schema.merge(&data).unwrap();
+        assert_eq!(
+            merged.get_fields(),
+            &vec![
+                field("id", "string", false),
+                field("value", "long", true),
+                field("extra", "double", true),
+            ]
+        );
+
+        // narrower data is written with the types of the table
+        let data = Schema::new(vec![
+            field("id", "string", false),
+            field("value", "short", true),
+        ]);
+        assert_eq!(
+            schema.merge(&data).unwrap().get_fields(),
+            schema.get_fields()
+        );
+    }
+
+    #[test]
+    fn merge_nested_fields() {
+        let nested = |fields| {
+            SchemaField::new(
+                "nested".to_string(),
+                SchemaDataType::r#struct(SchemaTypeStruct::new(fields)),
+                true,
+                HashMap::new(),
+            )
+        };
+        let schema = Schema::new(vec![nested(vec![field("a", "integer", true)])]);
+        let data = Schema::new(vec![nested(vec![
+            field("a", "integer", true),
+            field("b", "string", true),
+        ])]);
+
+        let merged = schema.merge(&data).unwrap();
+        assert_eq!(merged.get_fields(), data.get_fields());
+    }
+
+    #[test]
+    fn merge_reports_every_mismatch() {
+        let schema = Schema::new(vec![
+            field("id", "string", false),
+            field("value", "integer", true),
+            field("score", "double", true),
+        ]);
+        let data = Schema::new(vec![
+            field("value", "string", true),
+            field("score", "long", true),
+        ]);
+
+        match schema.merge(&data) {
+            Err(DeltaTableError::SchemaMismatch { msg }) => {
+                assert!(msg.contains("`id`: non-nullable column is missing"));
+                assert!(msg.contains("`value`: type integer cannot be merged with type string"));
+                assert!(msg.contains("`score`: type double cannot be merged with type long"));
+            }
+            other => panic!("Expected a schema mismatch, got {:?}", other),
+        }
+    }
 }
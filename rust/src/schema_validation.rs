@@ -0,0 +1,596 @@
+//! Validation of the data written to a Delta table against the table schema. Data is validated
+//! before any file is uploaded, since a single committed file that does not match the schema
+//! breaks every reader of the table.
+//!
+//! Mismatches are reported in a `DeltaTableError::SchemaMismatch` listing every offending field
+//! by its path, e.g. `address.street` or `tags.element`.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use arrow::array::{Array, ArrayRef, ListArray, StructArray};
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::parquet_to_arrow_schema;
+use parquet::file::metadata::ParquetMetaData;
+use serde_json::Value;
+
+use crate::delta_arrow;
+use crate::{DeltaTableError, DeltaTableMetaData};
+
+/// Validates that the record batch has the columns of the schema, with the same types, and no
+/// null values in non-nullable fields, including nested ones. The order of the columns is not
+/// validated.
+pub(crate) fn validate_batch(
+    schema: &ArrowSchema,
+    batch: &RecordBatch,
+) -> Result<(), DeltaTableError> {
+    let mut errors = vec![];
+    let batch_schema = batch.schema();
+    for field in schema.fields() {
+        match batch_schema.index_of(field.name()) {
+            Ok(i) => validate_array(field.name(), field, batch.column(i), None, &mut errors),
+            Err(_) => errors.push(missing_column(field.name())),
+        }
+    }
+    for field in batch_schema.fields() {
+        if schema.index_of(field.name()).is_err() {
+            errors.push(unknown_column(field.name()));
+        }
+    }
+    mismatch(errors)
+}
+
+/// Validates the Parquet data file added to a table by its footer, along with its partition
+/// values keyed by the physical names of the partition columns. Partition columns may be stored
+/// in the file as well, but every other column of the table has to be, with a type its values can
+/// be cast to losslessly, e.g. `INT96` timestamps to the microsecond timestamps of Delta or
+/// integers to wider integers.
+///
+/// Nulls in non-nullable fields are detected by the null counts of the footer statistics, which
+/// count the nulls of enclosing structs as well. Non-nullable fields nested in nullable structs
+/// are therefore not validated.
+pub(crate) fn validate_data_file(
+    metadata: &DeltaTableMetaData,
+    footer: &ParquetMetaData,
+    partition_values: Option<&Vec<(String, String)>>,
+) -> Result<(), DeltaTableError> {
+    let physical_partition_columns: Vec<&str> = metadata
+        .partition_columns
+        .iter()
+        .map(|c| metadata.physical_name(c))
+        .collect();
+    validate_partition_values(&physical_partition_columns, partition_values)?;
+
+    let file_metadata = footer.file_metadata();
+    let file_schema = parquet_to_arrow_schema(
+        file_metadata.schema_descr(),
+        file_metadata.key_value_metadata(),
+    )?;
+    let physical_schema =
+        delta_arrow::physical_arrow_schema(&metadata.schema, metadata.column_mapping_mode()?)?;
+
+    let mut errors = vec![];
+    for field in physical_schema.fields() {
+        match file_schema.index_of(field.name()) {
+            Ok(i) => {
+                let file_field = file_schema.field(i);
+                validate_file_type(
+                    field.name(),
+                    field.data_type(),
+                    file_field.data_type(),
+                    &mut errors,
+                );
+                validate_null_counts(field.name(), field, footer, &mut errors);
+            }
+            Err(_) if physical_partition_columns.contains(&field.name().as_str()) => {}
+            Err(_) => errors.push(missing_column(field.name())),
+        }
+    }
+    for file_field in file_schema.fields() {
+        if physical_schema.index_of(file_field.name()).is_err() {
+            errors.push(unknown_column(file_field.name()));
+        }
+    }
+    mismatch(errors)
+}
+
+/// Validates that the values of a column of a data file can be cast losslessly to the type of
+/// the table.
+fn validate_file_type(
+    path: &str,
+    data_type: &DataType,
+    file_type: &DataType,
+    errors: &mut Vec<String>,
+) {
+    let matches = match (data_type, file_type) {
+        (DataType::Struct(fields), DataType::Struct(file_fields)) => {
+            for field in fields {
+                let field_path = format!("{}.{}", path, field.name());
+                match file_fields.iter().find(|f| f.name() == field.name()) {
+                    Some(file_field) => validate_file_type(
+                        &field_path,
+                        field.data_type(),
+                        file_field.data_type(),
+                        errors,
+                    ),
+                    None => errors.push(missing_column(&field_path)),
+                }
+            }
+            for file_field in file_fields {
+                if !fields.iter().any(|f| f.name() == file_field.name()) {
+                    errors.push(unknown_column(&format!("{}.{}", path, file_field.name())));
+                }
+            }
+            true
+        }
+        (DataType::List(field), DataType::List(file_field)) => {
+            validate_file_type(
+                &format!("{}.element", path),
+                field.data_type(),
+                file_field.data_type(),
+                errors,
+            );
+            true
+        }
+        (expected, actual) if expected == actual => true,
+        (DataType::Timestamp(_, _), DataType::Timestamp(_, _)) => true,
+        (DataType::Int16, DataType::Int8)
+        | (DataType::Int32, DataType::Int8)
+        | (DataType::Int32, DataType::Int16)
+        | (DataType::Int64, DataType::Int8)
+        | (DataType::Int64, DataType::Int16)
+        | (DataType::Int64, DataType::Int32)
+        | (DataType::Float64, DataType::Float32)
+        | (DataType::Utf8, DataType::LargeUtf8)
+        | (DataType::Binary, DataType::LargeBinary) => true,
+        (DataType::Decimal(precision, scale), DataType::Decimal(file_precision, file_scale)) => {
+            scale == file_scale && file_precision <= precision
+        }
+        _ => false,
+    };
+    if !matches {
+        errors.push(format!(
+            "  - `{}`: type {:?} does not match type {:?} of the table",
+            path, file_type, data_type
+        ));
+    }
+}
+
+/// Validates the footer null counts of the leaf columns of non-nullable fields, down to the
+/// first nullable struct. Fields stored as `required` cannot hold nulls and are not counted.
+fn validate_null_counts(
+    path: &str,
+    field: &Field,
+    footer: &ParquetMetaData,
+    errors: &mut Vec<String>,
+) {
+    if field.is_nullable() {
+        return;
+    }
+    match field.data_type() {
+        DataType::Struct(children) => {
+            for child in children {
+                validate_null_counts(&format!("{}.{}", path, child.name()), child, footer, errors);
+            }
+        }
+        DataType::List(_) => {}
+        _ => {
+            // a column without definition levels is required along its whole path
+            let required = footer
+                .file_metadata()
+                .schema_descr()
+                .columns()
+                .iter()
+                .any(|column| column.path().string() == path && column.max_def_level() == 0);
+            if required {
+                return;
+            }
+            let num_nulls: Option<u64> = footer
+                .row_groups()
+                .iter()
+                .map(|row_group| {
+                    row_group
+                        .columns()
+                        .iter()
+                        .find(|c| c.column_path().string() == path)
+                        .and_then(|c| c.statistics())
+                        .map(|stats| stats.null_count())
+                })
+                .sum();
+            match num_nulls {
+                Some(0) => {}
+                Some(num_nulls) => errors.push(format!(
+                    "  - `{}`: {} null values in non-nullable field",
+                    path, num_nulls
+                )),
+                None => errors.push(format!(
+                    "  - `{}`: non-nullable field is stored as optional without null counts",
+                    path
+                )),
+            }
+        }
+    }
+}
+
+/// Validates that there is a value for every partition column, and only for those.
+pub(crate) fn validate_partition_values<S: AsRef<str>>(
+    partition_columns: &[S],
+    partition_values: Option<&Vec<(String, String)>>,
+) -> Result<(), DeltaTableError> {
+    let keys: HashSet<&str> = partition_values
+        .map(|values| values.iter().map(|(key, _)| key.as_str()).collect())
+        .unwrap_or_default();
+    let mut errors: Vec<String> = partition_columns
+        .iter()
+        .filter(|column| !keys.contains(column.as_ref()))
+        .map(|column| format!("  - `{}`: partition column has no value", column.as_ref()))
+        .collect();
+    for key in partition_values.into_iter().flatten().map(|(key, _)| key) {
+        if !partition_columns
+            .iter()
+            .any(|column| column.as_ref() == key)
+        {
+            errors.push(format!(
+                "  - `{}`: not a partition column of the table",
+                key
+            ));
+        }
+    }
+    mismatch(errors)
+}
+
+/// Validates that the JSON row only has fields of the schema, with values of matching types.
+/// Null values are accepted here, nullability is validated on the decoded batches.
+pub(crate) fn validate_json_value(
+    schema: &ArrowSchema,
+    value: &Value,
+) -> Result<(), DeltaTableError> {
+    let mut errors = vec![];
+    match value {
+        Value::Object(_) => validate_json_fields("", schema.fields(), value, &mut errors),
+        _ => errors.push(format!("  - row is not a JSON object: {}", value)),
+    }
+    mismatch(errors)
+}
+
+fn validate_json_fields(path: &str, fields: &[Field], value: &Value, errors: &mut Vec<String>) {
+    let object = match value.as_object() {
+        Some(object) => object,
+        None => return,
+    };
+    for (name, value) in object {
+        let field_path = format!("{}{}", path, name);
+        match fields.iter().find(|f| f.name() == name) {
+            Some(field) => validate_json_type(&field_path, field.data_type(), value, errors),
+            None => errors.push(unknown_column(&field_path)),
+        }
+    }
+}
+
+fn validate_json_type(path: &str, data_type: &DataType, value: &Value, errors: &mut Vec<String>) {
+    let matches = match (data_type, value) {
+        (_, Value::Null) => true,
+        (DataType::Struct(fields), Value::Object(_)) => {
+            validate_json_fields(&format!("{}.", path), fields, value, errors);
+            true
+        }
+        (DataType::List(field), Value::Array(elements)) => {
+            let element_path = format!("{}.element", path);
+            for element in elements {
+                validate_json_type(&element_path, field.data_type(), element, errors);
+            }
+            true
+        }
+        (DataType::Int8, _) => value.as_i64().map_or(false, |v| i8::try_from(v).is_ok()),
+        (DataType::Int16, _) => value.as_i64().map_or(false, |v| i16::try_from(v).is_ok()),
+        (DataType::Int32, _) => value.as_i64().map_or(false, |v| i32::try_from(v).is_ok()),
+        (DataType::Int64, _) => value.is_i64(),
+        (DataType::Float32, _) | (DataType::Float64, _) => value.is_number(),
+        (DataType::Utf8, _) => value.is_string(),
+        (DataType::Boolean, _) => value.is_boolean(),
+        (DataType::Struct(_), _) | (DataType::List(_), _) => false,
+        // dates, timestamps and decimals are parsed from strings or numbers by the decoder
+        _ => value.is_string() || value.is_number(),
+    };
+    if !matches {
+        errors.push(format!(
+            "  - `{}`: value {} does not match type {:?}",
+            path, value, data_type
+        ));
+    }
+}
+
+/// Validates the type and nullability of the values of a field. `present` holds the rows the
+/// values of the field are expected in, i.e. the rows where the enclosing struct or list is not
+/// null, or all rows for top level fields.
+fn validate_array(
+    path: &str,
+    field: &Field,
+    array: &ArrayRef,
+    present: Option<&[bool]>,
+    errors: &mut Vec<String>,
+) {
+    let is_present = |row: usize| present.map_or(true, |present| present[row]);
+
+    if !field.is_nullable() && array.null_count() > 0 {
+        let num_nulls = (0..array.len())
+            .filter(|row| is_present(*row) && array.is_null(*row))
+            .count();
+        if num_nulls > 0 {
+            errors.push(format!(
+                "  - `{}`: {} null values in non-nullable field",
+                path, num_nulls
+            ));
+        }
+    }
+
+    match (field.data_type(), array.data_type()) {
+        (DataType::Struct(fields), DataType::Struct(array_fields)) => {
+            let array = array.as_any().downcast_ref::<StructArray>().unwrap();
+            let child_present: Vec<bool> = (0..array.len())
+                .map(|row| is_present(row) && array.is_valid(row))
+                .collect();
+            for child in fields {
+                let child_path = format!("{}.{}", path, child.name());
+                match array.column_by_name(child.name()) {
+                    Some(column) => {
+                        validate_array(&child_path, child, column, Some(&child_present), errors)
+                    }
+                    None => errors.push(missing_column(&child_path)),
+                }
+            }
+            for child in array_fields {
+                if !fields.iter().any(|f| f.name() == child.name()) {
+                    errors.push(unknown_column(&format!("{}.{}", path, child.name())));
+                }
+            }
+        }
+        (DataType::List(element), DataType::List(_)) => {
+            let array = array.as_any().downcast_ref::<ListArray>().unwrap();
+            let values = array.values();
+            let offsets = array.value_offsets();
+            let mut element_present = vec![false; values.len()];
+            for row in (0..array.len()).filter(|row| is_present(*row) && array.is_valid(*row)) {
+                for present in
+                    &mut element_present[offsets[row] as usize..offsets[row + 1] as usize]
+                {
+                    *present = true;
+                }
+            }
+            validate_array(
+                &format!("{}.element", path),
+                element,
+                &values,
+                Some(&element_present),
+                errors,
+            );
+        }
+        (expected, actual) if expected == actual => {}
+        (expected, actual) => errors.push(format!(
+            "  - `{}`: type {:?} does not match type {:?} of the table",
+            path, actual, expected
+        )),
+    }
+}
+
+fn missing_column(path: &str) -> String {
+    format!(
+        "  - `{}`: field of the table is missing from the data",
+        path
+    )
+}
+
+fn unknown_column(path: &str) -> String {
+    format!("  - `{}`: field is not in the table schema", path)
+}
+
+fn mismatch(errors: Vec<String>) -> Result<(), DeltaTableError> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(DeltaTableError::SchemaMismatch {
+            msg: format!(
+                "Data does not match the table schema:\n{}",
+                errors.join("\n")
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Schema, SchemaDataType, SchemaField};
+    use arrow::array::{Int32Array, StringArray, TimestampMillisecondArray};
+    use arrow::buffer::Buffer;
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::writer::InMemoryWriteableCursor;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn schema() -> ArrowSchema {
+        ArrowSchema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new(
+                "address",
+                DataType::Struct(vec![
+                    Field::new("street", DataType::Utf8, false),
+                    Field::new("number", DataType::Int32, true),
+                ]),
+                true,
+            ),
+        ])
+    }
+
+    /// Returns an `address` struct array, with the validity of the structs as bitmask.
+    fn address(streets: Vec<Option<&str>>, validity: u8) -> ArrayRef {
+        let num_rows = streets.len();
+        Arc::new(StructArray::from((
+            vec![
+                (
+                    Field::new("street", DataType::Utf8, true),
+                    Arc::new(StringArray::from(streets)) as ArrayRef,
+                ),
+                (
+                    Field::new("number", DataType::Int32, true),
+                    Arc::new(Int32Array::from(vec![None; num_rows])) as ArrayRef,
+                ),
+            ],
+            Buffer::from(&[validity]),
+        )))
+    }
+
+    fn batch(ids: Vec<Option<&str>>, address: ArrayRef) -> RecordBatch {
+        let schema = ArrowSchema::new(vec![
+            Field::new("id", DataType::Utf8, true),
+            Field::new("address", address.data_type().clone(), true),
+        ]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![Arc::new(StringArray::from(ids)), address],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn nulls_in_non_nullable_fields_are_rejected() {
+        let valid = batch(
+            vec![Some("a"), Some("b")],
+            address(vec![Some("main"), None], 0b01),
+        );
+        validate_batch(&schema(), &valid).unwrap();
+
+        let invalid = batch(
+            vec![None, Some("b")],
+            address(vec![Some("main"), None], 0b11),
+        );
+        match validate_batch(&schema(), &invalid) {
+            Err(DeltaTableError::SchemaMismatch { msg }) => {
+                assert!(msg.contains("`id`: 1 null values in non-nullable field"));
+                assert!(msg.contains("`address.street`: 1 null values in non-nullable field"));
+            }
+            other => panic!("Expected a schema mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_and_unknown_columns_are_rejected() {
+        let schema = ArrowSchema::new(vec![
+            Field::new("id", DataType::Utf8, true),
+            Field::new("value", DataType::Int32, true),
+        ]);
+        let batch = RecordBatch::try_new(
+            Arc::new(ArrowSchema::new(vec![
+                Field::new("id", DataType::Int32, true),
+                Field::new("other", DataType::Utf8, true),
+            ])),
+            vec![
+                Arc::new(Int32Array::from(vec![1])),
+                Arc::new(StringArray::from(vec!["a"])),
+            ],
+        )
+        .unwrap();
+
+        match validate_batch(&schema, &batch) {
+            Err(DeltaTableError::SchemaMismatch { msg }) => {
+                assert!(msg.contains("`id`: type Int32 does not match type Utf8 of the table"));
+                assert!(msg.contains("`value`: field of the table is missing from the data"));
+                assert!(msg.contains("`other`: field is not in the table schema"));
+            }
+            other => panic!("Expected a schema mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn partition_values_have_to_match_partition_columns() {
+        let values = vec![("year".to_string(), "2021".to_string())];
+        validate_partition_values(&["year"], Some(&values)).unwrap();
+        assert!(validate_partition_values(&["year", "month"], Some(&values)).is_err());
+        assert!(validate_partition_values::<&str>(&[], Some(&values)).is_err());
+        assert!(validate_partition_values(&["year"], None).is_err());
+    }
+
+    #[test]
+    fn json_values_are_validated() {
+        validate_json_value(&schema(), &json!({"id": "a", "address": {"number": 1}})).unwrap();
+        validate_json_value(&schema(), &json!({"id": null})).unwrap();
+
+        match validate_json_value(
+            &schema(),
+            &json!({"id": 1, "address": {"street": "main", "zip": "1000"}, "other": true}),
+        ) {
+            Err(DeltaTableError::SchemaMismatch { msg }) => {
+                assert!(msg.contains("`id`: value 1 does not match type Utf8"));
+                assert!(msg.contains("`address.zip`: field is not in the table schema"));
+                assert!(msg.contains("`other`: field is not in the table schema"));
+            }
+            other => panic!("Expected a schema mismatch, got {:?}", other),
+        }
+
+        // integers have to fit the type of their field
+        match validate_json_value(&schema(), &json!({"address": {"number": 1u64 << 40}})) {
+            Err(DeltaTableError::SchemaMismatch { msg }) => {
+                assert!(
+                    msg.contains("`address.number`: value 1099511627776 does not match type Int32")
+                );
+            }
+            other => panic!("Expected a schema mismatch, got {:?}", other),
+        }
+    }
+
+    fn file_footer(columns: Vec<(&str, ArrayRef)>) -> ParquetMetaData {
+        let schema = ArrowSchema::new(
+            columns
+                .iter()
+                .map(|(name, array)| Field::new(name, array.data_type().clone(), true))
+                .collect(),
+        );
+        let columns = columns.into_iter().map(|(_, array)| array).collect();
+        let batch = RecordBatch::try_new(Arc::new(schema), columns).unwrap();
+        let cursor = InMemoryWriteableCursor::default();
+        let mut writer = ArrowWriter::try_new(cursor.clone(), batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        crate::stats::parquet_footer(&cursor.data()).unwrap()
+    }
+
+    #[test]
+    fn data_files_are_validated_by_their_footer() {
+        let schema = Schema::new(vec![
+            SchemaField::new(
+                "id".to_string(),
+                SchemaDataType::primitive("long".to_string()),
+                false,
+                HashMap::new(),
+            ),
+            SchemaField::new(
+                "ts".to_string(),
+                SchemaDataType::primitive("timestamp".to_string()),
+                true,
+                HashMap::new(),
+            ),
+        ]);
+        let metadata = DeltaTableMetaData::new(None, None, None, schema, vec![], HashMap::new());
+        let timestamps: ArrayRef = Arc::new(TimestampMillisecondArray::from(vec![Some(1), None]));
+
+        // integers and timestamps are cast losslessly to the types of the table
+        let footer = file_footer(vec![
+            ("id", Arc::new(Int32Array::from(vec![1, 2]))),
+            ("ts", timestamps.clone()),
+        ]);
+        validate_data_file(&metadata, &footer, None).unwrap();
+
+        let footer = file_footer(vec![
+            ("id", Arc::new(Int32Array::from(vec![Some(1), None]))),
+            ("ts", Arc::new(StringArray::from(vec!["a", "b"]))),
+        ]);
+        match validate_data_file(&metadata, &footer, None) {
+            Err(DeltaTableError::SchemaMismatch { msg }) => {
+                assert!(msg.contains("`id`: 1 null values in non-nullable field"));
+                assert!(msg.contains("`ts`: type Utf8 does not match type Timestamp"));
+            }
+            other => panic!("Expected a schema mismatch, got {:?}", other),
+        }
+    }
+}
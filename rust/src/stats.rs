@@ -0,0 +1,494 @@
+//! Statistics of data files, collected from the parquet footer of the files and stored in the
+//! `stats` field of add actions so that readers are able to skip files.
+
+use crate::action::{ColumnCountStat, ColumnValueStat, Stats};
+use crate::DeltaTableError;
+use arrow::datatypes::{DataType, Field as ArrowField, Schema as ArrowSchema, TimeUnit};
+use parquet::arrow::parquet_to_arrow_schema;
+use parquet::errors::ParquetError;
+use parquet::file::footer;
+use parquet::file::metadata::{ParquetMetaData, RowGroupMetaData};
+use parquet::file::serialized_reader::SliceableCursor;
+use parquet::file::statistics::Statistics;
+use parquet::schema::types::SchemaDescriptor;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Number of leading columns statistics are collected for, unless configured otherwise by the
+/// `delta.dataSkippingNumIndexedCols` table property.
+pub(crate) const DEFAULT_NUM_INDEXED_COLS: usize = 32;
+
+/// Returns the number of leading leaf columns statistics are collected for, as configured by the
+/// `delta.dataSkippingNumIndexedCols` table property. `None` means all columns, which is
+/// configured with `-1`.
+pub(crate) fn num_indexed_cols(
+    configuration: &HashMap<String, String>,
+) -> Result<Option<usize>, DeltaTableError> {
+    match configuration.get("delta.dataSkippingNumIndexedCols") {
+        None => Ok(Some(DEFAULT_NUM_INDEXED_COLS)),
+        Some(value) => match value.parse::<i64>() {
+            Ok(-1) => Ok(None),
+            Ok(n) if n >= 0 => Ok(Some(n as usize)),
+            _ => Err(DeltaTableError::Generic(format!(
+                "Invalid delta.dataSkippingNumIndexedCols table property: {}",
+                value
+            ))),
+        },
+    }
+}
+
+/// Builds the statistics of a data file from the footer metadata returned when the file was
+/// written.
+pub(crate) fn stats_from_file_metadata(
+    file_metadata: &parquet_format::FileMetaData,
+    num_indexed_cols: Option<usize>,
+) -> Result<Stats, ParquetError> {
+    let schema = parquet::schema::types::from_thrift(&file_metadata.schema)?;
+    let schema_descr = Arc::new(SchemaDescriptor::new(schema));
+    let row_groups = file_metadata
+        .row_groups
+        .iter()
+        .cloned()
+        .map(|rg| RowGroupMetaData::from_thrift(schema_descr.clone(), rg))
+        .collect::<Result<Vec<RowGroupMetaData>, ParquetError>>()?;
+    let arrow_schema = parquet_to_arrow_schema(&schema_descr, &file_metadata.key_value_metadata)?;
+
+    Ok(stats_from_row_groups(
+        file_metadata.num_rows,
+        &row_groups,
+        &arrow_schema,
+        num_indexed_cols,
+    ))
+}
+
+/// Size of the end of a Parquet file holding the length of the footer metadata and the magic
+/// number.
+const FOOTER_SIZE: usize = 8;
+
+/// Reads the footer metadata of a Parquet file from its content, copying only the footer.
+pub(crate) fn parquet_footer(bytes: &[u8]) -> Result<ParquetMetaData, ParquetError> {
+    let len = bytes.len();
+    if len < FOOTER_SIZE {
+        return Err(ParquetError::General(
+            "Invalid Parquet file. Size is smaller than footer".to_string(),
+        ));
+    }
+    let mut metadata_len = [0; 4];
+    metadata_len.copy_from_slice(&bytes[len - 8..len - 4]);
+    let start = len
+        .checked_sub(FOOTER_SIZE + u32::from_le_bytes(metadata_len) as usize)
+        .ok_or_else(|| {
+            ParquetError::General(
+                "Invalid Parquet file. Metadata length is larger than the file".to_string(),
+            )
+        })?;
+    footer::parse_metadata(&SliceableCursor::new(bytes[start..].to_vec()))
+}
+
+/// Builds the statistics of a data file from its footer metadata.
+pub(crate) fn stats_from_footer(
+    footer: &ParquetMetaData,
+    num_indexed_cols: Option<usize>,
+) -> Result<Stats, ParquetError> {
+    let file_metadata = footer.file_metadata();
+    let arrow_schema = parquet_to_arrow_schema(
+        file_metadata.schema_descr(),
+        file_metadata.key_value_metadata(),
+    )?;
+
+    Ok(stats_from_row_groups(
+        file_metadata.num_rows(),
+        footer.row_groups(),
+        &arrow_schema,
+        num_indexed_cols,
+    ))
+}
+
+fn stats_from_row_groups(
+    num_rows: i64,
+    row_groups: &[RowGroupMetaData],
+    arrow_schema: &ArrowSchema,
+    num_indexed_cols: Option<usize>,
+) -> Stats {
+    let mut stats = Stats {
+        num_records: num_rows,
+        ..Default::default()
+    };
+
+    let mut remaining_cols = num_indexed_cols;
+    for field in arrow_schema.fields() {
+        let column_stats = field_stats(field, field.name(), row_groups, &mut remaining_cols);
+        if let Some(min) = column_stats.min {
+            stats.min_values.insert(field.name().clone(), min);
+        }
+        if let Some(max) = column_stats.max {
+            stats.max_values.insert(field.name().clone(), max);
+        }
+        if let Some(null_count) = column_stats.null_count {
+            stats.null_count.insert(field.name().clone(), null_count);
+        }
+    }
+
+    stats
+}
+
+#[derive(Default)]
+struct ColumnStats {
+    min: Option<ColumnValueStat>,
+    max: Option<ColumnValueStat>,
+    null_count: Option<ColumnCountStat>,
+}
+
+/// Collects the statistics of a (possibly nested) field. Every leaf column counts towards the
+/// number of indexed columns, lists and maps are skipped.
+fn field_stats(
+    field: &ArrowField,
+    path: &str,
+    row_groups: &[RowGroupMetaData],
+    remaining_cols: &mut Option<usize>,
+) -> ColumnStats {
+    match field.data_type() {
+        DataType::Struct(children) => {
+            let mut min_values = HashMap::new();
+            let mut max_values = HashMap::new();
+            let mut null_counts = HashMap::new();
+            for child in children {
+                let child_path = format!("{}.{}", path, child.name());
+                let child_stats = field_stats(child, &child_path, row_groups, remaining_cols);
+                if let Some(min) = child_stats.min {
+                    min_values.insert(child.name().clone(), min);
+                }
+                if let Some(max) = child_stats.max {
+                    max_values.insert(child.name().clone(), max);
+                }
+                if let Some(null_count) = child_stats.null_count {
+                    null_counts.insert(child.name().clone(), null_count);
+                }
+            }
+
+            ColumnStats {
+                min: Some(min_values)
+                    .filter(|m| !m.is_empty())
+                    .map(ColumnValueStat::Column),
+                max: Some(max_values)
+                    .filter(|m| !m.is_empty())
+                    .map(ColumnValueStat::Column),
+                null_count: Some(null_counts)
+                    .filter(|m| !m.is_empty())
+                    .map(ColumnCountStat::Column),
+            }
+        }
+        DataType::List(_)
+        | DataType::LargeList(_)
+        | DataType::FixedSizeList(_, _)
+        | DataType::Dictionary(_, _) => ColumnStats::default(),
+        data_type => {
+            match remaining_cols {
+                Some(0) => return ColumnStats::default(),
+                Some(n) => *n -= 1,
+                None => {}
+            }
+            leaf_stats(path, data_type, row_groups)
+        }
+    }
+}
+
+fn leaf_stats(path: &str, data_type: &DataType, row_groups: &[RowGroupMetaData]) -> ColumnStats {
+    let mut null_count: Option<i64> = Some(0);
+    let mut min_max: Option<(Value, Value)> = None;
+    let mut min_max_complete = true;
+
+    for row_group in row_groups {
+        let column_stats = row_group
+            .columns()
+            .iter()
+            .find(|c| c.column_path().string() == path)
+            .and_then(|c| c.statistics());
+        let column_stats = match column_stats {
+            Some(s) => s,
+            None => {
+                null_count = None;
+                min_max_complete = false;
+                continue;
+            }
+        };
+
+        null_count = null_count.map(|n| n + column_stats.null_count() as i64);
+
+        // a row group with only null values has no min and max values
+        if !column_stats.has_min_max_set()
+            && column_stats.null_count() == row_group.num_rows() as u64
+        {
+            continue;
+        }
+
+        match min_max_values(column_stats, data_type) {
+            Some((min, max)) => {
+                min_max = Some(match min_max {
+                    None => (min, max),
+                    Some((cur_min, cur_max)) => (
+                        if compare_values(&min, &cur_min) == Some(Ordering::Less) {
+                            min
+                        } else {
+                            cur_min
+                        },
+                        if compare_values(&max, &cur_max) == Some(Ordering::Greater) {
+                            max
+                        } else {
+                            cur_max
+                        },
+                    ),
+                });
+            }
+            None => min_max_complete = false,
+        }
+    }
+
+    let min_max = min_max.filter(|_| min_max_complete);
+    ColumnStats {
+        min: min_max
+            .as_ref()
+            .map(|(min, _)| ColumnValueStat::Value(min.clone())),
+        max: min_max.map(|(_, max)| ColumnValueStat::Value(max)),
+        null_count: null_count.map(ColumnCountStat::Value),
+    }
+}
+
+/// Converts the min and max values of a parquet column chunk to their JSON representation in
+/// Delta statistics. Returns `None` for types without a lossless JSON representation.
+fn min_max_values(stats: &Statistics, data_type: &DataType) -> Option<(Value, Value)> {
+    if !stats.has_min_max_set() {
+        return None;
+    }
+
+    match (stats, data_type) {
+        (Statistics::Boolean(s), DataType::Boolean) => {
+            Some((Value::Bool(*s.min()), Value::Bool(*s.max())))
+        }
+        (Statistics::Int32(s), DataType::Int8)
+        | (Statistics::Int32(s), DataType::Int16)
+        | (Statistics::Int32(s), DataType::Int32) => {
+            Some((Value::from(*s.min()), Value::from(*s.max())))
+        }
+        (Statistics::Int32(s), DataType::Date32) => {
+            let date = |days: i32| {
+                chrono::NaiveDate::from_num_days_from_ce_opt(days + 719_163)
+                    .map(|d| Value::String(d.to_string()))
+            };
+            Some((date(*s.min())?, date(*s.max())?))
+        }
+        (Statistics::Int64(s), DataType::Int64) => {
+            Some((Value::from(*s.min()), Value::from(*s.max())))
+        }
+        (Statistics::Int64(s), DataType::Timestamp(unit, _)) => {
+            // timestamps are stored with millisecond precision, so the min value is rounded down
+            // and the max value is rounded up to still bound the values of the file
+            let millis = |t: i64, round_up: bool| {
+                let per_milli = match unit {
+                    TimeUnit::Second => return t.checked_mul(1_000),
+                    TimeUnit::Millisecond => 1,
+                    TimeUnit::Microsecond => 1_000,
+                    TimeUnit::Nanosecond => 1_000_000,
+                };
+                let millis = t.div_euclid(per_milli);
+                if round_up && t.rem_euclid(per_milli) != 0 {
+                    millis.checked_add(1)
+                } else {
+                    Some(millis)
+                }
+            };
+            let timestamp = |millis: i64| {
+                chrono::NaiveDateTime::from_timestamp_opt(
+                    millis.div_euclid(1_000),
+                    (millis.rem_euclid(1_000) * 1_000_000) as u32,
+                )
+                .map(|t| Value::String(t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()))
+            };
+            Some((
+                timestamp(millis(*s.min(), false)?)?,
+                timestamp(millis(*s.max(), true)?)?,
+            ))
+        }
+        (Statistics::Float(s), DataType::Float32) => Some((
+            serde_json::Number::from_f64(*s.min() as f64).map(Value::Number)?,
+            serde_json::Number::from_f64(*s.max() as f64).map(Value::Number)?,
+        )),
+        (Statistics::Double(s), DataType::Float64) => Some((
+            serde_json::Number::from_f64(*s.min()).map(Value::Number)?,
+            serde_json::Number::from_f64(*s.max()).map(Value::Number)?,
+        )),
+        (Statistics::ByteArray(s), DataType::Utf8) => Some((
+            Value::String(std::str::from_utf8(s.min().data()).ok()?.to_string()),
+            Value::String(std::str::from_utf8(s.max().data()).ok()?.to_string()),
+        )),
+        _ => None,
+    }
+}
+
+fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        },
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{ArrayRef, Int32Array, Int64Array, StringArray, StructArray};
+    use arrow::record_batch::RecordBatch;
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::writer::InMemoryWriteableCursor;
+
+    fn write_parquet(batches: Vec<RecordBatch>) -> (parquet_format::FileMetaData, Vec<u8>) {
+        let cursor = InMemoryWriteableCursor::default();
+        let mut writer = ArrowWriter::try_new(cursor.clone(), batches[0].schema(), None).unwrap();
+        for batch in &batches {
+            writer.write(batch).unwrap();
+        }
+        let metadata = writer.close().unwrap();
+        (metadata, cursor.data())
+    }
+
+    fn nested_batch(
+        ids: Vec<Option<i64>>,
+        names: Vec<&str>,
+        ages: Vec<Option<i32>>,
+    ) -> RecordBatch {
+        let person = StructArray::from(vec![
+            (
+                ArrowField::new("name", DataType::Utf8, true),
+                Arc::new(StringArray::from(names)) as ArrayRef,
+            ),
+            (
+                ArrowField::new("age", DataType::Int32, true),
+                Arc::new(Int32Array::from(ages)) as ArrayRef,
+            ),
+        ]);
+        let schema = ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int64, true),
+            ArrowField::new("person", person.data_type().clone(), true),
+        ]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![Arc::new(Int64Array::from(ids)), Arc::new(person)],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_stats_from_file_metadata() {
+        let (metadata, bytes) = write_parquet(vec![
+            nested_batch(vec![Some(3), None], vec!["b", "c"], vec![Some(30), None]),
+            nested_batch(
+                vec![Some(1), Some(7)],
+                vec!["a", "d"],
+                vec![Some(20), Some(40)],
+            ),
+        ]);
+
+        let stats = stats_from_file_metadata(&metadata, Some(DEFAULT_NUM_INDEXED_COLS)).unwrap();
+        assert_eq!(
+            serde_json::to_value(&stats).unwrap(),
+            json!({
+                "numRecords": 4,
+                "minValues": {"id": 1, "person": {"name": "a", "age": 20}},
+                "maxValues": {"id": 7, "person": {"name": "d", "age": 40}},
+                "nullCount": {"id": 1, "person": {"name": 0, "age": 1}},
+            })
+        );
+
+        // the footer read back from the file yields the same statistics
+        let footer = parquet_footer(&bytes).unwrap();
+        let stats_from_bytes = stats_from_footer(&footer, Some(DEFAULT_NUM_INDEXED_COLS)).unwrap();
+        assert_eq!(
+            serde_json::to_value(&stats_from_bytes).unwrap(),
+            serde_json::to_value(&stats).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stats_num_indexed_cols() {
+        let (metadata, _) =
+            write_parquet(vec![nested_batch(vec![Some(1)], vec!["a"], vec![Some(20)])]);
+
+        let stats = stats_from_file_metadata(&metadata, Some(2)).unwrap();
+        assert_eq!(
+            serde_json::to_value(&stats).unwrap(),
+            json!({
+                "numRecords": 1,
+                "minValues": {"id": 1, "person": {"name": "a"}},
+                "maxValues": {"id": 1, "person": {"name": "a"}},
+                "nullCount": {"id": 0, "person": {"name": 0}},
+            })
+        );
+
+        let stats = stats_from_file_metadata(&metadata, Some(0)).unwrap();
+        assert_eq!(stats.num_records, 1);
+        assert!(stats.min_values.is_empty());
+        assert!(stats.null_count.is_empty());
+    }
+
+    #[test]
+    fn test_timestamp_stats_bound_sub_millisecond_values() {
+        use arrow::array::TimestampMicrosecondArray;
+
+        let schema = ArrowSchema::new(vec![ArrowField::new(
+            "ts",
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+            true,
+        )]);
+        // 2021-02-01T00:00:00.000001Z and 2021-02-01T00:00:01.000500Z
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![Arc::new(TimestampMicrosecondArray::from_vec(
+                vec![1_612_137_600_000_001, 1_612_137_601_000_500],
+                None,
+            ))],
+        )
+        .unwrap();
+        let (metadata, _) = write_parquet(vec![batch]);
+
+        let stats = stats_from_file_metadata(&metadata, None).unwrap();
+        assert_eq!(
+            serde_json::to_value(&stats).unwrap(),
+            json!({
+                "numRecords": 2,
+                "minValues": {"ts": "2021-02-01T00:00:00.000Z"},
+                "maxValues": {"ts": "2021-02-01T00:00:01.001Z"},
+                "nullCount": {"ts": 0},
+            })
+        );
+    }
+
+    #[test]
+    fn test_num_indexed_cols() {
+        let mut configuration = HashMap::new();
+        assert_eq!(num_indexed_cols(&configuration).unwrap(), Some(32));
+
+        configuration.insert(
+            "delta.dataSkippingNumIndexedCols".to_string(),
+            "5".to_string(),
+        );
+        assert_eq!(num_indexed_cols(&configuration).unwrap(), Some(5));
+
+        configuration.insert(
+            "delta.dataSkippingNumIndexedCols".to_string(),
+            "-1".to_string(),
+        );
+        assert_eq!(num_indexed_cols(&configuration).unwrap(), None);
+
+        configuration.insert(
+            "delta.dataSkippingNumIndexedCols".to_string(),
+            "many".to_string(),
+        );
+        assert!(num_indexed_cols(&configuration).is_err());
+    }
+}
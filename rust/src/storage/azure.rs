@@ -1,8 +1,11 @@
-//! The Azure Data Lake Storage Gen2 storage backend. It currently only supports read operations.
+//! The Azure Data Lake Storage Gen2 storage backend.
 //!
 //! This module is gated behind the "azure" feature. Its usage also requires
 //! the `AZURE_STORAGE_ACCOUNT` and `AZURE_STORAGE_KEY` environment variables
 //! to be set to the name and key of the Azure Storage Account, respectively.
+//!
+//! Setting `AZURE_STORAGE_USE_EMULATOR` points the backend at a local Azurite
+//! instance using the well known development storage account instead.
 
 use std::error::Error;
 use std::sync::Arc;
@@ -10,11 +13,12 @@ use std::{env, fmt, pin::Pin};
 
 use azure_core::errors::AzureError;
 use azure_core::prelude::*;
+use azure_storage::blob::prelude::{CopyStatus, DeleteSnapshotsMethod};
 use azure_storage::clients::{
     AsBlobClient, AsContainerClient, AsStorageClient, ContainerClient, StorageAccountClient,
 };
 use futures::stream::{Stream, TryStreamExt};
-use log::debug;
+use log::{debug, warn};
 
 use super::{parse_uri, ObjectMeta, StorageBackend, StorageError, UriError};
 
@@ -61,9 +65,23 @@ impl AdlsGen2Backend {
     ///
     /// and will panic if both are unset. This also implies that the backend is
     /// only valid for a single Storage Account.
+    ///
+    /// If `AZURE_STORAGE_USE_EMULATOR` is set, the backend connects to a local
+    /// Azurite emulator with its default account and credentials instead.
     pub fn new(container: &str) -> Result<Self, StorageError> {
         let http_client: Arc<Box<dyn HttpClient>> = Arc::new(Box::new(reqwest::Client::new()));
 
+        if env::var("AZURE_STORAGE_USE_EMULATOR").is_ok() {
+            debug!("Connecting to the Azurite storage emulator");
+            let storage_account_client = StorageAccountClient::new_emulator_default(http_client);
+            return Ok(Self {
+                account: "devstoreaccount1".to_string(),
+                container_client: storage_account_client
+                    .as_storage_client()
+                    .as_container_client(container),
+            });
+        }
+
         let account_name = env::var("AZURE_STORAGE_ACCOUNT").map_err(|_| {
             StorageError::AzureConfig("AZURE_STORAGE_ACCOUNT must be set".to_string())
         })?;
@@ -102,6 +120,12 @@ impl AdlsGen2Backend {
     }
 }
 
+/// Time between two polls of the status of a pending blob copy.
+const COPY_STATUS_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+/// Number of polls of the status of a pending blob copy before the copy is given up, i.e. one
+/// minute.
+const MAX_COPY_STATUS_POLLS: usize = 600;
+
 fn to_storage_err(err: Box<dyn Error + Sync + std::marker::Send>) -> StorageError {
     match err.downcast_ref::<AzureError>() {
         Some(AzureError::UnexpectedHTTPResult(e)) if e.status_code().as_u16() == 404 => {
@@ -111,6 +135,22 @@ fn to_storage_err(err: Box<dyn Error + Sync + std::marker::Send>) -> StorageErro
     }
 }
 
+/// Maps the error of a conditional (`If-None-Match: *`) write to `dst`. Azure answers with
+/// `409 Conflict` or `412 Precondition Failed` when the destination blob already exists.
+fn to_storage_err_noreplace(
+    dst: &str,
+    err: Box<dyn Error + Sync + std::marker::Send>,
+) -> StorageError {
+    match err.downcast_ref::<AzureError>() {
+        Some(AzureError::UnexpectedHTTPResult(e))
+            if matches!(e.status_code().as_u16(), 409 | 412) =>
+        {
+            StorageError::AlreadyExists(dst.to_string())
+        }
+        _ => to_storage_err(err),
+    }
+}
+
 #[async_trait::async_trait]
 impl StorageBackend for AdlsGen2Backend {
     async fn head_obj(&self, path: &str) -> Result<ObjectMeta, StorageError> {
@@ -189,16 +229,119 @@ impl StorageBackend for AdlsGen2Backend {
         Ok(Box::pin(stream))
     }
 
-    async fn put_obj(&self, _path: &str, _obj_bytes: &[u8]) -> Result<(), StorageError> {
-        unimplemented!("put_obj not implemented for azure");
+    async fn put_obj(&self, path: &str, obj_bytes: &[u8]) -> Result<(), StorageError> {
+        debug!("Writing {}", path);
+        let obj = parse_uri(path)?.into_adlsgen2_object()?;
+        self.validate_container(&obj)?;
+
+        self.container_client
+            .as_blob_client(obj.path)
+            .put_block_blob(bytes::Bytes::copy_from_slice(obj_bytes))
+            .execute()
+            .await
+            .map_err(to_storage_err)?;
+
+        Ok(())
     }
 
-    async fn rename_obj(&self, _src: &str, _dst: &str) -> Result<(), StorageError> {
-        unimplemented!("rename_obj not implemented for azure");
+    /// Renames `src` to `dst` with a server side copy guarded by `If-None-Match: *`, followed by
+    /// a delete of `src`. The conditional copy either creates `dst` or fails without touching an
+    /// existing object, which gives us the same atomic no-overwrite semantic as the local file
+    /// backend. Azure may complete the copy asynchronously, `src` is only deleted once the copy
+    /// status of `dst` reports success. If the copy fails, its status is missing or it is still
+    /// pending after `MAX_COPY_STATUS_POLLS` polls, `dst` is deleted and an error returned.
+    async fn rename_obj(&self, src: &str, dst: &str) -> Result<(), StorageError> {
+        debug!("Renaming {} to {}", src, dst);
+        let src_obj = parse_uri(src)?.into_adlsgen2_object()?;
+        self.validate_container(&src_obj)?;
+        let dst_obj = parse_uri(dst)?.into_adlsgen2_object()?;
+        self.validate_container(&dst_obj)?;
+
+        let src_client = self.container_client.as_blob_client(src_obj.path);
+        let src_url =
+            src_client
+                .url_with_segments(None)
+                .map_err(|e| StorageError::AzureGeneric {
+                    source: Box::new(e),
+                })?;
+
+        let dst_client = self.container_client.as_blob_client(dst_obj.path);
+        let mut copy_status = dst_client
+            .copy(&src_url)
+            .if_match_condition(IfMatchCondition::NotMatch("*"))
+            .execute()
+            .await
+            .map_err(|err| to_storage_err_noreplace(dst, err))?
+            .copy_status;
+        let mut copy_status_description = None;
+        let mut num_polls = 0;
+        let copy_error = loop {
+            match copy_status {
+                CopyStatus::Success => break None,
+                CopyStatus::Pending if num_polls < MAX_COPY_STATUS_POLLS => {}
+                CopyStatus::Pending => {
+                    break Some(format!("still pending after {} polls", num_polls));
+                }
+                status => {
+                    break Some(format!(
+                        "{:?} {}",
+                        status,
+                        copy_status_description.unwrap_or_default()
+                    ));
+                }
+            }
+            num_polls += 1;
+            tokio::time::sleep(COPY_STATUS_POLL_INTERVAL).await;
+            let properties = match dst_client.get_properties().execute().await {
+                Ok(response) => response.blob.properties,
+                Err(err) => break Some(format!("cannot read the copy status: {}", err)),
+            };
+            copy_status = match properties.copy_status {
+                Some(status) => status,
+                None => break Some("the copy status is missing".to_string()),
+            };
+            copy_status_description = properties.copy_status_description;
+        };
+        if let Some(reason) = copy_error {
+            // an incomplete `dst` would take its name, e.g. the version of a commit, for good
+            if let Err(err) = dst_client
+                .delete()
+                .delete_snapshots_method(DeleteSnapshotsMethod::Include)
+                .execute()
+                .await
+            {
+                warn!("Failed to delete {} after the failed copy: {}", dst, err);
+            }
+            return Err(StorageError::Generic(format!(
+                "Failed to copy {} to {}: {}",
+                src, dst, reason
+            )));
+        }
+
+        src_client
+            .delete()
+            .delete_snapshots_method(DeleteSnapshotsMethod::Include)
+            .execute()
+            .await
+            .map_err(to_storage_err)?;
+
+        Ok(())
     }
 
-    async fn delete_obj(&self, _path: &str) -> Result<(), StorageError> {
-        unimplemented!("delete_obj not implemented for azure");
+    async fn delete_obj(&self, path: &str) -> Result<(), StorageError> {
+        debug!("Deleting {}", path);
+        let obj = parse_uri(path)?.into_adlsgen2_object()?;
+        self.validate_container(&obj)?;
+
+        self.container_client
+            .as_blob_client(obj.path)
+            .delete()
+            .delete_snapshots_method(DeleteSnapshotsMethod::Include)
+            .execute()
+            .await
+            .map_err(to_storage_err)?;
+
+        Ok(())
     }
 }
 
@@ -4,18 +4,38 @@
 //! Unlike the transaction API on DeltaTable, this higher level writer will also write out the
 //! parquet files
 
-use crate::action::Txn;
-use crate::{DeltaTableError, DeltaTransactionError};
+use crate::action::{self, Action, DeltaOperation, SaveMode, Txn};
+use crate::constraints::DataChecker;
+use crate::delta_arrow;
+use crate::generated_columns::{ColumnGenerator, GeneratedColumn};
+use crate::partitions::{filters_to_predicate, PartitionFilter, PartitionValue};
+use crate::schema::DeltaDataTypeVersion;
+use crate::schema_validation;
+use crate::stats;
+use crate::storage::{self, StorageBackend};
+use crate::{DeltaTable, DeltaTableError, DeltaTableMetaData, DeltaTransactionError};
+use arrow::array::{
+    as_boolean_array, as_primitive_array, as_string_array, new_null_array, Array, ArrayRef,
+    UInt32Array,
+};
+use arrow::datatypes::{
+    DataType, Date32Type, Field, Int16Type, Int32Type, Int64Type, Int8Type, Schema as ArrowSchema,
+    SchemaRef, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+};
 use arrow::record_batch::RecordBatch;
 use log::*;
 use parquet::arrow::ArrowWriter;
 use parquet::basic::Compression;
+use parquet::errors::ParquetError;
 use parquet::file::properties::WriterProperties;
 use parquet::file::writer::InMemoryWriteableCursor;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::io::{Seek, SeekFrom};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
 /// BufferedJsonWriter allows for buffering serde_json::Value rows before flushing to parquet files
 /// and a Delta transaction
@@ -23,29 +43,75 @@ pub struct BufferedJsonWriter {
     table: crate::DeltaTable,
     buffer: HashMap<WriterPartition, Vec<Value>>,
     schema: arrow::datatypes::SchemaRef,
+    /// Schema of the written files, with the columns named by their physical names
+    physical_schema: arrow::datatypes::SchemaRef,
     partitions: Vec<String>,
+    num_indexed_cols: Option<usize>,
+    generator: ColumnGenerator,
+    checker: DataChecker,
+    save_mode: SaveMode,
+    replace_where: Vec<(String, PartitionValue<String>)>,
+    merge_schema: bool,
+    /// Table metadata with the fields of the buffered values merged into the schema, committed
+    /// on flush
+    metadata: Option<DeltaTableMetaData>,
     txns: Vec<Txn>,
 }
 
 impl BufferedJsonWriter {
     /// Attempt to construct the BufferedJsonWriter, will fail if the table's metadata is not
-    /// present
+    /// present or if the table maps its columns by field ids
     pub fn try_new(table: crate::DeltaTable) -> Result<Self, DeltaTableError> {
         let metadata = table.get_metadata()?.clone();
+        let mode = metadata.write_column_mapping_mode()?;
+        let physical_schema = Arc::new(delta_arrow::physical_arrow_schema(&metadata.schema, mode)?);
         let schema = metadata.schema;
         let arrow_schema =
             <arrow::datatypes::Schema as TryFrom<&crate::Schema>>::try_from(&schema).unwrap();
         let schema = Arc::new(arrow_schema);
+        let num_indexed_cols = stats::num_indexed_cols(&metadata.configuration)?;
+        let generator = ColumnGenerator::try_new(&metadata, schema.clone())?;
+        let checker = DataChecker::try_new(&metadata)?;
 
         Ok(Self {
             table,
             schema,
+            physical_schema,
             buffer: HashMap::new(),
             partitions: metadata.partition_columns,
+            num_indexed_cols,
+            generator,
+            checker,
+            save_mode: SaveMode::Append,
+            replace_where: vec![],
+            merge_schema: false,
+            metadata: None,
             txns: vec![],
         })
     }
 
+    /// Sets the save mode of the writes, `SaveMode::Append` by default.
+    pub fn with_save_mode(mut self, save_mode: SaveMode) -> Self {
+        self.save_mode = save_mode;
+        self
+    }
+
+    /// Sets whether fields of the written values missing from the table schema are added to it
+    /// as nullable columns, committed with the data in a new `metaData` action. Otherwise, which
+    /// is the default, `write` rejects values with such fields with
+    /// `DeltaTableError::SchemaMismatch`.
+    pub fn with_merge_schema(mut self, merge_schema: bool) -> Self {
+        self.merge_schema = merge_schema;
+        self
+    }
+
+    /// Restricts a `SaveMode::Overwrite` write to the partitions matching all of the given
+    /// filters. Every row written has to match the filters as well.
+    pub fn with_replace_where(mut self, filters: &[PartitionFilter<&str>]) -> Self {
+        self.replace_where = owned_filters(filters);
+        self
+    }
+
     /// Return the total Values pending in the buffer
     pub fn count(&self, partitions: &WriterPartition) -> Option<usize> {
         self.buffer.get(&partitions).map(|b| b.len())
@@ -56,28 +122,27 @@ impl BufferedJsonWriter {
         self.txns.push(txn);
     }
 
-    /// Write a new Value into the buffer
+    /// Write a new Value into the buffer. Fails if the value has fields that are not in the table
+    /// schema, unless the schema is merged, or values that do not match the types of their
+    /// fields, or if the partition values do not match the partition columns of the table.
+    /// Generated columns the values miss are computed on flush.
     pub fn write(
         &mut self,
         value: Value,
         partitions: WriterPartition,
     ) -> Result<(), DeltaTableError> {
-        match partitions {
+        match &partitions {
             WriterPartition::NoPartitions => {
-                if !self.partitions.is_empty() {
-                    return Err(DeltaTableError::SchemaMismatch {
-                        msg: "Table has partitions but noone were supplied on write".to_string(),
-                    });
-                }
+                schema_validation::validate_partition_values(&self.partitions, None)?
             }
-            WriterPartition::KeyValues { .. } => {
-                if self.partitions.is_empty() {
-                    return Err(DeltaTableError::SchemaMismatch {
-                        msg: "Table has no partitions yet they were supplied on write".to_string(),
-                    });
-                }
+            WriterPartition::KeyValues { partitions } => {
+                schema_validation::validate_partition_values(&self.partitions, Some(partitions))?
             }
         }
+        // unknown fields are added to the schema on flush when merging it
+        if !self.merge_schema {
+            schema_validation::validate_json_value(&self.schema, &value)?;
+        }
 
         if let Some(buffer) = self.buffer.get_mut(&partitions) {
             buffer.push(value);
@@ -90,10 +155,26 @@ impl BufferedJsonWriter {
     /// Flush the buffer, causing a write of parquet files for each set of partitioned information
     /// as well as any buffered txn actions
     ///
-    /// This will create a single transaction in the delta transaction log
+    /// This will create a single transaction in the delta transaction log, unless the save mode
+    /// is `SaveMode::Ignore` and the table already has data, in which case the buffer is dropped.
     pub async fn flush(&mut self) -> Result<(), DeltaTransactionError> {
         use arrow::json::reader::Decoder;
 
+        validate_replace_where(self.save_mode, &self.replace_where)?;
+        if self.save_mode != SaveMode::Append {
+            // the existing data has to be checked or replaced as of the latest version
+            self.table.update_incremental().await?;
+        }
+        if !self.table.check_save_mode(self.save_mode)? {
+            info!("Table already has data, ignoring the buffered rows");
+            self.buffer.clear();
+            self.txns.clear();
+            return Ok(());
+        }
+        if self.merge_schema {
+            self.merge_value_schema()?;
+        }
+
         let mut parquet_bufs = vec![];
 
         for (partitions, values) in self.buffer.iter() {
@@ -105,28 +186,69 @@ impl BufferedJsonWriter {
                 .next_batch(&mut value_iter)
                 .map_err(|source| DeltaTableError::ArrowError { source })?;
 
-            if record_batch.is_some() {
-                let mut pb = ParquetBuffer::try_new(self.schema.clone())?;
-                pb.write_batch(&record_batch.unwrap())?;
-                let _metadata = pb.close();
-                parquet_bufs.push((partitions.clone(), pb.data()));
+            if let Some(record_batch) = record_batch {
+                // generated values missing from the rows, decoded as nulls, are computed per row
+                let record_batch = self.generator.generate(&record_batch).await?;
+                if let WriterPartition::KeyValues { partitions } = partitions {
+                    validate_generated_partition_values(
+                        &record_batch,
+                        self.generator.columns(),
+                        partitions,
+                    )?;
+                }
+                schema_validation::validate_batch(&self.schema, &record_batch)?;
+                self.checker.check(&record_batch).await?;
+                let record_batch = delta_arrow::physical_batch(
+                    self.physical_schema.clone(),
+                    record_batch.columns(),
+                )
+                .map_err(|source| DeltaTableError::ArrowError { source })?;
+                let mut pb = ParquetBuffer::try_new(self.physical_schema.clone())?;
+                pb.write_batch(&record_batch)?;
+                let metadata = pb.close()?;
+                let stats = stats::stats_from_file_metadata(&metadata, self.num_indexed_cols)
+                    .map_err(|source| DeltaTableError::ParquetError { source })?;
+                parquet_bufs.push((partitions.clone(), pb.data(), stats));
             } else {
                 warn!("Attempted to flush an empty RecordBatch from the BufferedJsonWriter");
             }
         }
 
+        let replace_where = borrowed_filters(&self.replace_where);
+        let merged_metadata = self.metadata.take();
+        let metadata = self.table.get_metadata()?.clone();
         let mut dtx = self.table.create_transaction(None);
-        for (partitions, buf) in parquet_bufs {
+        // the table was checked above, this registers the save mode with the transaction so
+        // that concurrent appends conflict with it
+        dtx.check_save_mode(self.save_mode)?;
+
+        if let Some(merged_metadata) = merged_metadata {
+            dtx.add_actions(vec![Action::metaData(action::MetaData::try_from(
+                merged_metadata,
+            )?)]);
+        }
+
+        for (partitions, buf, stats) in parquet_bufs {
             match partitions {
                 WriterPartition::NoPartitions => {
-                    dtx.add_file(&buf, None).await?;
+                    dtx.add_file_with_stats(&buf, None, Some(stats)).await?;
                 }
                 WriterPartition::KeyValues { partitions } => {
-                    dtx.add_file(&buf, Some(partitions)).await?;
+                    // partition values are keyed by the physical names of the columns
+                    let partitions = partitions
+                        .into_iter()
+                        .map(|(key, value)| (metadata.physical_name(&key).to_string(), value))
+                        .collect();
+                    dtx.add_file_with_stats(&buf, Some(partitions), Some(stats))
+                        .await?;
                 }
             }
         }
 
+        if self.save_mode == SaveMode::Overwrite {
+            dtx.overwrite(&replace_where)?;
+        }
+
         dtx.add_actions(
             self.txns
                 .drain(0..)
@@ -134,10 +256,768 @@ impl BufferedJsonWriter {
                 .collect(),
         );
 
-        dtx.commit(None).await?;
+        let partition_by = if self.partitions.is_empty() {
+            None
+        } else {
+            Some(self.partitions.clone())
+        };
+        dtx.commit(Some(DeltaOperation::Write {
+            mode: self.save_mode,
+            partitionBy: partition_by,
+            predicate: filters_to_predicate(&replace_where),
+        }))
+        .await?;
         self.buffer.clear();
         Ok(())
     }
+
+    /// Adds the fields of the buffered values that are missing from the table schema, including
+    /// nested fields of struct columns, to the schema of the written files as nullable columns
+    /// with the types inferred from the values. Columns are not widened: the buffered values are
+    /// validated against the merged schema, failing with `DeltaTableError::SchemaMismatch` for
+    /// values that do not fit the type of their column.
+    fn merge_value_schema(&mut self) -> Result<(), DeltaTableError> {
+        use arrow::json::reader::infer_json_schema_from_iterator;
+
+        let metadata = match &self.metadata {
+            Some(metadata) => metadata,
+            None => self.table.get_metadata()?,
+        };
+        let inferred = infer_json_schema_from_iterator(
+            self.buffer
+                .values()
+                .flatten()
+                .map(|value| Ok(value.clone())),
+        )?;
+        let fields = with_value_fields(self.schema.fields(), inferred.fields());
+        let merged = metadata
+            .schema
+            .merge(&crate::Schema::try_from(&ArrowSchema::new(fields))?)?;
+        if merged.get_fields() == metadata.schema.get_fields() {
+            return self.validate_values(&self.schema);
+        }
+
+        let mut metadata = metadata.clone();
+        metadata.schema = merged;
+        metadata.map_new_columns()?;
+        let schema = Arc::new(ArrowSchema::try_from(&metadata.schema)?);
+        self.validate_values(&schema)?;
+        let mode = metadata.column_mapping_mode()?;
+        self.schema = schema;
+        self.physical_schema =
+            Arc::new(delta_arrow::physical_arrow_schema(&metadata.schema, mode)?);
+        self.generator = ColumnGenerator::try_new(&metadata, self.schema.clone())?;
+        self.checker = DataChecker::try_new(&metadata)?;
+        self.metadata = Some(metadata);
+        Ok(())
+    }
+
+    /// Validates the buffered values against the schema.
+    fn validate_values(&self, schema: &ArrowSchema) -> Result<(), DeltaTableError> {
+        self.buffer
+            .values()
+            .flatten()
+            .try_for_each(|value| schema_validation::validate_json_value(schema, value))
+    }
+}
+
+/// Returns the fields of the table with the fields only the values have appended, including the
+/// nested fields of structs. The fields of the table keep their types, since the types inferred
+/// from JSON values, e.g. `Int64` for every integer, are too coarse to widen them.
+fn with_value_fields(fields: &[Field], value_fields: &[Field]) -> Vec<Field> {
+    let mut merged: Vec<Field> = fields
+        .iter()
+        .map(
+            |field| match value_fields.iter().find(|f| f.name() == field.name()) {
+                Some(value_field) => Field::new(
+                    field.name(),
+                    with_value_type(field.data_type(), value_field.data_type()),
+                    field.is_nullable(),
+                ),
+                None => field.clone(),
+            },
+        )
+        .collect();
+    merged.extend(
+        value_fields
+            .iter()
+            .filter(|value_field| fields.iter().all(|f| f.name() != value_field.name()))
+            .cloned(),
+    );
+    merged
+}
+
+fn with_value_type(data_type: &DataType, value_type: &DataType) -> DataType {
+    match (data_type, value_type) {
+        (DataType::Struct(fields), DataType::Struct(value_fields)) => {
+            DataType::Struct(with_value_fields(fields, value_fields))
+        }
+        (DataType::List(field), DataType::List(value_field)) => {
+            DataType::List(Box::new(Field::new(
+                field.name(),
+                with_value_type(field.data_type(), value_field.data_type()),
+                field.is_nullable(),
+            )))
+        }
+        _ => data_type.clone(),
+    }
+}
+
+/// Size in bytes a data file written by the `RecordBatchWriter` grows to before it is closed and
+/// a new file is started, unless configured otherwise.
+pub const DEFAULT_TARGET_FILE_SIZE: usize = 128 * 1024 * 1024;
+
+/// Name of the partition directory of null partition values, the same as Hive and Spark use.
+const NULL_PARTITION_VALUE_DATA_PATH: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// RecordBatchWriter writes Arrow record batches to parquet data files of a Delta table.
+///
+/// Rows are split by the partition columns of the table and written to one file per partition.
+/// Once a file reaches the target size it is uploaded and a new file is started for the
+/// partition. All files written since the last flush are committed in a single transaction on
+/// `flush`, together with their statistics.
+pub struct RecordBatchWriter {
+    table: DeltaTable,
+    files: DataFileWriter,
+    save_mode: SaveMode,
+    replace_where: Vec<(String, PartitionValue<String>)>,
+    merge_schema: bool,
+    /// Whether the written batches are dropped because the table already has data and the save
+    /// mode is `SaveMode::Ignore`. Checked on the first write since the last flush.
+    ignore_writes: Option<bool>,
+    /// Table metadata with the schemas of the written batches merged into the schema, committed
+    /// on flush
+    metadata: Option<DeltaTableMetaData>,
+    txns: Vec<Txn>,
+}
+
+impl RecordBatchWriter {
+    /// Attempt to construct the RecordBatchWriter, will fail if the table's metadata is not
+    /// present or if the table maps its columns by field ids
+    pub fn try_new(table: DeltaTable) -> Result<Self, DeltaTableError> {
+        let files = DataFileWriter::try_new(&table)?;
+
+        Ok(Self {
+            table,
+            files,
+            save_mode: SaveMode::Append,
+            replace_where: vec![],
+            merge_schema: false,
+            ignore_writes: None,
+            metadata: None,
+            txns: vec![],
+        })
+    }
+
+    /// Sets the size in bytes a data file may grow to before a new file is started.
+    pub fn with_target_file_size(mut self, target_file_size: usize) -> Self {
+        self.files = self.files.with_target_file_size(target_file_size);
+        self
+    }
+
+    /// Sets the save mode of the writes, `SaveMode::Append` by default.
+    pub fn with_save_mode(mut self, save_mode: SaveMode) -> Self {
+        self.save_mode = save_mode;
+        self
+    }
+
+    /// Restricts a `SaveMode::Overwrite` write to the partitions matching all of the given
+    /// filters. Every row written has to match the filters as well.
+    pub fn with_replace_where(mut self, filters: &[PartitionFilter<&str>]) -> Self {
+        self.replace_where = owned_filters(filters);
+        self
+    }
+
+    /// Sets whether the schemas of the written batches are merged into the table schema, see
+    /// `Schema::merge`. The merged schema is committed with the data in a new `metaData` action.
+    /// Batches have to match the table schema otherwise, which is the default.
+    pub fn with_merge_schema(mut self, merge_schema: bool) -> Self {
+        self.merge_schema = merge_schema;
+        self
+    }
+
+    /// Add a txn action to the buffer
+    pub fn record_txn(&mut self, txn: Txn) {
+        self.txns.push(txn);
+    }
+
+    /// Write a record batch into the data files of its partitions. Unless the schema is merged,
+    /// the schema of the batch has to match the schema of the table, including the partition
+    /// columns. Generated columns missing from the batch are computed from its other columns,
+    /// the values of those present have to match their generation expressions.
+    ///
+    /// The save mode is checked before the first batch is written, a `SaveMode::ErrorIfExists`
+    /// write to an existing table fails right away and a `SaveMode::Ignore` write to a table with
+    /// data drops the batches without writing any file.
+    pub async fn write(&mut self, batch: &RecordBatch) -> Result<(), DeltaTableError> {
+        let ignore_writes = match self.ignore_writes {
+            Some(ignore_writes) => ignore_writes,
+            None => {
+                if self.save_mode != SaveMode::Append {
+                    self.table.update_incremental().await?;
+                }
+                let ignore_writes = !self.table.check_save_mode(self.save_mode)?;
+                self.ignore_writes = Some(ignore_writes);
+                ignore_writes
+            }
+        };
+        if ignore_writes {
+            return Ok(());
+        }
+
+        if !self.merge_schema {
+            return self.files.write(batch).await;
+        }
+        // generated columns are computed before they would be filled with nulls by the merge
+        let batch = &self.files.generate_columns(batch).await?;
+
+        let metadata = match &self.metadata {
+            Some(metadata) => metadata,
+            None => self.table.get_metadata()?,
+        };
+        let merged = metadata
+            .schema
+            .merge(&crate::Schema::try_from(batch.schema().as_ref())?)?;
+        if merged.get_fields() != metadata.schema.get_fields() {
+            let mut metadata = metadata.clone();
+            metadata.schema = merged;
+            metadata.map_new_columns()?;
+            self.files.update_schema(&metadata).await?;
+            self.metadata = Some(metadata);
+        }
+
+        let batch = self.files.conform_batch(batch)?;
+        self.files.write(&batch).await
+    }
+
+    /// Flush the open data files and commit every file written since the last flush as well as
+    /// any buffered txn actions.
+    ///
+    /// This will create a single transaction in the delta transaction log, and returns its
+    /// version. If nothing was written or recorded since the last flush, or if the save mode is
+    /// `SaveMode::Ignore` and the table already has data, nothing is committed and the current
+    /// version of the table is returned. Data appended by another writer since the save mode was
+    /// checked makes the commit fail with a conflict under `SaveMode::Ignore`.
+    pub async fn flush(&mut self) -> Result<DeltaDataTypeVersion, DeltaTransactionError> {
+        validate_replace_where(self.save_mode, &self.replace_where)?;
+        if self.ignore_writes.take() == Some(true) {
+            info!("Table already has data, ignoring the written batches");
+            self.metadata = None;
+            self.txns.clear();
+            return Ok(self.table.version);
+        }
+
+        let written_files = self.files.close().await?;
+        if written_files.is_empty() && self.txns.is_empty() && self.metadata.is_none() {
+            return Ok(self.table.version);
+        }
+
+        if self.save_mode != SaveMode::Append {
+            // the existing data has to be checked or replaced as of the latest version
+            self.table.update_incremental().await?;
+        }
+
+        let replace_where = borrowed_filters(&self.replace_where);
+        let metadata = self.metadata.take();
+        let mut dtx = self.table.create_transaction(None);
+        // data may have been added since the save mode was checked on the first write
+        match dtx.check_save_mode(self.save_mode) {
+            Ok(true) => {}
+            result => {
+                info!("Table already has data, deleting the written files");
+                self.files.delete_files(&written_files).await?;
+                self.txns.clear();
+                return result.map(|_| self.table.version);
+            }
+        }
+
+        if let Some(metadata) = metadata {
+            dtx.add_actions(vec![Action::metaData(action::MetaData::try_from(
+                metadata,
+            )?)]);
+        }
+        dtx.add_actions(self.txns.drain(0..).map(Action::txn).collect());
+        dtx.add_actions(written_files.into_iter().map(Action::add).collect());
+        if self.save_mode == SaveMode::Overwrite {
+            dtx.overwrite(&replace_where)?;
+        }
+
+        let partition_by = if self.files.partition_columns.is_empty() {
+            None
+        } else {
+            Some(self.files.partition_columns.clone())
+        };
+        dtx.commit(Some(DeltaOperation::Write {
+            mode: self.save_mode,
+            partitionBy: partition_by,
+            predicate: filters_to_predicate(&replace_where),
+        }))
+        .await
+    }
+}
+
+/// Writes record batches with the schema of a table to new data files of the table, without
+/// committing them. Used by the `RecordBatchWriter` as well as by operations rewriting existing
+/// data files.
+pub(crate) struct DataFileWriter {
+    storage: Box<dyn StorageBackend>,
+    table_uri: String,
+    /// Arrow schema of the table, which incoming record batches have to match
+    arrow_schema: SchemaRef,
+    /// Arrow schema of the data files, i.e. the table schema without the partition columns, with
+    /// the columns named by their physical names
+    data_schema: SchemaRef,
+    partition_columns: Vec<String>,
+    /// Physical names of the partition columns, the keys of the partition values
+    physical_partition_columns: Vec<String>,
+    num_indexed_cols: Option<usize>,
+    /// Computes the generated values missing from the written rows
+    generator: ColumnGenerator,
+    /// Checks the written rows against the constraints and invariants of the table
+    checker: DataChecker,
+    target_file_size: usize,
+    /// Recorded in the add actions of the written files, false for files that only rearrange
+    /// existing data
+    data_change: bool,
+    open_files: HashMap<String, PartitionFile>,
+    written_files: Vec<action::Add>,
+}
+
+/// A data file of a single partition that is still being written to.
+struct PartitionFile {
+    partition_values: Vec<(String, String)>,
+    buffer: ParquetBuffer,
+}
+
+impl DataFileWriter {
+    /// Creates a writer for the current schema of the table. Fails if the table's metadata is
+    /// not present, or if the table maps its columns by field ids, which the Parquet writer
+    /// cannot record.
+    pub(crate) fn try_new(table: &DeltaTable) -> Result<Self, DeltaTableError> {
+        let metadata = table.get_metadata()?;
+        let (arrow_schema, data_schema, physical_partition_columns) = file_schemas(metadata)?;
+        let storage = storage::get_backend_for_uri(&table.table_uri)?;
+        let num_indexed_cols = stats::num_indexed_cols(&metadata.configuration)?;
+        let arrow_schema = Arc::new(arrow_schema);
+        let generator = ColumnGenerator::try_new(metadata, arrow_schema.clone())?;
+        let checker = DataChecker::try_new(metadata)?;
+
+        Ok(Self {
+            storage,
+            table_uri: table.table_uri.clone(),
+            arrow_schema,
+            data_schema: Arc::new(data_schema),
+            partition_columns: metadata.partition_columns.clone(),
+            physical_partition_columns,
+            num_indexed_cols,
+            generator,
+            checker,
+            target_file_size: DEFAULT_TARGET_FILE_SIZE,
+            data_change: true,
+            open_files: HashMap::new(),
+            written_files: vec![],
+        })
+    }
+
+    /// Sets the size in bytes a data file may grow to before a new file is started.
+    pub(crate) fn with_target_file_size(mut self, target_file_size: usize) -> Self {
+        self.target_file_size = target_file_size;
+        self
+    }
+
+    /// Sets whether the written files change the data of the table, true by default.
+    pub(crate) fn with_data_change(mut self, data_change: bool) -> Self {
+        self.data_change = data_change;
+        self
+    }
+
+    /// Switches to the schema of the given table metadata, after uploading the files still open
+    /// with the previous schema. Their add actions are returned by the next `close`.
+    pub(crate) async fn update_schema(
+        &mut self,
+        metadata: &DeltaTableMetaData,
+    ) -> Result<(), DeltaTableError> {
+        let (arrow_schema, data_schema, physical_partition_columns) = file_schemas(metadata)?;
+        let num_indexed_cols = stats::num_indexed_cols(&metadata.configuration)?;
+        let arrow_schema = Arc::new(arrow_schema);
+        let generator = ColumnGenerator::try_new(metadata, arrow_schema.clone())?;
+        let checker = DataChecker::try_new(metadata)?;
+
+        let open_files: Vec<PartitionFile> =
+            self.open_files.drain().map(|(_, file)| file).collect();
+        for file in open_files {
+            let add = self.write_file(file).await?;
+            self.written_files.push(add);
+        }
+
+        self.arrow_schema = arrow_schema;
+        self.data_schema = Arc::new(data_schema);
+        self.partition_columns = metadata.partition_columns.clone();
+        self.physical_partition_columns = physical_partition_columns;
+        self.num_indexed_cols = num_indexed_cols;
+        self.generator = generator;
+        self.checker = checker;
+        Ok(())
+    }
+
+    /// Converts a record batch whose schema was merged into the table schema to the table
+    /// schema. Columns are put in the order of the table, columns missing from the batch are
+    /// filled with nulls and narrower columns are cast to the types of the table.
+    pub(crate) fn conform_batch(
+        &self,
+        batch: &RecordBatch,
+    ) -> Result<RecordBatch, DeltaTableError> {
+        let columns = self
+            .arrow_schema
+            .fields()
+            .iter()
+            .map(|field| match batch.schema().index_of(field.name()) {
+                Ok(i) if batch.column(i).data_type() == field.data_type() => {
+                    Ok(batch.column(i).clone())
+                }
+                Ok(i) => Ok(arrow::compute::cast(batch.column(i), field.data_type())?),
+                Err(_) => Ok(new_null_array(field.data_type(), batch.num_rows())),
+            })
+            .collect::<Result<Vec<ArrayRef>, DeltaTableError>>()?;
+        Ok(RecordBatch::try_new(self.arrow_schema.clone(), columns)?)
+    }
+
+    /// Returns the batch with the generated columns of the table it misses computed from its
+    /// other columns.
+    pub(crate) async fn generate_columns(
+        &self,
+        batch: &RecordBatch,
+    ) -> Result<RecordBatch, DeltaTableError> {
+        self.generator.generate(batch).await
+    }
+
+    /// Write a record batch into the data files of its partitions. The schema of the batch has
+    /// to match the schema of the table, including the partition columns, except for generated
+    /// columns, which are computed if missing. Its rows have to satisfy the constraints and
+    /// invariants of the table.
+    pub(crate) async fn write(&mut self, batch: &RecordBatch) -> Result<(), DeltaTableError> {
+        let batch = &self.generator.generate(batch).await?;
+        schema_validation::validate_batch(&self.arrow_schema, batch)?;
+        // the columns of the batch may be in any order
+        let batch = self.conform_batch(batch)?;
+        self.checker.check(&batch).await?;
+
+        for (partition_values, data) in self.divide_by_partition_values(&batch)? {
+            let partition_path = partition_path(&partition_values);
+            if !self.open_files.contains_key(&partition_path) {
+                let file = PartitionFile {
+                    partition_values,
+                    buffer: ParquetBuffer::try_new(self.data_schema.clone())?,
+                };
+                self.open_files.insert(partition_path.clone(), file);
+            }
+
+            let file = self.open_files.get_mut(&partition_path).unwrap();
+            file.buffer.write_batch(&data)?;
+            if file.buffer.num_bytes()? >= self.target_file_size {
+                let file = self.open_files.remove(&partition_path).unwrap();
+                let add = self.write_file(file).await?;
+                self.written_files.push(add);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Uploads the open data files and returns the add actions of every file written since the
+    /// last call.
+    pub(crate) async fn close(&mut self) -> Result<Vec<action::Add>, DeltaTableError> {
+        let open_files: Vec<PartitionFile> =
+            self.open_files.drain().map(|(_, file)| file).collect();
+        for file in open_files {
+            let add = self.write_file(file).await?;
+            self.written_files.push(add);
+        }
+
+        Ok(self.written_files.drain(0..).collect())
+    }
+
+    /// Deletes written files that are not going to be committed.
+    pub(crate) async fn delete_files(&self, files: &[action::Add]) -> Result<(), DeltaTableError> {
+        for add in files {
+            let uri = self
+                .storage
+                .join_path(&self.table_uri, &action::decode_path(&add.path));
+            self.storage.delete_obj(&uri).await?;
+        }
+        Ok(())
+    }
+
+    /// Splits the record batch by the values of the partition columns. Returned batches only
+    /// contain the data columns.
+    fn divide_by_partition_values(
+        &self,
+        batch: &RecordBatch,
+    ) -> Result<Vec<(Vec<(String, String)>, RecordBatch)>, DeltaTableError> {
+        let data_columns: Vec<ArrayRef> = batch
+            .schema()
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .filter(|(f, _)| !self.partition_columns.contains(f.name()))
+            .map(|(_, c)| c.clone())
+            .collect();
+
+        if self.partition_columns.is_empty() {
+            let data = delta_arrow::physical_batch(self.data_schema.clone(), &data_columns)?;
+            return Ok(vec![(vec![], data)]);
+        }
+
+        let partition_arrays = self
+            .partition_columns
+            .iter()
+            .map(|name| {
+                let i = batch.schema().index_of(name)?;
+                Ok(batch.column(i).clone())
+            })
+            .collect::<Result<Vec<ArrayRef>, DeltaTableError>>()?;
+
+        // row indices of every distinct set of partition values, in order of appearance
+        let mut partitions: Vec<(Vec<String>, Vec<u32>)> = vec![];
+        let mut partition_index: HashMap<Vec<String>, usize> = HashMap::new();
+        for row in 0..batch.num_rows() {
+            let values = partition_arrays
+                .iter()
+                .map(|arr| stringified_partition_value(arr, row))
+                .collect::<Result<Vec<String>, DeltaTableError>>()?;
+            match partition_index.get(&values) {
+                Some(i) => partitions[*i].1.push(row as u32),
+                None => {
+                    partition_index.insert(values.clone(), partitions.len());
+                    partitions.push((values, vec![row as u32]));
+                }
+            }
+        }
+
+        partitions
+            .into_iter()
+            .map(|(values, rows)| -> Result<_, DeltaTableError> {
+                let indices = UInt32Array::from(rows);
+                let columns = data_columns
+                    .iter()
+                    .map(|c| arrow::compute::take(c.as_ref(), &indices, None))
+                    .collect::<Result<Vec<ArrayRef>, _>>()?;
+                let data = delta_arrow::physical_batch(self.data_schema.clone(), &columns)?;
+                let partition_values: Vec<(String, String)> = self
+                    .physical_partition_columns
+                    .iter()
+                    .cloned()
+                    .zip(values.into_iter())
+                    .collect();
+                Ok((partition_values, data))
+            })
+            .collect()
+    }
+
+    /// Closes the file and uploads it to the table location. Returns the add action of the file.
+    async fn write_file(&self, mut file: PartitionFile) -> Result<action::Add, DeltaTableError> {
+        let file_metadata = file.buffer.close()?;
+        let bytes = file.buffer.data();
+
+        let path = format!(
+            "{}part-00000-{}-c000.snappy.parquet",
+            partition_path(&file.partition_values),
+            Uuid::new_v4()
+        );
+        let uri = self.storage.join_path(&self.table_uri, &path);
+        debug!("Writing a parquet file to {}", &uri);
+        self.storage.put_obj(&uri, &bytes).await?;
+
+        let stats = stats::stats_from_file_metadata(&file_metadata, self.num_indexed_cols)?;
+
+        // Err should be impossible in this case since `SystemTime::now()` is always greater than `UNIX_EPOCH`
+        let modification_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        Ok(action::Add {
+            path: action::encode_path(&path),
+            size: bytes.len() as i64,
+            partition_values: file.partition_values.into_iter().collect(),
+            partition_values_parsed: None,
+            modification_time: modification_time.as_millis() as i64,
+            data_change: self.data_change,
+            stats: Some(serde_json::to_string(&stats)?),
+            stats_parsed: None,
+            tags: None,
+        })
+    }
+}
+
+/// Returns the Arrow schemas of the table and of its data files, along with the physical names of
+/// the partition columns. Fails if the table maps its columns by field ids, which the Parquet
+/// writer cannot record.
+fn file_schemas(
+    metadata: &DeltaTableMetaData,
+) -> Result<(ArrowSchema, ArrowSchema, Vec<String>), DeltaTableError> {
+    let mode = metadata.write_column_mapping_mode()?;
+    let arrow_schema = ArrowSchema::try_from(&metadata.schema)?;
+    let physical_schema = delta_arrow::physical_arrow_schema(&metadata.schema, mode)?;
+    let data_schema = ArrowSchema::new(
+        arrow_schema
+            .fields()
+            .iter()
+            .zip(physical_schema.fields())
+            .filter(|(f, _)| !metadata.partition_columns.contains(f.name()))
+            .map(|(_, physical)| physical.clone())
+            .collect(),
+    );
+    let physical_partition_columns = metadata
+        .partition_columns
+        .iter()
+        .map(|c| metadata.physical_name(c).to_string())
+        .collect();
+    Ok((arrow_schema, data_schema, physical_partition_columns))
+}
+
+fn owned_filters(filters: &[PartitionFilter<&str>]) -> Vec<(String, PartitionValue<String>)> {
+    let owned = |values: &Vec<&str>| values.iter().map(|v| v.to_string()).collect();
+    filters
+        .iter()
+        .map(|f| {
+            let value = match &f.value {
+                PartitionValue::Equal(v) => PartitionValue::Equal(v.to_string()),
+                PartitionValue::NotEqual(v) => PartitionValue::NotEqual(v.to_string()),
+                PartitionValue::In(v) => PartitionValue::In(owned(v)),
+                PartitionValue::NotIn(v) => PartitionValue::NotIn(owned(v)),
+            };
+            (f.key.to_string(), value)
+        })
+        .collect()
+}
+
+fn borrowed_filters(filters: &[(String, PartitionValue<String>)]) -> Vec<PartitionFilter<&str>> {
+    let borrowed = |values: &Vec<String>| values.iter().map(|v| v.as_str()).collect();
+    filters
+        .iter()
+        .map(|(key, value)| PartitionFilter {
+            key,
+            value: match value {
+                PartitionValue::Equal(v) => PartitionValue::Equal(v.as_str()),
+                PartitionValue::NotEqual(v) => PartitionValue::NotEqual(v.as_str()),
+                PartitionValue::In(v) => PartitionValue::In(borrowed(v)),
+                PartitionValue::NotIn(v) => PartitionValue::NotIn(borrowed(v)),
+            },
+        })
+        .collect()
+}
+
+fn validate_replace_where(
+    save_mode: SaveMode,
+    replace_where: &[(String, PartitionValue<String>)],
+) -> Result<(), DeltaTableError> {
+    if !replace_where.is_empty() && save_mode != SaveMode::Overwrite {
+        return Err(DeltaTableError::Generic(format!(
+            "Partition filters to replace are only supported by SaveMode::Overwrite, not {:?}",
+            save_mode
+        )));
+    }
+    Ok(())
+}
+
+/// Fails if a generated partition column of the batch holds values other than the value of the
+/// partition the rows are written to.
+fn validate_generated_partition_values(
+    batch: &RecordBatch,
+    generated_columns: &[GeneratedColumn],
+    partitions: &[(String, String)],
+) -> Result<(), DeltaTableError> {
+    for (key, value) in partitions {
+        if generated_columns.iter().all(|column| &column.name != key) {
+            continue;
+        }
+        let column = batch.column(batch.schema().index_of(key)?);
+        for row in 0..batch.num_rows() {
+            let generated = stringified_partition_value(column, row)?;
+            if &generated != value {
+                return Err(DeltaTableError::GeneratedColumn {
+                    msg: format!(
+                        "Row of partition `{}={}` holds the generated value {}",
+                        key, value, generated
+                    ),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the hive style directory of a partition relative to the table root, e.g.
+/// `year=2021/month=01/`. Returns an empty string for unpartitioned data. The directory is not
+/// URI-encoded, unlike the `path` of the add actions.
+fn partition_path(partition_values: &[(String, String)]) -> String {
+    partition_values
+        .iter()
+        .map(|(k, v)| match v.as_str() {
+            "" => format!("{}={}/", k, NULL_PARTITION_VALUE_DATA_PATH),
+            v => format!("{}={}/", k, escape_partition_value(v)),
+        })
+        .collect()
+}
+
+/// Escapes characters that are not allowed in a partition directory name, the same way Hive and
+/// Spark do.
+fn escape_partition_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\u{01}'..='\u{1F}'
+            | '"'
+            | '#'
+            | '%'
+            | '\''
+            | '*'
+            | '/'
+            | ':'
+            | '='
+            | '?'
+            | '\\'
+            | '\u{7F}'
+            | '{'
+            | '['
+            | ']'
+            | '^' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn stringified_partition_value(arr: &ArrayRef, row: usize) -> Result<String, DeltaTableError> {
+    // null partition values are stored as empty strings
+    if arr.is_null(row) {
+        return Ok(String::new());
+    }
+
+    let s = match arr.data_type() {
+        DataType::Int8 => as_primitive_array::<Int8Type>(arr).value(row).to_string(),
+        DataType::Int16 => as_primitive_array::<Int16Type>(arr).value(row).to_string(),
+        DataType::Int32 => as_primitive_array::<Int32Type>(arr).value(row).to_string(),
+        DataType::Int64 => as_primitive_array::<Int64Type>(arr).value(row).to_string(),
+        DataType::UInt8 => as_primitive_array::<UInt8Type>(arr).value(row).to_string(),
+        DataType::UInt16 => as_primitive_array::<UInt16Type>(arr).value(row).to_string(),
+        DataType::UInt32 => as_primitive_array::<UInt32Type>(arr).value(row).to_string(),
+        DataType::UInt64 => as_primitive_array::<UInt64Type>(arr).value(row).to_string(),
+        DataType::Boolean => as_boolean_array(arr).value(row).to_string(),
+        DataType::Utf8 => as_string_array(arr).value(row).to_string(),
+        DataType::Date32 => as_primitive_array::<Date32Type>(arr)
+            .value_as_date(row)
+            .map(|d| d.to_string())
+            .unwrap_or_default(),
+        data_type => {
+            return Err(DeltaTableError::SchemaMismatch {
+                msg: format!(
+                    "Unsupported data type for partition column: {:?}",
+                    data_type
+                ),
+            })
+        }
+    };
+
+    Ok(s)
 }
 
 struct ParquetBuffer {
@@ -169,6 +1049,15 @@ impl ParquetBuffer {
         self.cursor.data()
     }
 
+    /// Number of bytes written to the buffer so far.
+    fn num_bytes(&mut self) -> Result<usize, DeltaTableError> {
+        let position = self
+            .cursor
+            .seek(SeekFrom::Current(0))
+            .map_err(ParquetError::from)?;
+        Ok(position as usize)
+    }
+
     fn close(&mut self) -> Result<parquet_format::FileMetaData, DeltaTableError> {
         self.writer
             .close()
@@ -221,11 +1110,20 @@ mod tests {
         let table = crate::open_table("./tests/data/delta-0.8.0").await.unwrap();
         let mut writer = BufferedJsonWriter::try_new(table).unwrap();
         assert_eq!(writer.count(&WriterPartition::NoPartitions), None);
-        let res = writer.write(json!({"hello":"world"}), WriterPartition::NoPartitions);
+        let res = writer.write(json!({"value": 1}), WriterPartition::NoPartitions);
         assert!(res.is_ok());
         assert_eq!(writer.count(&WriterPartition::NoPartitions), Some(1));
     }
 
+    #[tokio::test]
+    async fn test_writer_write_unknown_field() {
+        let table = crate::open_table("./tests/data/delta-0.8.0").await.unwrap();
+        let mut writer = BufferedJsonWriter::try_new(table).unwrap();
+        let res = writer.write(json!({"hello":"world"}), WriterPartition::NoPartitions);
+        assert!(matches!(res, Err(DeltaTableError::SchemaMismatch { .. })));
+        assert_eq!(writer.count(&WriterPartition::NoPartitions), None);
+    }
+
     #[tokio::test]
     async fn test_writer_write_partition_mismatch() {
         let table = crate::open_table("./tests/data/delta-0.8.0-partitioned")
@@ -236,6 +1134,23 @@ mod tests {
         assert!(res.is_err());
     }
 
+    #[test]
+    fn test_partition_path() {
+        assert_eq!(partition_path(&[]), "");
+        assert_eq!(
+            partition_path(&[
+                ("year".to_string(), "2021".to_string()),
+                ("ts".to_string(), "2021-02-01 10:00:00".to_string()),
+            ]),
+            "year=2021/ts=2021-02-01 10%3A00%3A00/"
+        );
+        assert_eq!(
+            partition_path(&[("ts".to_string(), "".to_string())]),
+            "ts=__HIVE_DEFAULT_PARTITION__/"
+        );
+        assert_eq!(escape_partition_value("a/b=c%"), "a%2Fb%3Dc%25");
+    }
+
     #[tokio::test]
     async fn test_writer_write_partitions_to_nopartition() {
         let table = crate::open_table("./tests/data/delta-0.8.0").await.unwrap();
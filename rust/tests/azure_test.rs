@@ -42,4 +42,46 @@ mod azure {
             }
         );
     }
+
+    /*
+     * Requires a local Azurite emulator with a `deltars` container, see the `azurite-setup`
+     * service in docker-compose.yml
+     */
+    #[ignore]
+    #[tokio::test]
+    async fn test_azurite_put_rename_delete() {
+        use deltalake::StorageError;
+
+        std::env::set_var("AZURE_STORAGE_USE_EMULATOR", "1");
+        let base = "abfss://deltars@devstoreaccount1.dfs.core.windows.net/rename_test";
+        let backend = deltalake::get_backend_for_uri(base).unwrap();
+
+        let tmp_file = format!("{}/tmp_file", base);
+        let new_file = format!("{}/new_file", base);
+        let _ = backend.delete_obj(&new_file).await;
+
+        // first try should result in successful rename
+        backend.put_obj(&tmp_file, b"hello").await.unwrap();
+        backend.rename_obj(&tmp_file, &new_file).await.unwrap();
+        assert_eq!(backend.get_obj(&new_file).await.unwrap(), b"hello");
+        assert!(matches!(
+            backend.head_obj(&tmp_file).await,
+            Err(StorageError::NotFound)
+        ));
+
+        // second try should result in already exists error and leave both objects untouched
+        backend.put_obj(&tmp_file, b"world").await.unwrap();
+        assert!(matches!(
+            backend.rename_obj(&tmp_file, &new_file).await,
+            Err(StorageError::AlreadyExists(s)) if s == new_file,
+        ));
+        assert_eq!(backend.get_obj(&new_file).await.unwrap(), b"hello");
+
+        backend.delete_obj(&tmp_file).await.unwrap();
+        backend.delete_obj(&new_file).await.unwrap();
+        assert!(matches!(
+            backend.head_obj(&new_file).await,
+            Err(StorageError::NotFound)
+        ));
+    }
 }
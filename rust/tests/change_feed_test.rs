@@ -0,0 +1,179 @@
+extern crate deltalake;
+
+#[allow(dead_code)]
+mod fs_common;
+
+use deltalake::action::{self, Action};
+use deltalake::change_feed::ChangeFeed;
+use fs_common::{add, commit_actions, create_id_table, remove};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::time::Duration;
+
+fn txn(app_id: &str, version: i64) -> Action {
+    Action::txn(action::Txn {
+        app_id: app_id.to_string(),
+        version,
+        last_updated: None,
+    })
+}
+
+#[tokio::test]
+async fn change_feed_tails_commits() {
+    let tmp_dir = tempdir::TempDir::new("change_feed").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_id_table(table_path, HashMap::new()).await;
+    commit_actions(
+        &mut table,
+        vec![add("a.parquet", true), txn("app", 1)],
+        None,
+    )
+    .await;
+    // an optimize that rewrites the data without changing it
+    commit_actions(
+        &mut table,
+        vec![add("b.parquet", false), remove("a.parquet", false)],
+        None,
+    )
+    .await;
+
+    let feed = ChangeFeed::new(deltalake::open_table(table_path).await.unwrap(), 1)
+        .with_skip_non_data_change(true)
+        .with_max_versions_per_batch(1)
+        .with_poll_interval(Duration::from_millis(10));
+    let mut changes = Box::pin(feed.into_stream());
+
+    let change = changes.next().await.unwrap().unwrap();
+    assert_eq!(change.version, 1);
+    assert_eq!(change.adds.len(), 1);
+    assert_eq!(change.adds[0].path, "a.parquet");
+    assert!(change.removes.is_empty());
+    assert_eq!(change.txns.len(), 1);
+    assert_eq!(change.txns[0].app_id, "app");
+    assert!(change.commit_info.is_some());
+
+    let change = changes.next().await.unwrap().unwrap();
+    assert_eq!(change.version, 2);
+    assert!(change.adds.is_empty());
+    assert!(change.removes.is_empty());
+
+    // commits written after the feed caught up are picked up by polling the log
+    commit_actions(&mut table, vec![remove("b.parquet", true)], None).await;
+    let change = tokio::time::timeout(Duration::from_secs(10), changes.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(change.version, 3);
+    assert_eq!(change.removes.len(), 1);
+    assert_eq!(change.removes[0].path, "b.parquet");
+}
+
+#[tokio::test]
+async fn change_feed_keeps_non_data_changes_by_default() {
+    let tmp_dir = tempdir::TempDir::new("change_feed").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_id_table(table_path, HashMap::new()).await;
+    commit_actions(
+        &mut table,
+        vec![add("b.parquet", false), remove("a.parquet", false)],
+        None,
+    )
+    .await;
+
+    let feed = ChangeFeed::new(table, 0);
+    let changes: Vec<_> = feed.into_stream().take(2).collect().await;
+    assert_eq!(changes.len(), 2);
+    let versions: Vec<i64> = changes
+        .iter()
+        .map(|change| change.as_ref().unwrap().version)
+        .collect();
+    assert_eq!(versions, vec![0, 1]);
+    let change = changes[1].as_ref().unwrap();
+    assert_eq!(change.adds.len(), 1);
+    assert_eq!(change.removes.len(), 1);
+}
+
+#[tokio::test]
+async fn change_feed_ends_with_error_for_missing_versions() {
+    let tmp_dir = tempdir::TempDir::new("change_feed").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_id_table(table_path, HashMap::new()).await;
+    std::fs::remove_file(
+        tmp_dir
+            .path()
+            .join("_delta_log")
+            .join("00000000000000000000.json"),
+    )
+    .unwrap();
+
+    let feed = ChangeFeed::new(table, 0);
+    let changes: Vec<_> = feed.into_stream().collect().await;
+    assert_eq!(changes.len(), 1);
+    assert!(matches!(
+        changes[0],
+        Err(deltalake::DeltaTableError::InvalidVersion(0))
+    ));
+}
+
+#[tokio::test]
+async fn change_feed_retries_storage_errors() {
+    let tmp_dir = tempdir::TempDir::new("change_feed").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let log_path = tmp_dir.path().join("_delta_log");
+    let mut table = create_id_table(table_path, HashMap::new()).await;
+    commit_actions(&mut table, vec![add("a.parquet", true)], None).await;
+
+    // the commit cannot be read while a directory takes its place
+    let commit_path = log_path.join("00000000000000000001.json");
+    let moved_path = tmp_dir.path().join("00000000000000000001.json");
+    std::fs::rename(&commit_path, &moved_path).unwrap();
+    std::fs::create_dir(&commit_path).unwrap();
+
+    let feed = ChangeFeed::new(table, 0)
+        .with_max_retries(1000)
+        .with_poll_interval(Duration::from_millis(10));
+    let mut changes = Box::pin(feed.into_stream());
+    assert_eq!(changes.next().await.unwrap().unwrap().version, 0);
+
+    let restore_commit = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        std::fs::remove_dir(&commit_path).unwrap();
+        std::fs::rename(&moved_path, &commit_path).unwrap();
+    };
+    let (change, _) = tokio::join!(
+        tokio::time::timeout(Duration::from_secs(10), changes.next()),
+        restore_commit
+    );
+    let change = change.unwrap().unwrap().unwrap();
+    assert_eq!(change.version, 1);
+    assert_eq!(change.adds[0].path, "a.parquet");
+}
+
+#[tokio::test]
+async fn change_feed_ends_with_error_after_retries() {
+    let tmp_dir = tempdir::TempDir::new("change_feed").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_id_table(table_path, HashMap::new()).await;
+    std::fs::create_dir(
+        tmp_dir
+            .path()
+            .join("_delta_log")
+            .join("00000000000000000001.json"),
+    )
+    .unwrap();
+
+    let feed = ChangeFeed::new(table, 0)
+        .with_max_retries(2)
+        .with_poll_interval(Duration::from_millis(10));
+    let changes: Vec<_> = feed.into_stream().collect().await;
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].as_ref().unwrap().version, 0);
+    assert!(matches!(
+        changes[1],
+        Err(deltalake::DeltaTableError::StorageError { .. })
+            | Err(deltalake::DeltaTableError::ApplyLog {
+                source: deltalake::ApplyLogError::Storage { .. }
+            })
+    ));
+}
@@ -1,9 +1,17 @@
 extern crate deltalake;
 
+#[allow(dead_code)]
+mod fs_common;
+
+use deltalake::action;
 use deltalake::checkpoints::CheckPointWriter;
 use deltalake::storage;
+use deltalake::DeltaTransactionOptions;
+use fs_common::{add, commit_actions, create_id_table};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 // NOTE: The below is a useful external command for inspecting the written checkpoint schema visually:
 // parquet-tools inspect tests/data/checkpoints/_delta_log/00000000000000000005.checkpoint.parquet
@@ -62,6 +70,258 @@ async fn write_simple_checkpoint() {
     assert_eq!(11, files.len());
 }
 
+#[tokio::test]
+async fn write_multi_part_checkpoint() {
+    // the log is copied, so that the checkpoint does not interfere with the other tests
+    let tmp_dir = tempdir::TempDir::new("multi_part_checkpoint").unwrap();
+    let table_location = tmp_dir.path().to_str().unwrap();
+    let log_path = tmp_dir.path().join("_delta_log");
+    fs::create_dir(&log_path).unwrap();
+    for entry in fs::read_dir("./tests/data/checkpoints/_delta_log").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().unwrap() == "json" {
+            fs::copy(&path, log_path.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+
+    let expected = deltalake::open_table(table_location).await.unwrap();
+    let table = deltalake::open_table_with_version(table_location, 5)
+        .await
+        .unwrap();
+    let num_actions = table.get_files().len()
+        + table.get_tombstones().len()
+        + table.get_app_transaction_version().len()
+        + 2;
+
+    let storage_backend = storage::get_backend_for_uri(table_location).unwrap();
+    let checkpoint_writer =
+        CheckPointWriter::new(table_location, storage_backend).with_max_actions_per_part(5);
+    checkpoint_writer
+        .create_checkpoint_from_state(table.version, table.get_state())
+        .await
+        .unwrap();
+
+    let num_parts = (num_actions + 4) / 5;
+    assert!(num_parts > 1);
+    for part in 1..=num_parts {
+        let part_path = log_path.join(format!(
+            "00000000000000000005.checkpoint.{:010}.{:010}.parquet",
+            part, num_parts
+        ));
+        assert!(part_path.as_path().exists());
+    }
+    assert!(!log_path
+        .join("00000000000000000005.checkpoint.parquet")
+        .as_path()
+        .exists());
+
+    let last_checkpoint_content =
+        fs::read_to_string(log_path.join("_last_checkpoint").as_path()).unwrap();
+    let last_checkpoint_content: serde_json::Value =
+        serde_json::from_str(last_checkpoint_content.trim()).unwrap();
+    assert_eq!(last_checkpoint_content["version"].as_i64(), Some(5));
+    assert_eq!(
+        last_checkpoint_content["parts"].as_u64(),
+        Some(num_parts as u64)
+    );
+    assert_eq!(
+        last_checkpoint_content["size"].as_u64(),
+        Some(num_actions as u64)
+    );
+
+    // the table is loaded from the parts without the commits they replace
+    for version in 0..=5 {
+        fs::remove_file(log_path.join(format!("{:020}.json", version))).unwrap();
+    }
+    let table = deltalake::open_table(table_location).await.unwrap();
+    assert_eq!(table.version, 11);
+    assert_eq!(table.get_files(), expected.get_files());
+}
+
+#[tokio::test]
+async fn write_checkpoint_at_checkpoint_interval() {
+    let tmp_dir = tempdir::TempDir::new("checkpoint_interval").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let log_path = tmp_dir.path().join("_delta_log");
+    let mut configuration = HashMap::new();
+    configuration.insert("delta.checkpointInterval".to_string(), "3".to_string());
+    let mut table = create_id_table(table_path, configuration).await;
+
+    for i in 1..=3 {
+        commit_actions(&mut table, vec![add(&format!("{}.parquet", i), true)], None).await;
+    }
+    assert!(log_path
+        .join("00000000000000000003.checkpoint.parquet")
+        .as_path()
+        .exists());
+    let last_checkpoint_content =
+        fs::read_to_string(log_path.join("_last_checkpoint").as_path()).unwrap();
+    let last_checkpoint_content: serde_json::Value =
+        serde_json::from_str(last_checkpoint_content.trim()).unwrap();
+    assert_eq!(last_checkpoint_content["version"].as_i64(), Some(3));
+
+    // checkpoints can be disabled per transaction
+    for i in 4..=6 {
+        let options = DeltaTransactionOptions::default().with_create_checkpoint(false);
+        commit_actions(
+            &mut table,
+            vec![add(&format!("{}.parquet", i), true)],
+            Some(options),
+        )
+        .await;
+    }
+    assert!(!log_path
+        .join("00000000000000000006.checkpoint.parquet")
+        .as_path()
+        .exists());
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert_eq!(table.version, 6);
+    assert_eq!(table.get_files().len(), 6);
+}
+
+#[tokio::test]
+async fn cleanup_expired_logs_and_tombstones() {
+    let tmp_dir = tempdir::TempDir::new("log_retention").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let log_path = tmp_dir.path().join("_delta_log");
+    let mut configuration = HashMap::new();
+    configuration.insert("delta.checkpointInterval".to_string(), "2".to_string());
+    configuration.insert(
+        "delta.logRetentionDuration".to_string(),
+        "interval 0 seconds".to_string(),
+    );
+    configuration.insert(
+        "delta.deletedFileRetentionDuration".to_string(),
+        "interval 1 hour".to_string(),
+    );
+    let mut table = create_id_table(table_path, configuration).await;
+
+    commit_actions(&mut table, vec![add("1.parquet", true)], None).await;
+    // removes 1.parquet with an expired and 2.parquet with a recent tombstone
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    let mut tx = table.create_transaction(None);
+    tx.add_actions(vec![
+        action::Action::remove(action::Remove {
+            path: "1.parquet".to_string(),
+            deletion_timestamp: now - 2 * 3600 * 1000,
+            data_change: true,
+            ..Default::default()
+        }),
+        action::Action::remove(action::Remove {
+            path: "2.parquet".to_string(),
+            deletion_timestamp: now,
+            data_change: true,
+            ..Default::default()
+        }),
+    ]);
+    tx.commit(None).await.unwrap();
+    assert!(log_path
+        .join("00000000000000000002.checkpoint.parquet")
+        .as_path()
+        .exists());
+
+    // the checkpoint covers the commits before it
+    let mut log_files: Vec<String> = fs::read_dir(&log_path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_str().unwrap().to_string())
+        .collect();
+    log_files.sort();
+    assert_eq!(
+        log_files,
+        vec![
+            "00000000000000000002.checkpoint.parquet",
+            "00000000000000000002.json",
+            "_last_checkpoint",
+        ]
+    );
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert_eq!(table.version, 2);
+    let tombstones: Vec<&str> = table
+        .get_tombstones()
+        .iter()
+        .map(|tombstone| tombstone.path.as_str())
+        .collect();
+    assert_eq!(tombstones, vec!["2.parquet"]);
+}
+
+#[tokio::test]
+async fn cleanup_expired_logs_separately_from_checkpoint() {
+    let tmp_dir = tempdir::TempDir::new("log_retention").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let log_path = tmp_dir.path().join("_delta_log");
+    let mut configuration = HashMap::new();
+    configuration.insert(
+        "delta.logRetentionDuration".to_string(),
+        "interval 0 seconds".to_string(),
+    );
+    let mut table = create_id_table(table_path, configuration).await;
+    let options = DeltaTransactionOptions::default().with_create_checkpoint(false);
+    commit_actions(&mut table, vec![add("1.parquet", true)], Some(options)).await;
+
+    let storage_backend = storage::get_backend_for_uri(table_path).unwrap();
+    let checkpoint_writer = CheckPointWriter::new(table_path, storage_backend);
+    checkpoint_writer
+        .create_checkpoint_from_state(table.version, table.get_state())
+        .await
+        .unwrap();
+    assert!(log_path.join("00000000000000000000.json").exists());
+
+    let num_deleted = checkpoint_writer
+        .cleanup_expired_logs_from_state(table.version, table.get_state())
+        .await
+        .unwrap();
+    assert_eq!(num_deleted, 1);
+    assert!(!log_path.join("00000000000000000000.json").exists());
+    assert!(log_path.join("00000000000000000001.json").exists());
+}
+
+#[tokio::test]
+async fn cleanup_expired_logs_only_before_complete_checkpoints() {
+    let tmp_dir = tempdir::TempDir::new("log_retention").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let log_path = tmp_dir.path().join("_delta_log");
+    let mut table = create_id_table(table_path, HashMap::new()).await;
+    for i in 1..=2 {
+        commit_actions(&mut table, vec![add(&format!("{}.parquet", i), true)], None).await;
+    }
+
+    // the second part of the checkpoint is missing, the version of the last file is out of range
+    fs::write(
+        log_path.join("00000000000000000002.checkpoint.0000000001.0000000002.parquet"),
+        b"",
+    )
+    .unwrap();
+    fs::write(log_path.join("99999999999999999999.json"), b"").unwrap();
+    let storage_backend = storage::get_backend_for_uri(table_path).unwrap();
+    let checkpoint_writer = CheckPointWriter::new(table_path, storage_backend);
+    let num_deleted = checkpoint_writer
+        .cleanup_expired_logs(2, Duration::from_secs(0))
+        .await
+        .unwrap();
+    assert_eq!(num_deleted, 0);
+    assert!(log_path.join("00000000000000000000.json").exists());
+
+    fs::write(
+        log_path.join("00000000000000000002.checkpoint.0000000002.0000000002.parquet"),
+        b"",
+    )
+    .unwrap();
+    let num_deleted = checkpoint_writer
+        .cleanup_expired_logs(2, Duration::from_secs(0))
+        .await
+        .unwrap();
+    assert_eq!(num_deleted, 2);
+    assert!(!log_path.join("00000000000000000000.json").exists());
+    assert!(!log_path.join("00000000000000000001.json").exists());
+    assert!(log_path.join("00000000000000000002.json").exists());
+    assert!(log_path.join("99999999999999999999.json").exists());
+}
+
 fn cleanup_checkpoint_files(log_path: &Path) {
     let paths = fs::read_dir(log_path).unwrap();
 
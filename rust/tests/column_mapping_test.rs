@@ -0,0 +1,500 @@
+extern crate deltalake;
+
+#[allow(dead_code)]
+mod fs_common;
+
+use arrow::datatypes::{DataType as ArrowDataType, Schema as ArrowSchema};
+use deltalake::column_mapping::{
+    ColumnMappingMode, COLUMN_MAPPING_MODE, COLUMN_MAPPING_PHYSICAL_NAME,
+};
+use deltalake::constraints::{CONSTRAINTS_PREFIX, INVARIANTS};
+use deltalake::generated_columns::GENERATION_EXPRESSION;
+use deltalake::writer::RecordBatchWriter;
+use deltalake::{
+    DeltaTable, DeltaTableError, DeltaTableMetaData, DeltaTransactionError, Schema, SchemaDataType,
+    SchemaField,
+};
+use fs_common::{
+    create_table_from_metadata, record_batch, record_batch_with_schema, table_schema, write_batch,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// Creates a table partitioned by `modified` with a file in `2021-02-01` and one in
+/// `2021-02-02`.
+async fn create_table(path: &str) -> DeltaTable {
+    let table = fs_common::create_table(path, vec!["modified"]).await;
+    let batch = record_batch(
+        vec!["A", "B", "C"],
+        vec![Some(1), Some(2), Some(3)],
+        vec!["2021-02-01", "2021-02-01", "2021-02-02"],
+    );
+    write_batch(table, &batch).await
+}
+
+#[tokio::test]
+async fn enable_column_mapping_assigns_physical_names() {
+    let tmp_dir = tempdir::TempDir::new("column_mapping").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_table(table_path).await;
+
+    table
+        .enable_column_mapping(ColumnMappingMode::Name)
+        .await
+        .unwrap();
+
+    let metadata = table.get_metadata().unwrap();
+    assert_eq!(
+        metadata.configuration.get(COLUMN_MAPPING_MODE).unwrap(),
+        "name"
+    );
+    assert_eq!(
+        metadata.column_mapping_mode().unwrap(),
+        ColumnMappingMode::Name
+    );
+    assert_eq!(table.get_min_reader_version(), 2);
+    assert_eq!(table.get_min_writer_version(), 5);
+    for field in metadata.schema.get_fields() {
+        assert_eq!(field.physical_name(), field.get_name());
+        assert!(field
+            .get_metadata()
+            .contains_key(COLUMN_MAPPING_PHYSICAL_NAME));
+        assert!(field.column_mapping_id().is_some());
+    }
+}
+
+#[tokio::test]
+async fn rename_and_drop_columns() {
+    let tmp_dir = tempdir::TempDir::new("column_mapping").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_table(table_path).await;
+    table
+        .enable_column_mapping(ColumnMappingMode::Name)
+        .await
+        .unwrap();
+    let files: Vec<String> = table.get_files().iter().map(|f| f.to_string()).collect();
+
+    table.rename_column("value", "amount").await.unwrap();
+    table.rename_column("modified", "updated").await.unwrap();
+
+    let metadata = table.get_metadata().unwrap();
+    assert!(metadata.schema.get_field_with_name("value").is_none());
+    assert_eq!(metadata.physical_name("amount"), "value");
+    assert_eq!(metadata.partition_columns, vec!["updated".to_string()]);
+    assert_eq!(table.get_files(), files);
+
+    // new files are written under the physical names of the columns
+    let schema = table.get_schema().unwrap().clone();
+    let batch = record_batch_with_schema(&schema, vec!["D"], vec![Some(4)], vec!["2021-02-03"]);
+    let mut table = write_batch(table, &batch).await;
+    assert!(table
+        .get_active_add_actions()
+        .iter()
+        .all(|add| add.partition_values.contains_key("modified")));
+
+    table.drop_columns(&["amount"]).await.unwrap();
+    let fields: Vec<&str> = table
+        .get_schema()
+        .unwrap()
+        .get_fields()
+        .iter()
+        .map(|field| field.get_name())
+        .collect();
+    assert_eq!(fields, vec!["id", "updated"]);
+    assert_eq!(table.get_files().len(), 3);
+}
+
+#[tokio::test]
+async fn column_mapping_errors() {
+    let tmp_dir = tempdir::TempDir::new("column_mapping").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_table(table_path).await;
+
+    assert!(matches!(
+        table.rename_column("value", "amount").await,
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::ColumnMapping { .. }
+        })
+    ));
+    assert!(matches!(
+        table.enable_column_mapping(ColumnMappingMode::Id).await,
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::ColumnMapping { .. }
+        })
+    ));
+
+    table
+        .enable_column_mapping(ColumnMappingMode::Name)
+        .await
+        .unwrap();
+    assert!(matches!(
+        table.drop_columns(&["modified"]).await,
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::SchemaMismatch { .. }
+        })
+    ));
+    assert!(matches!(
+        table.rename_column("value", "id").await,
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::SchemaMismatch { .. }
+        })
+    ));
+    assert_eq!(table.version, 2);
+}
+
+#[tokio::test]
+async fn columns_referred_to_by_constraints_cannot_be_renamed_or_dropped() {
+    let tmp_dir = tempdir::TempDir::new("column_mapping").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut invariant = HashMap::new();
+    invariant.insert(
+        INVARIANTS.to_string(),
+        json!({"expression": {"expression": "id IS NOT NULL"}}),
+    );
+    let fields = table_schema()
+        .get_fields()
+        .iter()
+        .map(|field| match field.get_name() {
+            "id" => SchemaField::new(
+                "id".to_string(),
+                field.get_type().clone(),
+                true,
+                invariant.clone(),
+            ),
+            _ => field.clone(),
+        })
+        .collect();
+    let mut configuration = HashMap::new();
+    configuration.insert(
+        format!("{}positive", CONSTRAINTS_PREFIX),
+        "value > 0".to_string(),
+    );
+    let metadata =
+        DeltaTableMetaData::new(None, None, None, Schema::new(fields), vec![], configuration);
+    let mut table = create_table_from_metadata(table_path, metadata, 3).await;
+    table
+        .enable_column_mapping(ColumnMappingMode::Name)
+        .await
+        .unwrap();
+
+    for result in vec![
+        table.rename_column("value", "amount").await,
+        table.drop_columns(&["value"]).await,
+        table.rename_column("id", "key").await,
+    ] {
+        match result {
+            Err(DeltaTransactionError::DeltaTable {
+                source: DeltaTableError::SchemaMismatch { msg },
+            }) => assert!(msg.contains("refers to it")),
+            other => panic!("Expected a schema mismatch, got {:?}", other),
+        }
+    }
+
+    // the invariant of a column is dropped with it
+    table.drop_columns(&["id"]).await.unwrap();
+    assert!(table
+        .get_schema()
+        .unwrap()
+        .get_field_with_name("id")
+        .is_none());
+}
+
+#[tokio::test]
+async fn source_columns_of_generated_columns_cannot_be_renamed_or_dropped() {
+    let tmp_dir = tempdir::TempDir::new("column_mapping").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut generated = HashMap::new();
+    generated.insert(GENERATION_EXPRESSION.to_string(), json!("value * 2"));
+    let mut fields = table_schema().get_fields().clone();
+    fields.push(SchemaField::new(
+        "doubled".to_string(),
+        SchemaDataType::primitive("integer".to_string()),
+        true,
+        generated,
+    ));
+    let metadata = DeltaTableMetaData::new(
+        None,
+        None,
+        None,
+        Schema::new(fields),
+        vec![],
+        HashMap::new(),
+    );
+    let mut table = create_table_from_metadata(table_path, metadata, 4).await;
+    table
+        .enable_column_mapping(ColumnMappingMode::Name)
+        .await
+        .unwrap();
+
+    for result in vec![
+        table.rename_column("value", "amount").await,
+        table.drop_columns(&["value"]).await,
+    ] {
+        match result {
+            Err(DeltaTransactionError::DeltaTable {
+                source: DeltaTableError::SchemaMismatch { msg },
+            }) => assert!(msg.contains("Generated column `doubled`")),
+            other => panic!("Expected a schema mismatch, got {:?}", other),
+        }
+    }
+
+    // the generated column can be dropped together with its source column
+    table.drop_columns(&["value", "doubled"]).await.unwrap();
+    assert!(table
+        .get_schema()
+        .unwrap()
+        .get_field_with_name("value")
+        .is_none());
+}
+
+#[tokio::test]
+async fn read_nested_columns_of_spark_table() {
+    let table = deltalake::open_table("./tests/data/column_mapping_nested")
+        .await
+        .unwrap();
+
+    let metadata = table.get_metadata().unwrap();
+    assert_eq!(
+        metadata.column_mapping_mode().unwrap(),
+        ColumnMappingMode::Name
+    );
+    let arrow_schema = ArrowSchema::try_from(&metadata.schema).unwrap();
+    let physical_schema =
+        deltalake::delta_arrow::physical_arrow_schema(&metadata.schema, ColumnMappingMode::Name)
+            .unwrap();
+    let nested_names = |schema: &ArrowSchema| match schema.field(1).data_type() {
+        ArrowDataType::Struct(fields) => fields
+            .iter()
+            .map(|field| field.name().clone())
+            .collect::<Vec<String>>(),
+        _ => unreachable!(),
+    };
+    assert_eq!(nested_names(&arrow_schema), vec!["town", "zip", "country"]);
+    assert_eq!(
+        nested_names(&physical_schema),
+        vec![
+            "col-1c2d3e4f-5a6b-4c7d-8e9f-0a1b2c3d4e5f",
+            "col-7e6d5c4b-3a29-4180-9f8e-7d6c5b4a3928",
+            "col-4b5c6d7e-8f90-4a1b-9c2d-3e4f5a6b7c8d",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn id_mode_tables_are_read_only() {
+    let table = deltalake::open_table("./tests/data/column_mapping_id")
+        .await
+        .unwrap();
+    assert_eq!(
+        table.get_metadata().unwrap().column_mapping_mode().unwrap(),
+        ColumnMappingMode::Id
+    );
+
+    assert!(matches!(
+        RecordBatchWriter::try_new(table),
+        Err(DeltaTableError::ColumnMapping { .. })
+    ));
+}
+
+#[cfg(feature = "datafusion-ext")]
+mod datafusion {
+    use super::*;
+    use arrow::array::{Array, Int32Array, Int64Array, StringArray, StructArray};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::execution::context::ExecutionContext;
+    use datafusion::logical_plan::{binary_expr, col, lit, Operator};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// Copies the table at `source` to `target`, so that tests can write to it.
+    fn copy_table(source: &str, target: &Path) {
+        fs::create_dir(target.join("_delta_log")).unwrap();
+        for dir in &["", "_delta_log"] {
+            for entry in fs::read_dir(Path::new(source).join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_file() {
+                    fs::copy(&path, target.join(dir).join(path.file_name().unwrap())).unwrap();
+                }
+            }
+        }
+    }
+
+    async fn select_nested(table: deltalake::DeltaTable) -> RecordBatch {
+        let mut ctx = ExecutionContext::new();
+        ctx.register_table("demo", Arc::new(table)).unwrap();
+        let batches = ctx
+            .sql("SELECT id, address FROM demo ORDER BY id")
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        batches[0].clone()
+    }
+
+    fn assert_nested_values(batch: &RecordBatch, ids: Vec<i64>) {
+        assert_eq!(
+            batch.column(0).as_ref(),
+            Arc::new(Int64Array::from(ids)).as_ref(),
+        );
+        let address = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap();
+        assert_eq!(address.null_count(), 1);
+        assert_eq!(
+            address.column_by_name("town").unwrap().as_ref(),
+            Arc::new(StringArray::from(vec![Some("Berlin"), Some("Paris"), None])).as_ref(),
+        );
+        assert_eq!(
+            address.column_by_name("zip").unwrap().as_ref(),
+            Arc::new(Int32Array::from(vec![Some(10115), Some(75001), None])).as_ref(),
+        );
+        assert_eq!(address.column_by_name("country").unwrap().null_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_datafusion_nested_columns_of_spark_table() {
+        let table = deltalake::open_table("./tests/data/column_mapping_nested")
+            .await
+            .unwrap();
+
+        let batch = select_nested(table).await;
+
+        assert_nested_values(&batch, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn rewrite_nested_columns_of_spark_table() {
+        let tmp_dir = tempdir::TempDir::new("column_mapping").unwrap();
+        copy_table("./tests/data/column_mapping_nested", tmp_dir.path());
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = deltalake::open_table(table_path).await.unwrap();
+
+        let mut assignments = HashMap::new();
+        assignments.insert(
+            "id".to_string(),
+            binary_expr(col("id"), Operator::Plus, lit(10i64)),
+        );
+        table.update_rows(None, assignments).await.unwrap();
+
+        // the rewritten file names the nested fields by their physical names
+        let path = table.get_file_uris()[0].clone();
+        let reader = SerializedFileReader::new(fs::File::open(path).unwrap()).unwrap();
+        let file_schema = reader
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .root_schema();
+        let address = &file_schema.get_fields()[1];
+        assert_eq!(address.name(), "col-9a8b7c6d-1e2f-4a3b-8c5d-6e7f8a9b0c1d");
+        let nested_names: Vec<&str> = address.get_fields().iter().map(|f| f.name()).collect();
+        assert_eq!(
+            nested_names,
+            vec![
+                "col-1c2d3e4f-5a6b-4c7d-8e9f-0a1b2c3d4e5f",
+                "col-7e6d5c4b-3a29-4180-9f8e-7d6c5b4a3928",
+                "col-4b5c6d7e-8f90-4a1b-9c2d-3e4f5a6b7c8d",
+            ]
+        );
+
+        let batch = select_nested(table).await;
+        assert_nested_values(&batch, vec![11, 12, 13]);
+    }
+
+    #[tokio::test]
+    async fn test_datafusion_renamed_and_dropped_columns() {
+        let tmp_dir = tempdir::TempDir::new("column_mapping").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_table(table_path).await;
+        table
+            .enable_column_mapping(ColumnMappingMode::Name)
+            .await
+            .unwrap();
+        table.rename_column("value", "amount").await.unwrap();
+        table.rename_column("modified", "updated").await.unwrap();
+
+        let mut ctx = ExecutionContext::new();
+        ctx.register_table("demo", Arc::new(table)).unwrap();
+        let batches = ctx
+            .sql("SELECT id, amount, updated FROM demo WHERE amount > 1 ORDER BY id")
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(
+            batch.column(0).as_ref(),
+            Arc::new(StringArray::from(vec!["B", "C"])).as_ref(),
+        );
+        assert_eq!(
+            batch.column(1).as_ref(),
+            Arc::new(Int32Array::from(vec![2, 3])).as_ref(),
+        );
+        assert_eq!(
+            batch.column(2).as_ref(),
+            Arc::new(StringArray::from(vec!["2021-02-01", "2021-02-02"])).as_ref(),
+        );
+
+        let mut table = deltalake::open_table(table_path).await.unwrap();
+        table.drop_columns(&["amount"]).await.unwrap();
+        let mut ctx = ExecutionContext::new();
+        ctx.register_table("demo", Arc::new(table)).unwrap();
+        let batches = ctx
+            .sql("SELECT * FROM demo")
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let num_rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(num_rows, 3);
+        assert!(batches.iter().all(|batch| batch.num_columns() == 2));
+        assert!(batches
+            .iter()
+            .all(|batch| batch.column(1).null_count() == 0));
+    }
+
+    #[tokio::test]
+    async fn test_datafusion_id_mode_table() {
+        // the second data file names its columns by their logical names, both carry field ids
+        let table = deltalake::open_table("./tests/data/column_mapping_id")
+            .await
+            .unwrap();
+        let mut ctx = ExecutionContext::new();
+        ctx.register_table("demo", Arc::new(table)).unwrap();
+
+        let batches = ctx
+            .sql("SELECT id, full_name, address FROM demo ORDER BY id")
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(
+            batch.column(0).as_ref(),
+            Arc::new(Int64Array::from(vec![1, 2, 3])).as_ref(),
+        );
+        assert_eq!(
+            batch.column(1).as_ref(),
+            Arc::new(StringArray::from(vec!["Ann", "Bob", "Cy"])).as_ref(),
+        );
+        let address = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap();
+        assert_eq!(address.null_count(), 1);
+        assert_eq!(
+            address.column_by_name("city").unwrap().as_ref(),
+            Arc::new(StringArray::from(vec![Some("Oslo"), None, Some("Rome")])).as_ref(),
+        );
+    }
+}
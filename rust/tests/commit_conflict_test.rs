@@ -0,0 +1,282 @@
+extern crate deltalake;
+
+#[allow(dead_code)]
+mod fs_common;
+
+use deltalake::action::{self, IsolationLevel, SaveMode};
+use deltalake::{CommitConflictError, DeltaTransactionError, DeltaTransactionOptions};
+use serial_test::serial;
+use std::collections::HashMap;
+
+const TABLE_PATH: &str = "./tests/data/commit_conflicts";
+
+#[tokio::test]
+#[serial]
+async fn test_concurrent_appends_do_not_conflict() {
+    prepare_fs();
+    let (mut winner, mut loser) = open_tables_after_first_commit().await;
+
+    let mut tx = loser.create_transaction(None);
+    tx.add_action(add("c.parquet"));
+
+    commit(&mut winner, vec![add("d.parquet")]).await;
+
+    assert_eq!(3, tx.commit(None).await.unwrap());
+    assert_eq!(4, loser.get_files().len());
+}
+
+#[tokio::test]
+#[serial]
+async fn test_concurrent_delete_conflicts_with_delete() {
+    prepare_fs();
+    let (mut winner, mut loser) = open_tables_after_first_commit().await;
+
+    let mut tx = loser.create_transaction(None);
+    tx.add_action(remove("a.parquet"));
+
+    commit(&mut winner, vec![remove("a.parquet")]).await;
+
+    let result = tx.commit(None).await;
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::CommitConflict {
+            source: CommitConflictError::ConcurrentDeleteDelete { version: 2, .. }
+        })
+    ));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_concurrent_delete_conflicts_with_read() {
+    prepare_fs();
+    let (mut winner, mut loser) = open_tables_after_first_commit().await;
+
+    let mut tx = loser.create_transaction(None);
+    tx.add_read_files(vec!["a.parquet".to_string()]);
+    tx.add_action(add("c.parquet"));
+
+    commit(&mut winner, vec![remove("a.parquet")]).await;
+
+    let result = tx.commit(None).await;
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::CommitConflict {
+            source: CommitConflictError::ConcurrentDeleteRead { version: 2, ref path }
+        }) if path == "a.parquet"
+    ));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_concurrent_append_conflicts_when_serializable() {
+    prepare_fs();
+    let (mut winner, mut loser) = open_tables_after_first_commit().await;
+
+    let options =
+        DeltaTransactionOptions::default().with_isolation_level(IsolationLevel::Serializable);
+    let mut tx = loser.create_transaction(Some(options));
+    tx.mark_read_whole_table();
+    tx.add_action(add("c.parquet"));
+
+    commit(&mut winner, vec![add("d.parquet")]).await;
+
+    let result = tx.commit(None).await;
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::CommitConflict {
+            source: CommitConflictError::ConcurrentAppend { version: 2 }
+        })
+    ));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_concurrent_append_conflicts_with_save_mode_of_empty_table() {
+    prepare_fs();
+    let mut winner = deltalake::open_table(TABLE_PATH).await.unwrap();
+    let mut loser = deltalake::open_table(TABLE_PATH).await.unwrap();
+
+    let mut tx = loser.create_transaction(None);
+    assert!(tx.check_save_mode(SaveMode::Ignore).unwrap());
+    tx.add_action(add("c.parquet"));
+
+    commit(&mut winner, vec![add("d.parquet")]).await;
+
+    let result = tx.commit(None).await;
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::CommitConflict {
+            source: CommitConflictError::ConcurrentAppend { version: 1 }
+        })
+    ));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_concurrent_txn_conflicts_with_same_app_id() {
+    prepare_fs();
+    let (mut winner, mut loser) = open_tables_after_first_commit().await;
+
+    let mut tx = loser.create_transaction(None);
+    tx.add_actions(vec![txn("app", 2), add("c.parquet")]);
+
+    commit(&mut winner, vec![txn("app", 1), add("d.parquet")]).await;
+
+    let result = tx.commit(None).await;
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::CommitConflict {
+            source: CommitConflictError::ConcurrentTransaction { version: 2, ref app_id }
+        }) if app_id == "app"
+    ));
+}
+
+#[cfg(feature = "datafusion-ext")]
+mod datafusion {
+    use super::fs_common::{self, create_table, record_batch, write_batch};
+    use deltalake::storage::file::FileStorageBackend;
+    use deltalake::storage::{ObjectMeta, StorageBackend, StorageError};
+    use deltalake::{CommitConflictError, DeltaTransactionError};
+    use futures::Stream;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+
+    /// A file storage backend that commits an append as the next version of the log the first
+    /// time a data file is read.
+    #[derive(Debug)]
+    struct AppendOnReadBackend {
+        inner: FileStorageBackend,
+        commit: Mutex<Option<(String, Vec<u8>)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl StorageBackend for AppendOnReadBackend {
+        async fn head_obj(&self, path: &str) -> Result<ObjectMeta, StorageError> {
+            self.inner.head_obj(path).await
+        }
+
+        async fn get_obj(&self, path: &str) -> Result<Vec<u8>, StorageError> {
+            if path.ends_with(".parquet") {
+                let commit = self.commit.lock().unwrap().take();
+                if let Some((commit_path, commit)) = commit {
+                    self.inner.put_obj(&commit_path, &commit).await?;
+                }
+            }
+            self.inner.get_obj(path).await
+        }
+
+        async fn list_objs<'a>(
+            &'a self,
+            path: &'a str,
+        ) -> Result<
+            Pin<Box<dyn Stream<Item = Result<ObjectMeta, StorageError>> + Send + 'a>>,
+            StorageError,
+        > {
+            self.inner.list_objs(path).await
+        }
+
+        async fn put_obj(&self, path: &str, obj_bytes: &[u8]) -> Result<(), StorageError> {
+            self.inner.put_obj(path, obj_bytes).await
+        }
+
+        async fn rename_obj(&self, src: &str, dst: &str) -> Result<(), StorageError> {
+            self.inner.rename_obj(src, dst).await
+        }
+
+        async fn delete_obj(&self, path: &str) -> Result<(), StorageError> {
+            self.inner.delete_obj(path).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_append_conflicts_with_add_constraint() {
+        let tmp_dir = tempdir::TempDir::new("add_constraint_conflict").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let table = create_table(table_path, vec![]).await;
+        write_batch(
+            table,
+            &record_batch(vec!["A"], vec![Some(1)], vec!["2021-02-01"]),
+        )
+        .await;
+
+        // the append is committed while the constraint checks the existing data
+        let append = serde_json::to_string(&fs_common::add("c.parquet", true)).unwrap();
+        let backend = AppendOnReadBackend {
+            inner: FileStorageBackend::new(table_path),
+            commit: Mutex::new(Some((
+                tmp_dir
+                    .path()
+                    .join("_delta_log")
+                    .join("00000000000000000002.json")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+                append.into_bytes(),
+            ))),
+        };
+        let mut table = deltalake::DeltaTable::new(table_path, Box::new(backend)).unwrap();
+        table.load().await.unwrap();
+        assert_eq!(table.version, 1);
+
+        let result = table.add_constraint("positive", "value > 0").await;
+        assert!(matches!(
+            result,
+            Err(DeltaTransactionError::CommitConflict {
+                source: CommitConflictError::ConcurrentAppend { version: 2 }
+            })
+        ));
+    }
+}
+
+async fn open_tables_after_first_commit() -> (deltalake::DeltaTable, deltalake::DeltaTable) {
+    let mut winner = deltalake::open_table(TABLE_PATH).await.unwrap();
+    commit(&mut winner, vec![add("a.parquet"), add("b.parquet")]).await;
+    let loser = deltalake::open_table(TABLE_PATH).await.unwrap();
+    assert_eq!(1, loser.version);
+
+    (winner, loser)
+}
+
+async fn commit(table: &mut deltalake::DeltaTable, actions: Vec<action::Action>) {
+    let mut tx = table.create_transaction(None);
+    tx.add_actions(actions);
+    tx.commit(None).await.unwrap();
+}
+
+fn add(path: &str) -> action::Action {
+    action::Action::add(action::Add {
+        path: path.to_string(),
+        size: 396,
+        partition_values: HashMap::new(),
+        partition_values_parsed: None,
+        modification_time: 1564524294000,
+        data_change: true,
+        stats: None,
+        stats_parsed: None,
+        tags: None,
+    })
+}
+
+fn remove(path: &str) -> action::Action {
+    action::Action::remove(action::Remove {
+        path: path.to_string(),
+        deletion_timestamp: 1564524298000,
+        data_change: true,
+        ..Default::default()
+    })
+}
+
+fn txn(app_id: &str, version: i64) -> action::Action {
+    action::Action::txn(action::Txn {
+        app_id: app_id.to_string(),
+        version,
+        last_updated: None,
+    })
+}
+
+fn prepare_fs() {
+    fs_common::cleanup_dir_except(
+        format!("{}/_delta_log", TABLE_PATH),
+        vec!["00000000000000000000.json".to_string()],
+    );
+}
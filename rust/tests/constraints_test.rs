@@ -0,0 +1,140 @@
+extern crate deltalake;
+
+#[allow(dead_code)]
+mod fs_common;
+
+#[cfg(feature = "datafusion-ext")]
+mod datafusion {
+    use super::fs_common::{create_table, record_batch};
+    use arrow::record_batch::RecordBatch;
+    use deltalake::constraints::{Constraint, CONSTRAINTS_PREFIX};
+    use deltalake::writer::RecordBatchWriter;
+    use deltalake::{DeltaTable, DeltaTableError, DeltaTransactionError};
+
+    async fn write_batch(table: DeltaTable, batch: &RecordBatch) -> Result<(), DeltaTableError> {
+        let mut writer = RecordBatchWriter::try_new(table)?;
+        writer.write(batch).await?;
+        writer.flush().await.unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn add_and_drop_constraint() {
+        let tmp_dir = tempdir::TempDir::new("constraints").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let table = create_table(table_path, vec![]).await;
+        write_batch(
+            table,
+            &record_batch(
+                vec!["A", "B"],
+                vec![Some(1), Some(2)],
+                vec!["2021-02-01"; 2],
+            ),
+        )
+        .await
+        .unwrap();
+
+        let mut table = deltalake::open_table(table_path).await.unwrap();
+        table.add_constraint("positive", "value > 0").await.unwrap();
+        assert_eq!(table.get_min_writer_version(), 3);
+        let metadata = table.get_metadata().unwrap();
+        assert_eq!(
+            metadata
+                .configuration
+                .get(&format!("{}positive", CONSTRAINTS_PREFIX))
+                .unwrap(),
+            "value > 0"
+        );
+        assert_eq!(
+            metadata.get_constraints(),
+            vec![Constraint {
+                name: "positive".to_string(),
+                expression: "value > 0".to_string(),
+            }]
+        );
+
+        // rows violating the constraint are rejected before any file is written
+        let result = write_batch(
+            table,
+            &record_batch(
+                vec!["C", "D"],
+                vec![Some(3), Some(-1)],
+                vec!["2021-02-01"; 2],
+            ),
+        )
+        .await;
+        match result {
+            Err(DeltaTableError::ConstraintViolation { msg }) => {
+                assert!(msg.contains("positive"));
+            }
+            other => panic!("Expected a constraint violation, got {:?}", other),
+        }
+        let table = deltalake::open_table(table_path).await.unwrap();
+        assert_eq!(table.get_files().len(), 1);
+
+        write_batch(
+            table,
+            &record_batch(vec!["C"], vec![Some(3)], vec!["2021-02-01"]),
+        )
+        .await
+        .unwrap();
+        let mut table = deltalake::open_table(table_path).await.unwrap();
+        assert_eq!(table.get_files().len(), 2);
+
+        table.drop_constraint("positive", false).await.unwrap();
+        assert!(table.get_metadata().unwrap().get_constraints().is_empty());
+        write_batch(
+            table,
+            &record_batch(vec!["D"], vec![Some(-1)], vec!["2021-02-01"]),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn add_constraint_errors() {
+        let tmp_dir = tempdir::TempDir::new("constraints").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let table = create_table(table_path, vec![]).await;
+        write_batch(
+            table,
+            &record_batch(
+                vec!["A", "B"],
+                vec![Some(1), Some(-2)],
+                vec!["2021-02-01"; 2],
+            ),
+        )
+        .await
+        .unwrap();
+        let mut table = deltalake::open_table(table_path).await.unwrap();
+
+        // existing rows violate the constraint
+        assert!(matches!(
+            table.add_constraint("positive", "value > 0").await,
+            Err(DeltaTransactionError::DeltaTable {
+                source: DeltaTableError::ConstraintViolation { .. }
+            })
+        ));
+        assert!(matches!(
+            table.add_constraint("unknown", "missing > 0").await,
+            Err(DeltaTransactionError::DeltaTable {
+                source: DeltaTableError::InvalidConstraint { .. }
+            })
+        ));
+
+        table.add_constraint("id", "id IS NOT NULL").await.unwrap();
+        assert!(matches!(
+            table.add_constraint("ID", "id <> 'Z'").await,
+            Err(DeltaTransactionError::DeltaTable {
+                source: DeltaTableError::InvalidConstraint { .. }
+            })
+        ));
+        assert!(matches!(
+            table.drop_constraint("positive", false).await,
+            Err(DeltaTransactionError::DeltaTable {
+                source: DeltaTableError::InvalidConstraint { .. }
+            })
+        ));
+        assert_eq!(table.drop_constraint("positive", true).await.unwrap(), 2);
+    }
+}
@@ -0,0 +1,130 @@
+extern crate deltalake;
+
+use deltalake::{
+    action, DeltaTable, DeltaTableError, DeltaTableMetaData, DeltaTransactionError, Schema,
+    SchemaDataType, SchemaField,
+};
+use std::collections::HashMap;
+
+fn table_metadata() -> DeltaTableMetaData {
+    let schema = Schema::new(vec![
+        SchemaField::new(
+            "id".to_string(),
+            SchemaDataType::primitive("string".to_string()),
+            true,
+            HashMap::new(),
+        ),
+        SchemaField::new(
+            "value".to_string(),
+            SchemaDataType::primitive("integer".to_string()),
+            true,
+            HashMap::new(),
+        ),
+        SchemaField::new(
+            "modified".to_string(),
+            SchemaDataType::primitive("string".to_string()),
+            true,
+            HashMap::new(),
+        ),
+    ]);
+
+    let mut configuration = HashMap::new();
+    configuration.insert("delta.appendOnly".to_string(), "true".to_string());
+
+    DeltaTableMetaData::new(
+        Some("test_table".to_string()),
+        Some("a table for testing".to_string()),
+        None,
+        schema,
+        vec!["modified".to_string()],
+        configuration,
+    )
+}
+
+fn protocol() -> action::Protocol {
+    action::Protocol {
+        min_reader_version: 1,
+        min_writer_version: 2,
+    }
+}
+
+fn new_table(path: &str) -> DeltaTable {
+    let backend = deltalake::get_backend_for_uri(path).unwrap();
+    DeltaTable::new(path, backend).unwrap()
+}
+
+#[tokio::test]
+async fn create_table() {
+    let tmp_dir = tempdir::TempDir::new("create_table").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+
+    let mut table = new_table(table_path);
+    table.create(table_metadata(), protocol()).await.unwrap();
+
+    assert_eq!(table.version, 0);
+    assert_eq!(table.get_min_reader_version(), 1);
+    assert_eq!(table.get_min_writer_version(), 2);
+    assert_eq!(table.get_files().len(), 0);
+
+    let metadata = table.get_metadata().unwrap();
+    assert_eq!(metadata.name.as_deref(), Some("test_table"));
+    assert_eq!(metadata.description.as_deref(), Some("a table for testing"));
+    assert_eq!(metadata.format.provider(), "parquet");
+    assert_eq!(metadata.partition_columns, vec!["modified"]);
+    assert_eq!(metadata.configuration["delta.appendOnly"], "true");
+    assert_eq!(metadata.schema.get_fields().len(), 3);
+
+    let history = table.history(None).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].1.operation.as_deref(), Some("CREATE TABLE"));
+    assert_eq!(history[0].1.read_version, None);
+
+    // the table can be opened like any other table
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert_eq!(table.version, 0);
+    assert_eq!(table.get_schema().unwrap().get_fields().len(), 3);
+}
+
+#[tokio::test]
+async fn create_table_fails_if_table_exists() {
+    let tmp_dir = tempdir::TempDir::new("create_table_exists").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+
+    new_table(table_path)
+        .create(table_metadata(), protocol())
+        .await
+        .unwrap();
+
+    let result = new_table(table_path)
+        .create(table_metadata(), protocol())
+        .await;
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::TableAlreadyExists(_)
+        })
+    ));
+
+    // only the first commit is in the log
+    let log_files = std::fs::read_dir(tmp_dir.path().join("_delta_log"))
+        .unwrap()
+        .count();
+    assert_eq!(log_files, 1);
+}
+
+#[tokio::test]
+async fn create_table_fails_with_unknown_partition_column() {
+    let tmp_dir = tempdir::TempDir::new("create_table_partitions").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+
+    let mut metadata = table_metadata();
+    metadata.partition_columns = vec!["year".to_string()];
+
+    let result = new_table(table_path).create(metadata, protocol()).await;
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::SchemaMismatch { .. }
+        })
+    ));
+}
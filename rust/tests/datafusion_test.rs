@@ -1,13 +1,20 @@
+#[allow(dead_code)]
+mod fs_common;
+
 #[cfg(feature = "datafusion-ext")]
 mod datafusion {
+    use std::fs;
     use std::sync::Arc;
 
     use arrow::array::*;
     use datafusion::datasource::TableProvider;
     use datafusion::error::Result;
     use datafusion::execution::context::ExecutionContext;
+    use datafusion::logical_plan::{col, lit, Expr};
     use datafusion::scalar::ScalarValue;
 
+    use super::fs_common::create_populated_table;
+
     #[tokio::test]
     async fn test_datafusion_simple_query() -> Result<()> {
         let mut ctx = ExecutionContext::new();
@@ -119,4 +126,103 @@ mod datafusion {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_datafusion_scan_skips_files() -> Result<()> {
+        let table = deltalake::open_table("./tests/data/delta-0.8.0")
+            .await
+            .unwrap();
+
+        let plan = table.scan(&None, 1024, &[], None)?;
+        assert_eq!(plan.output_partitioning().partition_count(), 2);
+
+        // the two files hold values from 0 to 2 and from 2 to 4, without nulls
+        let plan = table.scan(&None, 1024, &[col("value").gt(lit(2))], None)?;
+        assert_eq!(plan.output_partitioning().partition_count(), 1);
+
+        let plan = table.scan(&None, 1024, &[col("value").lt_eq(lit(2))], None)?;
+        assert_eq!(plan.output_partitioning().partition_count(), 2);
+
+        let plan = table.scan(&None, 1024, &[Expr::IsNull(Box::new(col("value")))], None)?;
+        assert_eq!(plan.output_partitioning().partition_count(), 0);
+
+        let mut ctx = ExecutionContext::new();
+        ctx.register_table("test_table", Arc::new(table))?;
+        let batches = ctx
+            .sql("SELECT value FROM test_table WHERE value > 2")?
+            .collect()
+            .await?;
+        let num_rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(num_rows, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_datafusion_scan_opens_files_on_execution() -> Result<()> {
+        let tmp_dir = tempdir::TempDir::new("datafusion_scan").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let table = create_populated_table(table_path).await;
+        let removed = table.get_file_uris()[0].clone();
+        fs::remove_file(&removed).unwrap();
+
+        // planning the scan does not read the data files
+        let plan = table.scan(&None, 1024, &[], None)?;
+        assert_eq!(plan.output_partitioning().partition_count(), 3);
+
+        // only the partition of the removed file fails
+        let mut failed = 0;
+        for partition in 0..3 {
+            if plan.execute(partition).await.is_err() {
+                failed += 1;
+            }
+        }
+        assert_eq!(failed, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_datafusion_partitioned_table() -> Result<()> {
+        let table = deltalake::open_table("./tests/data/delta-0.8.0-partitioned")
+            .await
+            .unwrap();
+
+        let plan = table.scan(&None, 1024, &[col("year").eq(lit("2021"))], None)?;
+        assert_eq!(plan.output_partitioning().partition_count(), 3);
+
+        let mut ctx = ExecutionContext::new();
+        ctx.register_table("demo", Arc::new(table))?;
+
+        let batches = ctx
+            .sql("SELECT year, month, day FROM demo WHERE year = '2021' ORDER BY month, day")?
+            .collect()
+            .await?;
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(
+            batch.column(0).as_ref(),
+            Arc::new(StringArray::from(vec!["2021", "2021", "2021"])).as_ref(),
+        );
+        assert_eq!(
+            batch.column(1).as_ref(),
+            Arc::new(StringArray::from(vec!["12", "12", "4"])).as_ref(),
+        );
+        assert_eq!(
+            batch.column(2).as_ref(),
+            Arc::new(StringArray::from(vec!["20", "4", "5"])).as_ref(),
+        );
+
+        // only partition columns are selected
+        let batches = ctx
+            .sql("SELECT day FROM demo WHERE month = '2' ORDER BY day")?
+            .collect()
+            .await?;
+        assert_eq!(
+            batches[0].column(0).as_ref(),
+            Arc::new(StringArray::from(vec!["3", "5"])).as_ref(),
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,158 @@
+extern crate deltalake;
+
+#[allow(dead_code)]
+mod fs_common;
+
+use deltalake::action;
+use deltalake::{
+    DeltaTable, DeltaTableError, DeltaTransactionError, PartitionFilter, PartitionValue,
+};
+use fs_common::{create_populated_table, num_records};
+use serde_json::Value;
+
+async fn last_commit_info(table: &DeltaTable) -> action::CommitInfo {
+    table.history(Some(1)).await.unwrap().remove(0).1
+}
+
+fn metric(commit_info: &action::CommitInfo, name: &str) -> Value {
+    commit_info.operation_metrics.as_ref().unwrap()[name].clone()
+}
+
+#[tokio::test]
+async fn delete_partitions_removes_matching_files() {
+    let tmp_dir = tempdir::TempDir::new("delete_partitions").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_populated_table(table_path).await;
+    assert_eq!(table.get_files().len(), 3);
+
+    let filters = vec![PartitionFilter {
+        key: "modified",
+        value: PartitionValue::Equal("2021-02-01"),
+    }];
+    let version = table.delete_partitions(&filters).await.unwrap();
+
+    assert_eq!(version, 3);
+    assert_eq!(table.get_files().len(), 1);
+    assert_eq!(num_records(&table), 1);
+
+    let commit_info = last_commit_info(&table).await;
+    assert_eq!(commit_info.operation.as_deref(), Some("DELETE"));
+    assert_eq!(
+        commit_info.operation_parameters.as_ref().unwrap()["predicate"],
+        "modified = '2021-02-01'"
+    );
+    assert_eq!(metric(&commit_info, "numRemovedFiles"), "2");
+    assert_eq!(metric(&commit_info, "numDeletedRows"), "4");
+    assert_eq!(metric(&commit_info, "numAddedFiles"), "0");
+
+    let actions = table.get_commit_actions(version).await.unwrap();
+    assert!(actions.iter().all(|a| !matches!(a, action::Action::add(_))));
+}
+
+#[tokio::test]
+async fn delete_partitions_without_matching_files() {
+    let tmp_dir = tempdir::TempDir::new("delete_partitions_no_match").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_populated_table(table_path).await;
+
+    let filters = vec![PartitionFilter {
+        key: "modified",
+        value: PartitionValue::Equal("2021-03-01"),
+    }];
+    let version = table.delete_partitions(&filters).await.unwrap();
+
+    // nothing is committed
+    assert_eq!(version, 2);
+    assert_eq!(table.version, 2);
+    assert_eq!(table.get_files().len(), 3);
+}
+
+#[tokio::test]
+async fn delete_partitions_fails_with_non_partition_column() {
+    let tmp_dir = tempdir::TempDir::new("delete_partitions_invalid").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_populated_table(table_path).await;
+
+    let filters = vec![PartitionFilter {
+        key: "id",
+        value: PartitionValue::Equal("A"),
+    }];
+    let result = table.delete_partitions(&filters).await;
+
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::InvalidPartitionFilter { .. }
+        })
+    ));
+    assert_eq!(table.version, 2);
+}
+
+#[cfg(feature = "datafusion-ext")]
+mod datafusion {
+    use super::*;
+    use datafusion::logical_plan::{col, lit};
+
+    #[tokio::test]
+    async fn delete_rows_rewrites_files() {
+        let tmp_dir = tempdir::TempDir::new("delete_rows").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+
+        let version = table.delete(col("value").gt(lit(2))).await.unwrap();
+
+        // C, D are deleted and E is kept since its value is null
+        assert_eq!(version, 3);
+        assert_eq!(num_records(&table), 3);
+
+        let commit_info = last_commit_info(&table).await;
+        assert_eq!(commit_info.operation.as_deref(), Some("DELETE"));
+        assert_eq!(
+            commit_info.operation_parameters.as_ref().unwrap()["predicate"],
+            "value > 2"
+        );
+        assert_eq!(metric(&commit_info, "numRemovedFiles"), "2");
+        assert_eq!(metric(&commit_info, "numAddedFiles"), "1");
+        assert_eq!(metric(&commit_info, "numDeletedRows"), "2");
+        assert_eq!(metric(&commit_info, "numCopiedRows"), "1");
+
+        // the file containing only C is removed without a copy
+        let files = table.get_files();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|f| f.starts_with("modified=2021-02-01/")));
+    }
+
+    #[tokio::test]
+    async fn delete_by_partition_predicate_only_removes_files() {
+        let tmp_dir = tempdir::TempDir::new("delete_partition_expr").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+
+        let version = table
+            .delete(col("modified").eq(lit("2021-02-02")))
+            .await
+            .unwrap();
+
+        assert_eq!(version, 3);
+        assert_eq!(table.get_files().len(), 2);
+        assert_eq!(num_records(&table), 4);
+
+        let commit_info = last_commit_info(&table).await;
+        assert_eq!(metric(&commit_info, "numRemovedFiles"), "1");
+        assert_eq!(metric(&commit_info, "numAddedFiles"), "0");
+        assert_eq!(metric(&commit_info, "numDeletedRows"), "1");
+    }
+
+    #[tokio::test]
+    async fn delete_without_matching_rows() {
+        let tmp_dir = tempdir::TempDir::new("delete_no_match").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+
+        let version = table.delete(col("id").eq(lit("Z"))).await.unwrap();
+
+        assert_eq!(version, 2);
+        assert_eq!(table.get_files().len(), 3);
+        assert_eq!(num_records(&table), 5);
+    }
+}
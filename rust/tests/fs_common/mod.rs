@@ -1,5 +1,17 @@
+use arrow::array::{Int32Array, StringArray};
+use arrow::datatypes::Schema as ArrowSchema;
+use arrow::record_batch::RecordBatch;
+use deltalake::action::{self, Action};
+use deltalake::writer::RecordBatchWriter;
+use deltalake::{
+    DeltaDataTypeInt, DeltaTable, DeltaTableMetaData, DeltaTransactionOptions, Schema,
+    SchemaDataType, SchemaField,
+};
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 
 pub fn cleanup_dir_except<P: AsRef<Path>>(path: P, ignore_files: Vec<String>) {
     for p in fs::read_dir(path).unwrap() {
@@ -13,3 +25,167 @@ pub fn cleanup_dir_except<P: AsRef<Path>>(path: P, ignore_files: Vec<String>) {
         }
     }
 }
+
+/// The schema of the test tables with the nullable columns `id: string`, `value: integer` and
+/// `modified: string`.
+pub fn table_schema() -> Schema {
+    Schema::new(vec![
+        SchemaField::new(
+            "id".to_string(),
+            SchemaDataType::primitive("string".to_string()),
+            true,
+            HashMap::new(),
+        ),
+        SchemaField::new(
+            "value".to_string(),
+            SchemaDataType::primitive("integer".to_string()),
+            true,
+            HashMap::new(),
+        ),
+        SchemaField::new(
+            "modified".to_string(),
+            SchemaDataType::primitive("string".to_string()),
+            true,
+            HashMap::new(),
+        ),
+    ])
+}
+
+/// Creates an empty table from the given metadata with the protocol
+/// (1, `min_writer_version`).
+pub async fn create_table_from_metadata(
+    path: &str,
+    metadata: DeltaTableMetaData,
+    min_writer_version: DeltaDataTypeInt,
+) -> DeltaTable {
+    let backend = deltalake::get_backend_for_uri(path).unwrap();
+    let mut table = DeltaTable::new(path, backend).unwrap();
+    let protocol = action::Protocol {
+        min_reader_version: 1,
+        min_writer_version,
+    };
+    table.create(metadata, protocol).await.unwrap();
+    table
+}
+
+/// Creates an empty table with the [`table_schema`] partitioned by the given columns.
+pub async fn create_table(path: &str, partition_columns: Vec<&str>) -> DeltaTable {
+    let metadata = DeltaTableMetaData::new(
+        None,
+        None,
+        None,
+        table_schema(),
+        partition_columns.iter().map(|c| c.to_string()).collect(),
+        HashMap::new(),
+    );
+    create_table_from_metadata(path, metadata, 2).await
+}
+
+/// Creates an empty table with a single `id: string` column and the given configuration.
+pub async fn create_id_table(path: &str, configuration: HashMap<String, String>) -> DeltaTable {
+    let schema = Schema::new(vec![SchemaField::new(
+        "id".to_string(),
+        SchemaDataType::primitive("string".to_string()),
+        true,
+        HashMap::new(),
+    )]);
+    let metadata = DeltaTableMetaData::new(None, None, None, schema, vec![], configuration);
+    create_table_from_metadata(path, metadata, 2).await
+}
+
+/// Creates a table partitioned by `modified` with the files (A, B) and (D, E) in
+/// `2021-02-01` and (C) in `2021-02-02`.
+pub async fn create_populated_table(path: &str) -> DeltaTable {
+    let mut table = create_table(path, vec!["modified"]).await;
+    let batches = vec![
+        record_batch(
+            vec!["A", "B", "C"],
+            vec![Some(1), Some(2), Some(3)],
+            vec!["2021-02-01", "2021-02-01", "2021-02-02"],
+        ),
+        record_batch(vec!["D", "E"], vec![Some(4), None], vec!["2021-02-01"; 2]),
+    ];
+    for batch in batches {
+        table = write_batch(table, &batch).await;
+    }
+    table
+}
+
+/// A batch of the [`table_schema`].
+pub fn record_batch(ids: Vec<&str>, values: Vec<Option<i32>>, modified: Vec<&str>) -> RecordBatch {
+    record_batch_with_schema(&table_schema(), ids, values, modified)
+}
+
+/// A batch of a schema with the same column types as the [`table_schema`], e.g. after renaming
+/// its columns.
+pub fn record_batch_with_schema(
+    schema: &Schema,
+    ids: Vec<&str>,
+    values: Vec<Option<i32>>,
+    modified: Vec<&str>,
+) -> RecordBatch {
+    let schema = ArrowSchema::try_from(schema).unwrap();
+    RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(StringArray::from(ids)),
+            Arc::new(Int32Array::from(values)),
+            Arc::new(StringArray::from(modified)),
+        ],
+    )
+    .unwrap()
+}
+
+/// Writes the batch to the table and returns the table reloaded at the new version.
+pub async fn write_batch(table: DeltaTable, batch: &RecordBatch) -> DeltaTable {
+    let table_uri = table.table_uri.clone();
+    let mut writer = RecordBatchWriter::try_new(table).unwrap();
+    writer.write(batch).await.unwrap();
+    writer.flush().await.unwrap();
+    deltalake::open_table(&table_uri).await.unwrap()
+}
+
+/// The number of records in the active files of the table according to their stats.
+pub fn num_records(table: &DeltaTable) -> i64 {
+    table
+        .get_active_add_actions()
+        .iter()
+        .map(|add| add.get_stats().unwrap().unwrap().num_records)
+        .sum()
+}
+
+/// Commits the actions in a new transaction with the given options.
+pub async fn commit_actions(
+    table: &mut DeltaTable,
+    actions: Vec<Action>,
+    options: Option<DeltaTransactionOptions>,
+) {
+    let mut tx = table.create_transaction(options);
+    tx.add_actions(actions);
+    tx.commit(None).await.unwrap();
+}
+
+/// An add action of a file without stats or partition values.
+pub fn add(path: &str, data_change: bool) -> Action {
+    Action::add(action::Add {
+        path: path.to_string(),
+        size: 396,
+        partition_values: HashMap::new(),
+        partition_values_parsed: None,
+        modification_time: 1564524294000,
+        data_change,
+        stats: None,
+        stats_parsed: None,
+        tags: None,
+    })
+}
+
+/// A remove action of a file.
+pub fn remove(path: &str, data_change: bool) -> Action {
+    Action::remove(action::Remove {
+        path: path.to_string(),
+        deletion_timestamp: 1564524298000,
+        data_change,
+        ..Default::default()
+    })
+}
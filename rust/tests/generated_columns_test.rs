@@ -0,0 +1,281 @@
+extern crate deltalake;
+
+#[allow(dead_code)]
+mod fs_common;
+
+#[cfg(feature = "datafusion-ext")]
+mod datafusion {
+    use super::fs_common::create_table_from_metadata;
+    use arrow::array::{Array, Date32Array, Int32Array, StringArray, TimestampNanosecondArray};
+    use arrow::datatypes::{DataType, Field, Schema as ArrowSchema, TimeUnit};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::datasource::TableProvider;
+    use datafusion::execution::context::ExecutionContext;
+    use datafusion::logical_plan::{col, lit};
+    use datafusion::scalar::ScalarValue;
+    use deltalake::generated_columns::{GeneratedColumn, GENERATION_EXPRESSION};
+    use deltalake::writer::{BufferedJsonWriter, RecordBatchWriter, WriterPartition};
+    use deltalake::{
+        DeltaTable, DeltaTableError, DeltaTableMetaData, Schema, SchemaDataType, SchemaField,
+    };
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    const NANOS_PER_DAY: i64 = 24 * 60 * 60 * 1_000_000_000;
+    /// 2021-02-01T00:00:00Z
+    const FEB_01: i64 = 18659 * NANOS_PER_DAY;
+
+    fn table_schema() -> Schema {
+        let mut generated = HashMap::new();
+        generated.insert(
+            GENERATION_EXPRESSION.to_string(),
+            json!("CAST(event_ts AS DATE)"),
+        );
+        Schema::new(vec![
+            SchemaField::new(
+                "id".to_string(),
+                SchemaDataType::primitive("string".to_string()),
+                true,
+                HashMap::new(),
+            ),
+            SchemaField::new(
+                "event_ts".to_string(),
+                SchemaDataType::primitive("timestamp".to_string()),
+                true,
+                HashMap::new(),
+            ),
+            SchemaField::new(
+                "event_date".to_string(),
+                SchemaDataType::primitive("date".to_string()),
+                true,
+                generated,
+            ),
+        ])
+    }
+
+    async fn create_table(path: &str) -> DeltaTable {
+        let metadata = DeltaTableMetaData::new(
+            None,
+            None,
+            None,
+            table_schema(),
+            vec!["event_date".to_string()],
+            HashMap::new(),
+        );
+        create_table_from_metadata(path, metadata, 4).await
+    }
+
+    /// Creates a table with the column `doubled` generated by `value * 2`.
+    async fn create_doubled_table(path: &str) -> DeltaTable {
+        let mut generated = HashMap::new();
+        generated.insert(GENERATION_EXPRESSION.to_string(), json!("value * 2"));
+        let schema = Schema::new(vec![
+            SchemaField::new(
+                "id".to_string(),
+                SchemaDataType::primitive("string".to_string()),
+                true,
+                HashMap::new(),
+            ),
+            SchemaField::new(
+                "value".to_string(),
+                SchemaDataType::primitive("integer".to_string()),
+                true,
+                HashMap::new(),
+            ),
+            SchemaField::new(
+                "doubled".to_string(),
+                SchemaDataType::primitive("integer".to_string()),
+                true,
+                generated,
+            ),
+        ]);
+        let metadata = DeltaTableMetaData::new(None, None, None, schema, vec![], HashMap::new());
+        create_table_from_metadata(path, metadata, 4).await
+    }
+
+    async fn doubled_values(table_path: &str) -> Vec<Option<i32>> {
+        let table = deltalake::open_table(table_path).await.unwrap();
+        let mut ctx = ExecutionContext::new();
+        ctx.register_table("demo", Arc::new(table)).unwrap();
+        let batches = ctx
+            .sql("SELECT doubled FROM demo ORDER BY id")
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let mut values = vec![];
+        for batch in batches {
+            let column = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            for i in 0..column.len() {
+                values.push(if column.is_valid(i) {
+                    Some(column.value(i))
+                } else {
+                    None
+                });
+            }
+        }
+        values
+    }
+
+    /// A batch without the generated `event_date` column.
+    fn source_batch(ids: Vec<&str>, timestamps: Vec<i64>) -> RecordBatch {
+        let schema = ArrowSchema::new(vec![
+            Field::new("id", DataType::Utf8, true),
+            Field::new(
+                "event_ts",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                true,
+            ),
+        ]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(StringArray::from(ids)),
+                Arc::new(TimestampNanosecondArray::from_vec(timestamps, None)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn write_computes_generated_columns() {
+        let tmp_dir = tempdir::TempDir::new("generated_columns").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let table = create_table(table_path).await;
+        assert_eq!(
+            table.get_schema().unwrap().get_generated_columns().unwrap(),
+            vec![GeneratedColumn {
+                name: "event_date".to_string(),
+                expression: "CAST(event_ts AS DATE)".to_string(),
+            }]
+        );
+
+        let batch = source_batch(
+            vec!["A", "B", "C"],
+            vec![
+                FEB_01 + 1_000,
+                FEB_01 + NANOS_PER_DAY - 1,
+                FEB_01 + NANOS_PER_DAY,
+            ],
+        );
+        let mut writer = RecordBatchWriter::try_new(table).unwrap();
+        writer.write(&batch).await.unwrap();
+        writer.flush().await.unwrap();
+
+        let table = deltalake::open_table(table_path).await.unwrap();
+        let mut partitions: Vec<&str> = table
+            .get_active_add_actions()
+            .iter()
+            .map(|add| add.partition_values["event_date"].as_str())
+            .collect();
+        partitions.sort_unstable();
+        assert_eq!(partitions, vec!["2021-02-01", "2021-02-02"]);
+
+        // filters on the source column prune the files by the generated partition values
+        let filter = col("event_ts").gt_eq(lit(ScalarValue::TimestampNanosecond(Some(
+            FEB_01 + NANOS_PER_DAY,
+        ))));
+        let plan = table.scan(&None, 1024, &[filter], None).unwrap();
+        assert_eq!(plan.output_partitioning().partition_count(), 1);
+        let filter = col("event_ts").lt(lit(ScalarValue::TimestampNanosecond(Some(FEB_01))));
+        let plan = table.scan(&None, 1024, &[filter], None).unwrap();
+        assert_eq!(plan.output_partitioning().partition_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn write_fails_on_wrong_generated_values() {
+        let tmp_dir = tempdir::TempDir::new("generated_columns").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let table = create_table(table_path).await;
+
+        let source = source_batch(vec!["A", "B"], vec![FEB_01, FEB_01 + NANOS_PER_DAY]);
+        let mut fields = source.schema().fields().clone();
+        fields.push(Field::new("event_date", DataType::Date32, true));
+        let mut columns = source.columns().to_vec();
+        columns.push(Arc::new(Date32Array::from(vec![18659, 18659])));
+        let batch = RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns).unwrap();
+
+        let mut writer = RecordBatchWriter::try_new(table).unwrap();
+        match writer.write(&batch).await {
+            Err(DeltaTableError::ConstraintViolation { msg }) => {
+                assert!(msg.contains("Generated column `event_date`"));
+                assert!(msg.contains("1 rows"));
+            }
+            other => panic!("Expected a constraint violation, got {:?}", other),
+        }
+        writer.flush().await.unwrap();
+        let table = deltalake::open_table(table_path).await.unwrap();
+        assert!(table.get_files().is_empty());
+    }
+
+    #[tokio::test]
+    async fn json_writer_computes_generated_values_per_row() {
+        let tmp_dir = tempdir::TempDir::new("generated_columns").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let table = create_doubled_table(table_path).await;
+
+        let mut writer = BufferedJsonWriter::try_new(table).unwrap();
+        for value in vec![
+            json!({"id": "A", "value": 1}),
+            json!({"id": "B", "value": 2, "doubled": 4}),
+            json!({"id": "C", "value": 3, "doubled": null}),
+        ] {
+            writer.write(value, WriterPartition::NoPartitions).unwrap();
+        }
+        writer.flush().await.unwrap();
+        assert_eq!(
+            doubled_values(table_path).await,
+            vec![Some(2), Some(4), Some(6)]
+        );
+
+        // supplied values are still checked
+        let table = deltalake::open_table(table_path).await.unwrap();
+        let mut writer = BufferedJsonWriter::try_new(table).unwrap();
+        writer
+            .write(
+                json!({"id": "D", "value": 4, "doubled": 9}),
+                WriterPartition::NoPartitions,
+            )
+            .unwrap();
+        writer
+            .write(
+                json!({"id": "E", "value": 5}),
+                WriterPartition::NoPartitions,
+            )
+            .unwrap();
+        assert!(writer.flush().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn update_computes_generated_values_again() {
+        let tmp_dir = tempdir::TempDir::new("generated_columns").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let table = create_table(table_path).await;
+        let batch = source_batch(vec!["A"], vec![FEB_01]);
+        let mut writer = RecordBatchWriter::try_new(table).unwrap();
+        writer.write(&batch).await.unwrap();
+        writer.flush().await.unwrap();
+
+        let mut table = deltalake::open_table(table_path).await.unwrap();
+        let mut assignments = HashMap::new();
+        assignments.insert(
+            "event_ts".to_string(),
+            lit(ScalarValue::TimestampNanosecond(Some(
+                FEB_01 + NANOS_PER_DAY,
+            ))),
+        );
+        table.update_rows(None, assignments).await.unwrap();
+
+        let partitions: Vec<&str> = table
+            .get_active_add_actions()
+            .iter()
+            .map(|add| add.partition_values["event_date"].as_str())
+            .collect();
+        assert_eq!(partitions, vec!["2021-02-02"]);
+    }
+}
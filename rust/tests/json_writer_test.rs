@@ -0,0 +1,110 @@
+extern crate deltalake;
+
+#[allow(dead_code)]
+mod fs_common;
+
+use deltalake::writer::{BufferedJsonWriter, WriterPartition};
+use deltalake::{
+    DeltaTableError, DeltaTableMetaData, DeltaTransactionError, Schema, SchemaDataType,
+    SchemaField, SchemaTypeStruct,
+};
+use serde_json::json;
+use std::collections::HashMap;
+
+fn field(name: &str, data_type: SchemaDataType) -> SchemaField {
+    SchemaField::new(name.to_string(), data_type, true, HashMap::new())
+}
+
+fn primitive(name: &str) -> SchemaDataType {
+    SchemaDataType::primitive(name.to_string())
+}
+
+/// Creates a table with the columns `id: string`, `value: integer` and
+/// `address: struct<city: string>`.
+async fn create_table(path: &str) -> deltalake::DeltaTable {
+    let schema = Schema::new(vec![
+        field("id", primitive("string")),
+        field("value", primitive("integer")),
+        field(
+            "address",
+            SchemaDataType::r#struct(SchemaTypeStruct::new(vec![field(
+                "city",
+                primitive("string"),
+            )])),
+        ),
+    ]);
+    let metadata = DeltaTableMetaData::new(None, None, None, schema, vec![], HashMap::new());
+    fs_common::create_table_from_metadata(path, metadata, 2).await
+}
+
+#[tokio::test]
+async fn write_json_with_merge_schema() {
+    let tmp_dir = tempdir::TempDir::new("json_merge_schema").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path).await;
+
+    let mut writer = BufferedJsonWriter::try_new(table)
+        .unwrap()
+        .with_merge_schema(true);
+    writer
+        .write(
+            json!({"id": "A", "value": 1, "address": {"city": "Oslo", "zip": "0150"}, "tags": ["a"]}),
+            WriterPartition::NoPartitions,
+        )
+        .unwrap();
+    writer
+        .write(
+            json!({"id": "B", "value": 2}),
+            WriterPartition::NoPartitions,
+        )
+        .unwrap();
+    writer.flush().await.unwrap();
+
+    // the nested field and the new column are added, `value` keeps its type
+    let table = deltalake::open_table(table_path).await.unwrap();
+    let fields = table.get_schema().unwrap().get_fields();
+    assert_eq!(fields.len(), 4);
+    assert_eq!(fields[1].get_type(), &primitive("integer"));
+    match fields[2].get_type() {
+        SchemaDataType::r#struct(address) => {
+            let nested: Vec<&str> = address
+                .get_fields()
+                .iter()
+                .map(|field| field.get_name())
+                .collect();
+            assert_eq!(nested, vec!["city", "zip"]);
+            assert!(address.get_fields()[1].is_nullable());
+        }
+        other => panic!("Expected a struct, got {:?}", other),
+    }
+    assert_eq!(fields[3].get_name(), "tags");
+    assert!(matches!(fields[3].get_type(), SchemaDataType::array(_)));
+    assert_eq!(table.get_files().len(), 1);
+}
+
+#[tokio::test]
+async fn write_json_with_merge_schema_does_not_widen_columns() {
+    let tmp_dir = tempdir::TempDir::new("json_merge_schema_widening").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path).await;
+
+    let mut writer = BufferedJsonWriter::try_new(table)
+        .unwrap()
+        .with_merge_schema(true);
+    writer
+        .write(
+            json!({"id": "A", "value": 1u64 << 40, "extra": true}),
+            WriterPartition::NoPartitions,
+        )
+        .unwrap();
+
+    match writer.flush().await {
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::SchemaMismatch { msg },
+        }) => assert!(msg.contains("`value`")),
+        other => panic!("Expected a schema mismatch, got {:?}", other),
+    }
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert_eq!(table.version, 0);
+    assert_eq!(table.get_schema().unwrap().get_fields().len(), 3);
+}
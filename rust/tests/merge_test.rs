@@ -0,0 +1,186 @@
+#[allow(dead_code)]
+mod fs_common;
+
+#[cfg(feature = "datafusion-ext")]
+mod datafusion {
+    use super::fs_common::create_populated_table;
+    use arrow::array::{Array, Int32Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema as ArrowSchema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::execution::context::ExecutionContext;
+    use datafusion::logical_plan::{col, lit};
+    use datafusion::physical_plan::memory::MemoryStream;
+    use datafusion::physical_plan::SendableRecordBatchStream;
+    use deltalake::merge::MergeBuilder;
+    use deltalake::{DeltaTable, DeltaTableError, DeltaTransactionError};
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    /// Returns a source stream of changes with the columns `id`, `value` and `op`.
+    fn changes(
+        ids: Vec<&str>,
+        values: Vec<Option<i32>>,
+        ops: Vec<&str>,
+    ) -> SendableRecordBatchStream {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            Field::new("id", DataType::Utf8, true),
+            Field::new("value", DataType::Int32, true),
+            Field::new("op", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(ids)),
+                Arc::new(Int32Array::from(values)),
+                Arc::new(StringArray::from(ops)),
+            ],
+        )
+        .unwrap();
+        Box::pin(MemoryStream::try_new(vec![batch], schema, None).unwrap())
+    }
+
+    fn merge_into(table: &mut DeltaTable, source: SendableRecordBatchStream) -> MergeBuilder {
+        let mut assignments = HashMap::new();
+        assignments.insert("value".to_string(), col("source.value"));
+        let mut values = assignments.clone();
+        values.insert("id".to_string(), col("source.id"));
+        values.insert("modified".to_string(), lit("2021-02-03"));
+
+        MergeBuilder::new(table, source)
+            .on("id", "id")
+            .when_matched_delete(Some(col("source.op").eq(lit("delete"))))
+            .when_matched_update(None, assignments)
+            .when_not_matched_insert(None, values)
+    }
+
+    /// Returns the (id, value) pairs of every row in the table, sorted by id.
+    async fn rows(table: &DeltaTable) -> Vec<(String, Option<i32>)> {
+        let mut ctx = ExecutionContext::new();
+        let table = deltalake::open_table(&table.table_uri).await.unwrap();
+        ctx.register_table("target", Arc::new(table)).unwrap();
+        let batches = ctx
+            .sql("SELECT id, value FROM target ORDER BY id")
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let mut rows = vec![];
+        for batch in batches {
+            let ids = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            let values = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            for i in 0..batch.num_rows() {
+                let value = if values.is_null(i) {
+                    None
+                } else {
+                    Some(values.value(i))
+                };
+                rows.push((ids.value(i).to_string(), value));
+            }
+        }
+        rows
+    }
+
+    #[tokio::test]
+    async fn merge_updates_deletes_and_inserts() {
+        let tmp_dir = tempdir::TempDir::new("merge").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+
+        let source = changes(
+            vec!["B", "D", "F"],
+            vec![Some(20), None, Some(6)],
+            vec!["update", "delete", "insert"],
+        );
+        let version = merge_into(&mut table, source).execute().await.unwrap();
+
+        assert_eq!(version, 3);
+        assert_eq!(
+            rows(&table).await,
+            vec![
+                ("A".to_string(), Some(1)),
+                ("B".to_string(), Some(20)),
+                ("C".to_string(), Some(3)),
+                ("E".to_string(), None),
+                ("F".to_string(), Some(6)),
+            ]
+        );
+
+        let commit_info = table.history(Some(1)).await.unwrap().remove(0).1;
+        assert_eq!(commit_info.operation.as_deref(), Some("MERGE"));
+        assert_eq!(
+            commit_info.operation_parameters.as_ref().unwrap()["predicate"],
+            "id = source.id"
+        );
+        let matched_predicates: Value = serde_json::from_str(
+            commit_info.operation_parameters.as_ref().unwrap()["matchedPredicates"]
+                .as_str()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            matched_predicates,
+            serde_json::json!([
+                {"actionType": "delete", "predicate": "source.op = 'delete'"},
+                {"actionType": "update"},
+            ])
+        );
+        let metrics: HashMap<String, Value> = commit_info.operation_metrics.unwrap();
+        assert_eq!(metrics["numSourceRows"], "3");
+        assert_eq!(metrics["numTargetRowsUpdated"], "1");
+        assert_eq!(metrics["numTargetRowsDeleted"], "1");
+        assert_eq!(metrics["numTargetRowsInserted"], "1");
+        // A and E are copied to the rewritten partition, the file containing C is not touched
+        assert_eq!(metrics["numTargetRowsCopied"], "2");
+        assert_eq!(metrics["numTargetFilesRemoved"], "2");
+        assert_eq!(metrics["numTargetFilesAdded"], "2");
+    }
+
+    #[tokio::test]
+    async fn merge_without_matches_only_inserts() {
+        let tmp_dir = tempdir::TempDir::new("merge_insert").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+        let files_before = table.get_files().len();
+
+        let source = changes(vec!["X"], vec![Some(24)], vec!["insert"]);
+        merge_into(&mut table, source).execute().await.unwrap();
+
+        assert_eq!(table.get_files().len(), files_before + 1);
+        assert_eq!(
+            rows(&table).await.last(),
+            Some(&("X".to_string(), Some(24)))
+        );
+    }
+
+    #[tokio::test]
+    async fn merge_fails_when_multiple_source_rows_match() {
+        let tmp_dir = tempdir::TempDir::new("merge_duplicates").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+
+        let source = changes(
+            vec!["A", "A"],
+            vec![Some(10), Some(11)],
+            vec!["update", "update"],
+        );
+        let result = merge_into(&mut table, source).execute().await;
+
+        assert!(matches!(
+            result,
+            Err(DeltaTransactionError::DeltaTable {
+                source: DeltaTableError::Generic(_)
+            })
+        ));
+        assert_eq!(table.version, 2);
+    }
+}
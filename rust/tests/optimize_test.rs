@@ -0,0 +1,149 @@
+extern crate deltalake;
+
+#[allow(dead_code)]
+mod fs_common;
+
+use deltalake::action;
+use deltalake::{DeltaTableError, DeltaTransactionError, PartitionFilter, PartitionValue};
+use fs_common::{create_populated_table, num_records};
+
+#[tokio::test]
+async fn optimize_compacts_small_files() {
+    let tmp_dir = tempdir::TempDir::new("optimize").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_populated_table(table_path).await;
+    let removed_bytes: i64 = table
+        .get_active_add_actions()
+        .iter()
+        .filter(|add| add.partition_values["modified"] == "2021-02-01")
+        .map(|add| add.size)
+        .sum();
+
+    let metrics = table.optimize(&[], None).await.unwrap();
+
+    assert_eq!(metrics.num_files_removed, 2);
+    assert_eq!(metrics.num_files_added, 1);
+    assert_eq!(metrics.num_bytes_removed, removed_bytes);
+    assert_eq!(metrics.num_partitions_optimized, 1);
+    assert_eq!(metrics.total_considered_files, 3);
+    assert_eq!(metrics.total_files_skipped, 1);
+
+    assert_eq!(table.version, 3);
+    assert_eq!(table.get_files().len(), 2);
+    assert_eq!(num_records(&table), 5);
+
+    let actions = table.get_commit_actions(3).await.unwrap();
+    for action in actions.iter() {
+        match action {
+            action::Action::add(add) => assert!(!add.data_change),
+            action::Action::remove(remove) => assert!(!remove.data_change),
+            _ => {}
+        }
+    }
+
+    let commit_info = table.history(Some(1)).await.unwrap().remove(0).1;
+    assert_eq!(commit_info.operation.as_deref(), Some("OPTIMIZE"));
+    let operation_metrics = commit_info.operation_metrics.unwrap();
+    assert_eq!(operation_metrics["numRemovedFiles"], "2");
+    assert_eq!(operation_metrics["numAddedFiles"], "1");
+    assert_eq!(
+        operation_metrics["numRemovedBytes"],
+        removed_bytes.to_string()
+    );
+}
+
+#[tokio::test]
+async fn optimize_only_considers_matching_partitions() {
+    let tmp_dir = tempdir::TempDir::new("optimize_partition").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_populated_table(table_path).await;
+
+    let filters = vec![PartitionFilter {
+        key: "modified",
+        value: PartitionValue::Equal("2021-02-02"),
+    }];
+    let metrics = table.optimize(&filters, None).await.unwrap();
+
+    // a single file is left as it is and nothing is committed
+    assert_eq!(metrics.total_considered_files, 1);
+    assert_eq!(metrics.total_files_skipped, 1);
+    assert_eq!(metrics.num_files_removed, 0);
+    assert_eq!(table.version, 2);
+    assert_eq!(table.get_files().len(), 3);
+}
+
+#[tokio::test]
+async fn optimize_skips_files_reaching_target_size() {
+    let tmp_dir = tempdir::TempDir::new("optimize_target_size").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_populated_table(table_path).await;
+
+    let metrics = table.optimize(&[], Some(1)).await.unwrap();
+
+    assert_eq!(metrics.total_files_skipped, 3);
+    assert_eq!(table.version, 2);
+}
+
+#[tokio::test]
+async fn optimize_fails_with_non_partition_column() {
+    let tmp_dir = tempdir::TempDir::new("optimize_invalid").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_populated_table(table_path).await;
+
+    let filters = vec![PartitionFilter {
+        key: "id",
+        value: PartitionValue::Equal("A"),
+    }];
+    let result = table.optimize(&filters, None).await;
+
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::InvalidPartitionFilter { .. }
+        })
+    ));
+}
+
+#[tokio::test]
+async fn optimize_zorder_rewrites_all_files() {
+    let tmp_dir = tempdir::TempDir::new("optimize_zorder").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_populated_table(table_path).await;
+
+    let metrics = table
+        .optimize_zorder(&[], &["id", "value"], None)
+        .await
+        .unwrap();
+
+    // every partition is rewritten, including the one holding a single file
+    assert_eq!(metrics.num_files_removed, 3);
+    assert_eq!(metrics.num_files_added, 2);
+    assert_eq!(metrics.num_partitions_optimized, 2);
+    assert_eq!(metrics.total_files_skipped, 0);
+    assert_eq!(table.get_files().len(), 2);
+    assert_eq!(num_records(&table), 5);
+
+    let commit_info = table.history(Some(1)).await.unwrap().remove(0).1;
+    assert_eq!(commit_info.operation.as_deref(), Some("OPTIMIZE"));
+    assert_eq!(
+        commit_info.operation_parameters.unwrap()["zOrderBy"],
+        "[\"id\",\"value\"]"
+    );
+}
+
+#[tokio::test]
+async fn optimize_zorder_fails_with_partition_column() {
+    let tmp_dir = tempdir::TempDir::new("optimize_zorder_invalid").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let mut table = create_populated_table(table_path).await;
+
+    let result = table.optimize_zorder(&[], &["id", "modified"], None).await;
+
+    assert!(matches!(
+        result,
+        Err(DeltaTransactionError::DeltaTable {
+            source: DeltaTableError::InvalidZOrder { .. }
+        })
+    ));
+    assert_eq!(table.version, 2);
+}
@@ -97,3 +97,18 @@ fn test_match_filters() {
     assert_eq!(valid_filter_month.match_partitions(&partitions), true);
     assert_eq!(invalid_filter.match_partitions(&partitions), false);
 }
+
+#[test]
+fn test_display_filters() {
+    let equal = deltalake::PartitionFilter {
+        key: "year",
+        value: deltalake::PartitionValue::Equal("2021"),
+    };
+    assert_eq!(equal.to_string(), "year = '2021'");
+
+    let not_in = deltalake::PartitionFilter {
+        key: "month",
+        value: deltalake::PartitionValue::NotIn(vec!["01", "02"]),
+    };
+    assert_eq!(not_in.to_string(), "month NOT IN ('01', '02')");
+}
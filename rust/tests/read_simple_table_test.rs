@@ -59,6 +59,36 @@ async fn read_simple_table() {
     }
 }
 
+#[tokio::test]
+async fn read_simple_table_history() {
+    let table = deltalake::open_table("./tests/data/simple_table")
+        .await
+        .unwrap();
+
+    let history = table.history(None).await.unwrap();
+    assert_eq!(history.len(), 5);
+    let versions: Vec<i64> = history.iter().map(|(version, _)| *version).collect();
+    assert_eq!(versions, vec![4, 3, 2, 1, 0]);
+    let operations: Vec<&str> = history
+        .iter()
+        .map(|(_, c)| c.operation.as_deref().unwrap())
+        .collect();
+    assert_eq!(
+        operations,
+        vec!["DELETE", "UPDATE", "WRITE", "MERGE", "WRITE"]
+    );
+    assert_eq!(history[0].1.timestamp, Some(1587968626537));
+    assert_eq!(history[0].1.read_version, Some(3));
+    assert_eq!(
+        history[2].1.operation_parameters.as_ref().unwrap()["mode"],
+        "Overwrite"
+    );
+
+    let history = table.history(Some(2)).await.unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1].0, 3);
+}
+
 #[tokio::test]
 async fn read_simple_table_with_version() {
     let mut table = deltalake::open_table_with_version("./tests/data/simple_table", 0)
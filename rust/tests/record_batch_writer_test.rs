@@ -0,0 +1,440 @@
+extern crate deltalake;
+
+#[allow(dead_code)]
+mod fs_common;
+
+use arrow::array::{Int32Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema};
+use arrow::record_batch::RecordBatch;
+use deltalake::action::{ColumnCountStat, ColumnValueStat, SaveMode};
+use deltalake::writer::RecordBatchWriter;
+use deltalake::{
+    DeltaTable, DeltaTableError, DeltaTableMetaData, PartitionFilter, PartitionValue, Schema,
+    SchemaDataType, SchemaField,
+};
+use fs_common::{create_table, create_table_from_metadata, record_batch, table_schema};
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn write_partitioned_record_batch() {
+    let tmp_dir = tempdir::TempDir::new("write_partitioned").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec!["modified"]).await;
+
+    let mut writer = RecordBatchWriter::try_new(table).unwrap();
+    let batch = record_batch(
+        vec!["A", "B", "C", "D"],
+        vec![Some(1), Some(42), None, Some(7)],
+        vec!["2021-02-01", "2021-02-01", "2021-02-02", "2021-02-01"],
+    );
+    writer.write(&batch).await.unwrap();
+    assert_eq!(writer.flush().await.unwrap(), 1);
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert_eq!(table.version, 1);
+
+    let mut adds = table.get_active_add_actions().clone();
+    adds.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(adds.len(), 2);
+    assert!(adds[0].path.starts_with("modified=2021-02-01/part-00000-"));
+    assert!(adds[1].path.starts_with("modified=2021-02-02/part-00000-"));
+    assert_eq!(adds[0].partition_values["modified"], "2021-02-01");
+
+    let stats = adds[0].get_stats().unwrap().unwrap();
+    assert_eq!(stats.num_records, 3);
+    assert_eq!(stats.min_values["value"], ColumnValueStat::Value(json!(1)));
+    assert_eq!(stats.max_values["value"], ColumnValueStat::Value(json!(42)));
+    assert_eq!(stats.min_values["id"], ColumnValueStat::Value(json!("A")));
+    assert_eq!(stats.max_values["id"], ColumnValueStat::Value(json!("D")));
+    assert_eq!(stats.null_count["value"], ColumnCountStat::Value(0));
+    // partition columns are not stored in the data files
+    assert!(!stats.null_count.contains_key("modified"));
+
+    let stats = adds[1].get_stats().unwrap().unwrap();
+    assert_eq!(stats.num_records, 1);
+    assert_eq!(stats.null_count["value"], ColumnCountStat::Value(1));
+    assert!(!stats.min_values.contains_key("value"));
+}
+
+#[tokio::test]
+async fn flush_without_batches_commits_nothing() {
+    let tmp_dir = tempdir::TempDir::new("flush_without_batches").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec![]).await;
+
+    let mut writer = RecordBatchWriter::try_new(table).unwrap();
+    assert_eq!(writer.flush().await.unwrap(), 0);
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert_eq!(table.version, 0);
+}
+
+#[tokio::test]
+async fn write_null_and_escaped_partition_values() {
+    let tmp_dir = tempdir::TempDir::new("write_partitioned").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec!["modified"]).await;
+
+    let schema = ArrowSchema::try_from(&table_schema()).unwrap();
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(StringArray::from(vec!["A", "B"])),
+            Arc::new(Int32Array::from(vec![Some(1), Some(2)])),
+            Arc::new(StringArray::from(vec![None, Some("2021-02-01 10:00")])),
+        ],
+    )
+    .unwrap();
+    let mut writer = RecordBatchWriter::try_new(table).unwrap();
+    writer.write(&batch).await.unwrap();
+    writer.flush().await.unwrap();
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    let mut adds = table.get_active_add_actions().clone();
+    adds.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(adds.len(), 2);
+    // the paths of the add actions are URI-encoded hive partition directories
+    assert!(adds[0]
+        .path
+        .starts_with("modified=2021-02-01%2010%253A00/part-00000-"));
+    assert_eq!(adds[0].partition_values["modified"], "2021-02-01 10:00");
+    assert!(adds[1]
+        .path
+        .starts_with("modified=__HIVE_DEFAULT_PARTITION__/part-00000-"));
+    assert_eq!(adds[1].partition_values["modified"], "");
+
+    let mut uris = table.get_file_uris();
+    uris.sort();
+    assert!(uris[0].contains("modified=2021-02-01 10%3A00/part-00000-"));
+    for uri in uris {
+        assert!(std::path::Path::new(&uri).exists());
+    }
+}
+
+#[tokio::test]
+async fn write_rolls_over_to_new_files() {
+    let tmp_dir = tempdir::TempDir::new("write_rolling").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec![]).await;
+
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_target_file_size(1);
+    for i in 0..3 {
+        let batch = record_batch(vec!["A"], vec![Some(i)], vec!["2021-02-01"]);
+        writer.write(&batch).await.unwrap();
+    }
+    assert_eq!(writer.flush().await.unwrap(), 1);
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert_eq!(table.version, 1);
+    assert_eq!(table.get_files().len(), 3);
+    for add in table.get_active_add_actions() {
+        assert_eq!(add.get_stats().unwrap().unwrap().num_records, 1);
+    }
+}
+
+#[tokio::test]
+async fn write_fails_on_schema_mismatch() {
+    let tmp_dir = tempdir::TempDir::new("write_mismatch").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec![]).await;
+
+    let mut writer = RecordBatchWriter::try_new(table).unwrap();
+    let schema = ArrowSchema::new(vec![arrow::datatypes::Field::new(
+        "id",
+        arrow::datatypes::DataType::Utf8,
+        true,
+    )]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(StringArray::from(vec!["A"]))],
+    )
+    .unwrap();
+
+    assert!(matches!(
+        writer.write(&batch).await,
+        Err(DeltaTableError::SchemaMismatch { .. })
+    ));
+}
+
+#[tokio::test]
+async fn write_with_merge_schema() {
+    let tmp_dir = tempdir::TempDir::new("write_merge_schema").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec!["modified"]).await;
+
+    // `value` is widened to long and `extra` is added
+    let schema = ArrowSchema::new(vec![
+        Field::new("id", DataType::Utf8, true),
+        Field::new("value", DataType::Int64, true),
+        Field::new("modified", DataType::Utf8, true),
+        Field::new("extra", DataType::Utf8, false),
+    ]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(StringArray::from(vec!["A", "B"])),
+            Arc::new(Int64Array::from(vec![1, 1 << 40])),
+            Arc::new(StringArray::from(vec!["2021-02-01", "2021-02-02"])),
+            Arc::new(StringArray::from(vec!["x", "y"])),
+        ],
+    )
+    .unwrap();
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_merge_schema(true);
+    writer.write(&batch).await.unwrap();
+    assert_eq!(writer.flush().await.unwrap(), 1);
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    let fields = table.get_schema().unwrap().get_fields();
+    assert_eq!(fields.len(), 4);
+    assert_eq!(
+        fields[1].get_type(),
+        &SchemaDataType::primitive("long".to_string())
+    );
+    assert_eq!(fields[3].get_name(), "extra");
+    assert!(fields[3].is_nullable());
+    assert_eq!(table.get_files().len(), 2);
+
+    // batches with the previous schema are written with nulls for the new column
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_merge_schema(true);
+    let batch = record_batch(vec!["C"], vec![Some(3)], vec!["2021-02-01"]);
+    writer.write(&batch).await.unwrap();
+    assert_eq!(writer.flush().await.unwrap(), 2);
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert_eq!(table.get_schema().unwrap().get_fields().len(), 4);
+    let add = table
+        .get_active_add_actions()
+        .iter()
+        .find(|add| {
+            add.get_stats().unwrap().unwrap().min_values.get("id")
+                == Some(&ColumnValueStat::Value(json!("C")))
+        })
+        .unwrap();
+    let stats = add.get_stats().unwrap().unwrap();
+    assert_eq!(stats.null_count["extra"], ColumnCountStat::Value(1));
+}
+
+#[tokio::test]
+async fn write_with_merge_schema_fails_on_schema_mismatch() {
+    let tmp_dir = tempdir::TempDir::new("write_merge_schema_mismatch").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec![]).await;
+
+    let schema = ArrowSchema::new(vec![
+        Field::new("id", DataType::Int32, true),
+        Field::new("value", DataType::Utf8, true),
+    ]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(Int32Array::from(vec![1])),
+            Arc::new(StringArray::from(vec!["A"])),
+        ],
+    )
+    .unwrap();
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_merge_schema(true);
+
+    match writer.write(&batch).await {
+        Err(DeltaTableError::SchemaMismatch { msg }) => {
+            assert!(msg.contains("`id`: type string cannot be merged with type integer"));
+            assert!(msg.contains("`value`: type integer cannot be merged with type string"));
+        }
+        other => panic!("Expected a schema mismatch, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn write_fails_on_nulls_in_non_nullable_columns() {
+    let tmp_dir = tempdir::TempDir::new("write_non_nullable").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let schema = Schema::new(vec![
+        SchemaField::new(
+            "id".to_string(),
+            SchemaDataType::primitive("string".to_string()),
+            false,
+            HashMap::new(),
+        ),
+        SchemaField::new(
+            "value".to_string(),
+            SchemaDataType::primitive("integer".to_string()),
+            true,
+            HashMap::new(),
+        ),
+    ]);
+    let metadata = DeltaTableMetaData::new(None, None, None, schema, vec![], HashMap::new());
+    let table = create_table_from_metadata(table_path, metadata, 2).await;
+
+    // nullability is validated on the values, not on the schema of the batch
+    let schema = ArrowSchema::new(vec![
+        Field::new("id", DataType::Utf8, true),
+        Field::new("value", DataType::Int32, true),
+    ]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(StringArray::from(vec![Some("A"), None])),
+            Arc::new(Int32Array::from(vec![Some(1), None])),
+        ],
+    )
+    .unwrap();
+    let mut writer = RecordBatchWriter::try_new(table).unwrap();
+
+    match writer.write(&batch).await {
+        Err(DeltaTableError::SchemaMismatch { msg }) => {
+            assert!(msg.contains("`id`: 1 null values in non-nullable field"));
+            assert!(!msg.contains("`value`"));
+        }
+        other => panic!("Expected a schema mismatch, got {:?}", other),
+    }
+    writer.flush().await.unwrap();
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert!(table.get_files().is_empty());
+}
+
+async fn write_batch(table: DeltaTable, mode: SaveMode, batch: &RecordBatch) -> DeltaTable {
+    let table_uri = table.table_uri.clone();
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_save_mode(mode);
+    writer.write(batch).await.unwrap();
+    writer.flush().await.unwrap();
+    deltalake::open_table(&table_uri).await.unwrap()
+}
+
+#[tokio::test]
+async fn write_with_overwrite_save_mode() {
+    let tmp_dir = tempdir::TempDir::new("write_overwrite").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec!["modified"]).await;
+
+    let batch = record_batch(
+        vec!["A", "B"],
+        vec![Some(1), Some(2)],
+        vec!["2021-02-01", "2021-02-02"],
+    );
+    let table = write_batch(table, SaveMode::Append, &batch).await;
+    let old_files = table.get_file_set().len();
+    assert_eq!(old_files, 2);
+
+    // replace a single partition
+    let batch = record_batch(vec!["C"], vec![Some(3)], vec!["2021-02-02"]);
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_save_mode(SaveMode::Overwrite)
+        .with_replace_where(&[PartitionFilter {
+            key: "modified",
+            value: PartitionValue::Equal("2021-02-02"),
+        }]);
+    writer.write(&batch).await.unwrap();
+    assert_eq!(writer.flush().await.unwrap(), 2);
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    let mut adds = table.get_active_add_actions().clone();
+    adds.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(adds.len(), 2);
+    assert_eq!(adds[0].partition_values["modified"], "2021-02-01");
+    assert_eq!(adds[1].partition_values["modified"], "2021-02-02");
+    assert_eq!(
+        adds[1].get_stats().unwrap().unwrap().min_values["id"],
+        ColumnValueStat::Value(json!("C"))
+    );
+    assert_eq!(table.get_tombstones().len(), 1);
+
+    let history = table.history(Some(1)).await.unwrap();
+    let parameters = history[0].1.operation_parameters.as_ref().unwrap();
+    assert_eq!(parameters["mode"], "Overwrite");
+    assert_eq!(parameters["predicate"], "modified = '2021-02-02'");
+
+    // rows outside of the replaced partitions are rejected
+    let batch = record_batch(vec!["D"], vec![Some(4)], vec!["2021-02-03"]);
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_save_mode(SaveMode::Overwrite)
+        .with_replace_where(&[PartitionFilter {
+            key: "modified",
+            value: PartitionValue::Equal("2021-02-02"),
+        }]);
+    writer.write(&batch).await.unwrap();
+    assert!(writer.flush().await.is_err());
+
+    // replace the whole table
+    let table = deltalake::open_table(table_path).await.unwrap();
+    let batch = record_batch(vec!["E"], vec![Some(5)], vec!["2021-02-05"]);
+    let table = write_batch(table, SaveMode::Overwrite, &batch).await;
+    assert_eq!(table.version, 3);
+    assert_eq!(table.get_files().len(), 1);
+    assert_eq!(
+        table.get_active_add_actions()[0].partition_values["modified"],
+        "2021-02-05"
+    );
+}
+
+#[tokio::test]
+async fn write_with_error_if_exists_and_ignore_save_modes() {
+    let tmp_dir = tempdir::TempDir::new("write_error_if_exists").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec![]).await;
+
+    let batch = record_batch(vec!["A"], vec![Some(1)], vec!["2021-02-01"]);
+
+    // the table exists even though it has no data yet, the save mode is checked before any file
+    // is written
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_save_mode(SaveMode::ErrorIfExists);
+    assert!(matches!(
+        writer.write(&batch).await,
+        Err(DeltaTableError::TableAlreadyExists(_))
+    ));
+
+    // the table has no data yet
+    let table = deltalake::open_table(table_path).await.unwrap();
+    let table = write_batch(table, SaveMode::Ignore, &batch).await;
+    assert_eq!(table.version, 1);
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_save_mode(SaveMode::Ignore)
+        .with_target_file_size(1);
+    writer.write(&batch).await.unwrap();
+    assert_eq!(writer.flush().await.unwrap(), 1);
+
+    let table = deltalake::open_table(table_path).await.unwrap();
+    assert_eq!(table.version, 1);
+    assert_eq!(table.get_files().len(), 1);
+    // no file was written for the ignored batch
+    let data_files = std::fs::read_dir(tmp_dir.path())
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().path().extension() == Some("parquet".as_ref()))
+        .count();
+    assert_eq!(data_files, 1);
+
+    // data appended after the save mode was checked is detected on flush, and the written
+    // files are deleted again
+    let tmp_dir = tempdir::TempDir::new("write_error_if_exists").unwrap();
+    let table_path = tmp_dir.path().to_str().unwrap();
+    let table = create_table(table_path, vec![]).await;
+    let mut writer = RecordBatchWriter::try_new(table)
+        .unwrap()
+        .with_save_mode(SaveMode::Ignore);
+    writer.write(&batch).await.unwrap();
+    let table = deltalake::open_table(table_path).await.unwrap();
+    write_batch(table, SaveMode::Append, &batch).await;
+    assert_eq!(writer.flush().await.unwrap(), 1);
+    let data_files = std::fs::read_dir(tmp_dir.path())
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().path().extension() == Some("parquet".as_ref()))
+        .count();
+    assert_eq!(data_files, 1);
+}
@@ -172,6 +172,50 @@ mod simple_commit_fs {
         assert_eq!(2, table.get_files().len());
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_commit_info_is_written() {
+        prepare_fs();
+
+        let table_path = "./tests/data/simple_commit";
+        let mut table = deltalake::open_table(table_path).await.unwrap();
+
+        let mut tx = table.create_transaction(None);
+        tx.add_actions(tx1_actions());
+        tx.set_user_metadata("nightly load".to_string());
+        let version = tx
+            .commit(Some(action::DeltaOperation::Write {
+                mode: action::SaveMode::Append,
+                partitionBy: None,
+                predicate: None,
+            }))
+            .await
+            .unwrap();
+
+        let actions = table.get_commit_actions(version).await.unwrap();
+        let commit_info = match &actions[0] {
+            action::Action::commitInfo(commit_info) => commit_info,
+            a => panic!("Expected commitInfo as first action, got {:?}", a),
+        };
+        assert_eq!(commit_info.operation.as_deref(), Some("WRITE"));
+        assert_eq!(
+            commit_info.operation_parameters.as_ref().unwrap()["mode"],
+            "Append"
+        );
+        assert_eq!(commit_info.read_version, Some(0));
+        assert_eq!(
+            commit_info.get_isolation_level(),
+            Some(action::IsolationLevel::WriteSerializable)
+        );
+        assert_eq!(commit_info.is_blind_append, Some(true));
+        let operation_metrics = commit_info.operation_metrics.as_ref().unwrap();
+        assert_eq!(operation_metrics["numFiles"], "2");
+        assert_eq!(operation_metrics["numOutputBytes"], "796");
+        assert_eq!(commit_info.user_metadata.as_deref(), Some("nightly load"));
+        assert!(commit_info.timestamp.is_some());
+        assert_eq!(3, actions.len());
+    }
+
     fn prepare_fs() {
         fs_common::cleanup_dir_except(
             "./tests/data/simple_commit/_delta_log",
@@ -0,0 +1,124 @@
+#[allow(dead_code)]
+mod fs_common;
+
+#[cfg(feature = "datafusion-ext")]
+mod datafusion {
+    use super::fs_common::create_populated_table;
+    use datafusion::logical_plan::{binary_expr, col, lit, Expr, Operator};
+    use deltalake::action::ColumnValueStat;
+    use deltalake::{DeltaTable, DeltaTableError, DeltaTransactionError};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    async fn last_metrics(table: &DeltaTable) -> HashMap<String, Value> {
+        let commit_info = table.history(Some(1)).await.unwrap().remove(0).1;
+        assert_eq!(commit_info.operation.as_deref(), Some("UPDATE"));
+        commit_info.operation_metrics.unwrap()
+    }
+
+    fn max_values(table: &DeltaTable) -> Vec<Value> {
+        let mut values: Vec<Value> = table
+            .get_active_add_actions()
+            .iter()
+            .map(
+                |add| match &add.get_stats().unwrap().unwrap().max_values["value"] {
+                    ColumnValueStat::Value(v) => v.clone(),
+                    _ => unreachable!(),
+                },
+            )
+            .collect();
+        values.sort_by_key(|v| v.as_i64());
+        values
+    }
+
+    #[tokio::test]
+    async fn update_rows_matching_predicate() {
+        let tmp_dir = tempdir::TempDir::new("update_rows").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+
+        let mut assignments = HashMap::new();
+        assignments.insert("value".to_string(), lit(40));
+        let predicate = col("id").eq(lit("D")).and(col("value").is_not_null());
+        let version = table
+            .update_rows(Some(predicate), assignments)
+            .await
+            .unwrap();
+
+        assert_eq!(version, 3);
+        assert_eq!(table.get_files().len(), 3);
+        assert_eq!(max_values(&table), vec![json!(2), json!(3), json!(40)]);
+
+        // the other files cannot contain the id according to their statistics
+        let metrics = last_metrics(&table).await;
+        assert_eq!(metrics["numRemovedFiles"], "1");
+        assert_eq!(metrics["numAddedFiles"], "1");
+        assert_eq!(metrics["numUpdatedRows"], "1");
+        assert_eq!(metrics["numCopiedRows"], "1");
+
+        let commit_info = table.history(Some(1)).await.unwrap().remove(0).1;
+        assert_eq!(
+            commit_info.operation_parameters.unwrap()["predicate"],
+            "(id = 'D') AND value IS NOT NULL"
+        );
+    }
+
+    #[tokio::test]
+    async fn update_all_rows() {
+        let tmp_dir = tempdir::TempDir::new("update_all_rows").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+
+        let mut assignments = HashMap::new();
+        assignments.insert(
+            "value".to_string(),
+            binary_expr(col("value"), Operator::Multiply, lit(10)),
+        );
+        let version = table.update_rows(None, assignments).await.unwrap();
+
+        assert_eq!(version, 3);
+        assert_eq!(max_values(&table), vec![json!(20), json!(30), json!(40)]);
+
+        let metrics = last_metrics(&table).await;
+        assert_eq!(metrics["numRemovedFiles"], "3");
+        assert_eq!(metrics["numAddedFiles"], "3");
+        assert_eq!(metrics["numUpdatedRows"], "5");
+        assert_eq!(metrics["numCopiedRows"], "0");
+    }
+
+    #[tokio::test]
+    async fn update_without_matching_rows() {
+        let tmp_dir = tempdir::TempDir::new("update_no_match").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+
+        let mut assignments = HashMap::new();
+        assignments.insert("value".to_string(), lit(0));
+        let predicate: Expr = col("value").gt(lit(100));
+        let version = table
+            .update_rows(Some(predicate), assignments)
+            .await
+            .unwrap();
+
+        assert_eq!(version, 2);
+        assert_eq!(max_values(&table), vec![json!(2), json!(3), json!(4)]);
+    }
+
+    #[tokio::test]
+    async fn update_fails_with_unknown_column() {
+        let tmp_dir = tempdir::TempDir::new("update_unknown_column").unwrap();
+        let table_path = tmp_dir.path().to_str().unwrap();
+        let mut table = create_populated_table(table_path).await;
+
+        let mut assignments = HashMap::new();
+        assignments.insert("price".to_string(), lit(0));
+        let result = table.update_rows(None, assignments).await;
+
+        assert!(matches!(
+            result,
+            Err(DeltaTransactionError::DeltaTable {
+                source: DeltaTableError::SchemaMismatch { .. }
+            })
+        ));
+    }
+}